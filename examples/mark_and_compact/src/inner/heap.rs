@@ -1,32 +1,68 @@
 use crate::inner::reference_table::PtrArena;
 use gc_api::error::{Error, ErrorKind};
 use gc_api::mark::Mark;
-use log::trace;
-use std::alloc::{GlobalAlloc, Layout, System};
+use log::{debug, trace};
+use std::alloc::{handle_alloc_error, GlobalAlloc, Layout, System};
+use std::collections::HashMap;
 use std::ptr;
 use std::ptr::NonNull;
 
+use crate::inner::immortal::ImmortalSpace;
+use crate::inner::large::LargeObjectSpace;
+use crate::inner::layout;
+use crate::inner::layout::{DropTable, Object, ObjectHeader};
+use crate::inner::sizing::SizingPolicy;
+use crate::inner::tlab::{Tlab, FILLER_RESERVE};
 use crate::inner::MarkWord;
-use crate::inner::{layout, ObjectHandle};
+use gc_api::alloc::{CollectionCounts, CollectionType, GcEvent, TaggedHandle, TaggedSlot};
+use std::time::Duration;
 
 /// Attempt to line the heap up with the page size, but we are not too worried if it is a bit off.
 const HEAP_ALIGNMENT: usize = 4096;
 
+/// The preferred size of the chunks handed out to TLABs.
+pub const TLAB_SIZE: usize = 32 * 1024;
+
+/// The result of compacting the heap.
+pub struct Compaction {
+    pub bytes_reclaimed: usize,
+    pub survivors: usize,
+}
+
 pub struct MarkCompactImpl {
     pub start: *mut u8,
     pub end: *mut u8,
     pub cursor: *mut u8,
     pub ref_table: PtrArena,
     pub global_mark_state: bool,
-    pub requested_gc: bool,
+    pub requested_gc: Option<CollectionType>,
+    pub collections: CollectionCounts,
+    pub bytes_reclaimed: u64,
+    pub pause_time: Duration,
+    /// Events raised while the heap was locked. They are passed on to observers once the lock has
+    /// been released, so observers are never run under the heap lock.
+    pub pending_events: Vec<GcEvent>,
+    /// The number of active pins for each pinned object, keyed by reference table slot. Pinned
+    /// objects are left in place during compaction.
+    pub pins: HashMap<*mut TaggedSlot, usize>,
+    pub drop_glue: DropTable,
+    pub large: LargeObjectSpace,
+    pub immortal: ImmortalSpace,
+    /// The number of bytes held back from regular allocations so they can be released by an OOM
+    /// handler.
+    pub emergency_reserve: usize,
+    pub sizing: SizingPolicy,
+    /// The number of additional bytes requested by allocators since the last collection. The heap
+    /// is grown by at least this much during the next collection.
+    pub requested_growth: usize,
 }
 
 impl MarkCompactImpl {
-    pub fn with_capacity(len: usize) -> Self {
-        let layout = Layout::from_size_align(len, HEAP_ALIGNMENT).unwrap();
-        trace!("Allocating heap: {:?}", layout);
-        let start = unsafe { System.alloc(layout) };
-        trace!("Allocated heap to: {:p}", start);
+    pub fn new(sizing: SizingPolicy) -> Self {
+        let len = sizing.min_capacity;
+        let start = allocate_space(len).unwrap_or_else(|| {
+            handle_alloc_error(Layout::from_size_align(len, HEAP_ALIGNMENT).unwrap())
+        });
 
         MarkCompactImpl {
             start,
@@ -34,39 +70,251 @@ impl MarkCompactImpl {
             cursor: start,
             ref_table: PtrArena::new(),
             global_mark_state: false,
-            requested_gc: false,
+            requested_gc: None,
+            collections: CollectionCounts::default(),
+            bytes_reclaimed: 0,
+            pause_time: Duration::ZERO,
+            pending_events: Vec::new(),
+            pins: HashMap::new(),
+            drop_glue: DropTable::new(),
+            large: LargeObjectSpace::new(),
+            immortal: ImmortalSpace::new(),
+            emergency_reserve: 0,
+            sizing,
+            requested_growth: 0,
         }
     }
 
-    pub unsafe fn alloc(&mut self, layout: Layout) -> Result<ObjectHandle, Error> {
+    /// Allocate an object directly within the shared portion of the heap. This is used for objects
+    /// which are too large to be allocated within a TLAB.
+    pub unsafe fn alloc(&mut self, layout: Layout) -> Result<TaggedHandle, Error> {
         if layout.align() > layout::FIXED_ALIGN {
             return Err(Error::from(ErrorKind::UnsupportedAlignment));
         }
 
-        let (mark_ptr, new_obj) = layout::next_obj(self.cursor);
+        let (header_ptr, new_obj) = layout::next_obj(self.cursor);
         let new_cursor = new_obj.add(layout.size());
 
-        if new_cursor > self.end {
+        if new_cursor as usize - self.cursor as usize > self.free_space() {
             return Err(Error::from(ErrorKind::OutOfMemory));
         }
 
         self.cursor = new_cursor;
 
-        // Write object mark
+        // Write object
+        let ref_table_slot = self.ref_table.claim_slot();
+        ref_table_slot.as_ref().set(new_obj);
+
+        // Write object header
         ptr::write(
-            mark_ptr,
-            MarkWord::new(layout.size(), self.global_mark_state),
+            header_ptr,
+            ObjectHeader {
+                slot: ref_table_slot.as_ptr(),
+                mark: MarkWord::new(layout.size(), self.global_mark_state),
+            },
         );
 
-        // Write object
+        Ok(TaggedHandle::new(ref_table_slot))
+    }
+
+    /// Allocate an object in the large object space. Large objects count towards the capacity of
+    /// the heap, but are never moved.
+    pub unsafe fn alloc_large(&mut self, layout: Layout) -> Result<TaggedHandle, Error> {
+        if layout.align() > layout::FIXED_ALIGN {
+            return Err(Error::from(ErrorKind::UnsupportedAlignment));
+        }
+
+        if LargeObjectSpace::required_len(layout.size()) > self.free_space() {
+            return Err(Error::from(ErrorKind::OutOfMemory));
+        }
+
+        let (header_ptr, new_obj) = self.large.alloc(layout.size())?;
+        Ok(self.init_object(header_ptr, new_obj, layout.size()))
+    }
+
+    /// Allocate an object in the immortal space. Immortal objects are never moved or collected.
+    pub unsafe fn alloc_immortal(&mut self, layout: Layout) -> Result<TaggedHandle, Error> {
+        if layout.align() > layout::FIXED_ALIGN {
+            return Err(Error::from(ErrorKind::UnsupportedAlignment));
+        }
+
+        if ImmortalSpace::required_len(layout.size()) > self.free_space() {
+            return Err(Error::from(ErrorKind::OutOfMemory));
+        }
+
+        let (header_ptr, new_obj) = self.immortal.alloc(layout.size())?;
+        Ok(self.init_object(header_ptr, new_obj, layout.size()))
+    }
+
+    /// Claim a reference table slot for a newly allocated object and write its header.
+    unsafe fn init_object(
+        &mut self,
+        header_ptr: *mut ObjectHeader,
+        obj: *mut Object,
+        size: usize,
+    ) -> TaggedHandle {
         let ref_table_slot = self.ref_table.claim_slot();
-        *ref_table_slot = new_obj;
+        ref_table_slot.as_ref().set(obj);
+
+        ptr::write(
+            header_ptr,
+            ObjectHeader {
+                slot: ref_table_slot.as_ptr(),
+                mark: MarkWord::new(size, self.global_mark_state),
+            },
+        );
+
+        TaggedHandle::new(ref_table_slot)
+    }
+
+    /// Claim a chunk of the heap for a TLAB which is able to hold at least `min_len` bytes of
+    /// objects and headers.
+    pub fn claim_chunk(&mut self, min_len: usize) -> Option<Tlab> {
+        let min_len = min_len + FILLER_RESERVE;
+        let len = TLAB_SIZE.max(min_len).min(self.free_space());
+
+        if len < min_len {
+            return None;
+        }
+
+        let start = self.cursor;
+        self.cursor = (start as usize + len) as *mut u8;
+        Some(Tlab::new(start, self.cursor, self.global_mark_state))
+    }
 
-        // TODO: Write this in a more maintainable way
-        Ok(NonNull::new_unchecked(ref_table_slot as *mut _))
+    /// Prevent the object in the given slot from being moved until a matching call to
+    /// [`MarkCompactImpl::unpin`].
+    pub fn pin(&mut self, slot: NonNull<TaggedSlot>) {
+        *self.pins.entry(slot.as_ptr()).or_insert(0) += 1;
     }
 
-    pub unsafe fn perform_compact(&mut self) -> usize {
+    pub fn unpin(&mut self, slot: NonNull<TaggedSlot>) {
+        if let Some(count) = self.pins.get_mut(&slot.as_ptr()) {
+            *count -= 1;
+
+            if *count == 0 {
+                self.pins.remove(&slot.as_ptr());
+            }
+        }
+    }
+
+    /// Claim a batch of free slots from the reference table.
+    pub unsafe fn claim_slots(&mut self, count: usize, slots: &mut Vec<NonNull<TaggedSlot>>) {
+        slots.extend((0..count).map(|_| self.ref_table.claim_slot()));
+    }
+
+    pub fn bytes_used(&self) -> usize {
+        self.cursor as usize - self.start as usize
+            + self.large.bytes_used()
+            + self.immortal.bytes_used()
+    }
+
+    /// The number of bytes which can still be allocated before the heap is full.
+    pub fn free_space(&self) -> usize {
+        let remaining = self.end as usize - self.cursor as usize;
+        remaining.saturating_sub(
+            self.large.bytes_used() + self.immortal.bytes_used() + self.emergency_reserve,
+        )
+    }
+
+    /// Replace the emergency reserve. Fails if there is not enough free space to hold it.
+    pub fn set_emergency_reserve(&mut self, bytes: usize) -> Result<(), Error> {
+        if bytes > self.free_space() + self.emergency_reserve {
+            return Err(Error::from(ErrorKind::OutOfMemory));
+        }
+
+        self.emergency_reserve = bytes;
+        Ok(())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.end as usize - self.start as usize
+    }
+
+    /// Resize the heap according to its sizing policy and any growth requested since the last
+    /// collection. Returns `true` if the capacity of the heap changed.
+    ///
+    /// # Safety
+    /// The heap must have just been compacted and no TLABs may have been claimed since.
+    pub unsafe fn resize_after_collection(&mut self) -> bool {
+        let additional = std::mem::take(&mut self.requested_growth);
+        if self.sizing.is_fixed() {
+            return false;
+        }
+
+        let live_bytes = self.bytes_used() + self.emergency_reserve;
+        let target = self
+            .sizing
+            .target_capacity(self.capacity(), live_bytes, additional);
+
+        // Round up to the alignment of the heap, but never past the maximum capacity
+        let target = match target.checked_next_multiple_of(HEAP_ALIGNMENT) {
+            Some(aligned) if aligned <= self.sizing.max_capacity => aligned,
+            _ => target,
+        };
+
+        if target == self.capacity() || target < live_bytes {
+            return false;
+        }
+
+        self.resize(target)
+    }
+
+    /// Move the compacted portion of the heap into a new allocation of the given size. Objects are
+    /// only ever referenced through the reference table, so moving them only requires updating their
+    /// slots. Pinned objects can not be moved, so the heap is left as is while any are pinned.
+    ///
+    /// # Safety
+    /// All TLABs must have been retired and no objects may be referenced outside of the reference
+    /// table.
+    unsafe fn resize(&mut self, new_capacity: usize) -> bool {
+        if !self.pins.is_empty() {
+            debug!("Unable to resize heap while objects are pinned");
+            return false;
+        }
+
+        let old_capacity = self.capacity();
+        let len = self.cursor as usize - self.start as usize;
+        let start = match allocate_space(new_capacity) {
+            Some(start) => start,
+            None => return false,
+        };
+        ptr::copy_nonoverlapping(self.start, start, len);
+
+        let mut cursor = start;
+        let end = start.add(len);
+        while cursor < end {
+            let (header, obj_ptr) = layout::next_obj(cursor);
+
+            // Filler objects do not have a slot
+            if let Some(slot) = (*header).slot.as_ref() {
+                slot.set(obj_ptr);
+            }
+
+            cursor = obj_ptr.add((*header).mark.object_len());
+        }
+
+        dealloc_space(self.start, old_capacity);
+        self.start = start;
+        self.cursor = end;
+        self.end = start.add(new_capacity);
+
+        debug!(
+            "Resized heap from {} to {} bytes",
+            old_capacity, new_capacity
+        );
+        self.pending_events.push(GcEvent::HeapResized {
+            old_capacity,
+            new_capacity,
+        });
+        true
+    }
+
+    /// Compact all marked objects to the start of the heap and free everything else.
+    ///
+    /// # Safety
+    /// All TLABs must have been retired and marking must be complete.
+    pub unsafe fn perform_compact(&mut self) -> Compaction {
         trace!(
             "Compacting heap [start: {:p}, cursor: {:p}, end: {:p}]",
             self.start,
@@ -75,21 +323,58 @@ impl MarkCompactImpl {
         );
         let mut compressed = self.start;
         let mut cursor = self.start;
+        let mut survivors = 0;
 
         while cursor < self.cursor {
-            let (mark_word, obj_ptr) = layout::next_obj(cursor);
-            let len = (*mark_word).object_len();
+            let (header, obj_ptr) = layout::next_obj(cursor);
+            let len = (*header).mark.object_len();
+            let slot = (*header).slot;
+
+            let (dst_header, dst_obj) = layout::next_obj(compressed);
 
-            let (dst_mark, dst_obj) = layout::next_obj(compressed);
+            let is_live = (*header).mark.load_mark_state() == self.global_mark_state;
+            let is_pinned = !self.pins.is_empty() && self.pins.contains_key(&slot);
 
-            if (*mark_word).load_mark_state() == self.global_mark_state {
-                ptr::copy(obj_ptr, dst_obj, (*mark_word).object_len());
-                ptr::copy(mark_word, dst_mark, 1);
+            if is_live && is_pinned {
+                // Pinned objects stay where they are, so cover the gap before them with a filler
+                // object. Dead objects always leave at least a header worth of space, so the gap is
+                // either empty or large enough to hold the filler header.
+                if dst_header != header {
+                    ptr::write(
+                        dst_header,
+                        ObjectHeader {
+                            slot: ptr::null_mut(),
+                            // Fillers are never marked, so the next collection will reclaim this
+                            mark: MarkWord::new(
+                                header as usize - dst_obj as usize,
+                                self.global_mark_state,
+                            ),
+                        },
+                    );
+                }
+
+                compressed = (obj_ptr as usize + len) as *mut u8;
+                survivors += 1;
+            } else if is_live {
+                // The header must be moved first since the object may overlap the old header
+                ptr::copy(header, dst_header, 1);
+                ptr::copy(obj_ptr, dst_obj, len);
 
                 compressed = (dst_obj as usize + len) as *mut u8;
-                self.ref_table.update_slot_by_value(obj_ptr, dst_obj);
+                (*slot).set(dst_obj);
+                survivors += 1;
             } else {
-                self.ref_table.free_slot_by_value(obj_ptr);
+                (*header).finalize(obj_ptr, &mut self.drop_glue);
+
+                // Freeing the slot bumps its generation which also clears any weak handles. Filler
+                // objects do not have a slot.
+                if let Some(slot) = NonNull::new(slot) {
+                    self.ref_table.free_slot(slot);
+                }
+
+                if is_pinned {
+                    self.pins.remove(&slot);
+                }
             }
 
             cursor = (obj_ptr as usize + len) as *mut u8;
@@ -98,23 +383,64 @@ impl MarkCompactImpl {
         assert_eq!(cursor, self.cursor);
         self.cursor = compressed;
 
-        cursor as usize - compressed as usize
+        Compaction {
+            bytes_reclaimed: cursor as usize - compressed as usize,
+            survivors,
+        }
+    }
+
+    /// Free all large objects which were not marked.
+    ///
+    /// # Safety
+    /// Marking must be complete.
+    pub unsafe fn sweep_large_objects(&mut self) -> Compaction {
+        let MarkCompactImpl {
+            ref_table,
+            pins,
+            drop_glue,
+            large,
+            global_mark_state,
+            ..
+        } = self;
+
+        large.sweep(*global_mark_state, |header, obj| {
+            (*header).finalize(obj, drop_glue);
+
+            // Objects which were abandoned during allocation do not have a slot
+            if let Some(slot) = NonNull::new((*header).slot) {
+                ref_table.free_slot(slot);
+                pins.remove(&slot.as_ptr());
+            }
+        })
     }
 }
 
+// The heap is only accessed through a lock or by allocators which own a portion of it.
+unsafe impl Send for MarkCompactImpl {}
+
 impl Drop for MarkCompactImpl {
     fn drop(&mut self) {
-        let len = self.end as usize - self.start as usize;
-        let layout = Layout::from_size_align(len, HEAP_ALIGNMENT).unwrap();
-
-        trace!(
-            "Dropping heap [Start: {:p}, Layout: {:?}]",
-            self.start,
-            layout
-        );
-
-        unsafe {
-            System.dealloc(self.start, layout);
+        // Finalize all remaining objects before releasing the heap. Every object which needs to be
+        // dropped has an entry in the drop glue table, including large and immortal objects.
+        for (slot, glue) in self.drop_glue.drain() {
+            unsafe { glue.run(NonNull::new_unchecked((*slot).get())) };
         }
+
+        unsafe { dealloc_space(self.start, self.capacity()) };
     }
 }
+
+fn allocate_space(len: usize) -> Option<*mut u8> {
+    let layout = Layout::from_size_align(len, HEAP_ALIGNMENT).unwrap();
+    trace!("Allocating heap: {:?}", layout);
+    let start = unsafe { System.alloc(layout) };
+    trace!("Allocated heap to: {:p}", start);
+
+    (!start.is_null()).then_some(start)
+}
+
+unsafe fn dealloc_space(start: *mut u8, len: usize) {
+    let layout = Layout::from_size_align(len, HEAP_ALIGNMENT).unwrap();
+    trace!("Dropping heap [Start: {:p}, Layout: {:?}]", start, layout);
+    System.dealloc(start, layout);
+}
@@ -0,0 +1,109 @@
+//! The immortal object space.
+//!
+//! Objects allocated with [`AllocFlags::IMMORTAL`](gc_api::alloc::AllocFlags::IMMORTAL) are bump
+//! allocated within chunks claimed from the system allocator. The space is never compacted or
+//! swept, so immortal objects keep the same address until the heap is dropped.
+
+use crate::inner::layout::{self, Object, ObjectHeader};
+use gc_api::error::{Error, ErrorKind};
+use log::trace;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::mem::size_of;
+
+/// The preferred size of each chunk in the immortal space.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+struct Chunk {
+    start: *mut u8,
+    /// The end of the last object allocated within this chunk.
+    cursor: *mut u8,
+    layout: Layout,
+}
+
+impl Chunk {
+    fn end(&self) -> *mut u8 {
+        (self.start as usize + self.layout.size()) as *mut u8
+    }
+}
+
+#[derive(Default)]
+pub struct ImmortalSpace {
+    chunks: Vec<Chunk>,
+    bytes_used: usize,
+    objects: usize,
+}
+
+impl ImmortalSpace {
+    pub fn new() -> Self {
+        ImmortalSpace::default()
+    }
+
+    /// The number of bytes used by immortal objects, including their headers.
+    pub fn bytes_used(&self) -> usize {
+        self.bytes_used
+    }
+
+    /// The number of objects which have been allocated in this space.
+    pub fn objects(&self) -> usize {
+        self.objects
+    }
+
+    /// The number of bytes which would be used by an object of the given size in the worst case.
+    pub fn required_len(size: usize) -> usize {
+        size_of::<ObjectHeader>() + layout::FIXED_ALIGN - 1 + size
+    }
+
+    /// Allocate space for an object of the given size. The caller is responsible for writing the
+    /// header of the new object.
+    pub fn alloc(&mut self, size: usize) -> Result<(*mut ObjectHeader, *mut Object), Error> {
+        let claimed = match self.chunks.last_mut().and_then(|chunk| bump(chunk, size)) {
+            Some(claimed) => claimed,
+            None => bump(self.new_chunk(size)?, size).unwrap(),
+        };
+
+        self.bytes_used += size_of::<ObjectHeader>() + size;
+        self.objects += 1;
+        Ok(claimed)
+    }
+
+    fn new_chunk(&mut self, size: usize) -> Result<&mut Chunk, Error> {
+        let len = CHUNK_SIZE.max(Self::required_len(size));
+        let layout = Layout::from_size_align(len, layout::FIXED_ALIGN)
+            .map_err(|_| Error::from(ErrorKind::AllocationTooLarge))?;
+
+        let start = unsafe { System.alloc(layout) };
+        if start.is_null() {
+            return Err(Error::from(ErrorKind::OutOfMemory));
+        }
+
+        trace!("Allocated immortal chunk at {:p} ({:?})", start, layout);
+        self.chunks.push(Chunk {
+            start,
+            cursor: start,
+            layout,
+        });
+
+        Ok(self.chunks.last_mut().unwrap())
+    }
+}
+
+fn bump(chunk: &mut Chunk, size: usize) -> Option<(*mut ObjectHeader, *mut Object)> {
+    let (header, obj) = layout::next_obj(chunk.cursor);
+    let new_cursor = obj as usize + size;
+
+    if new_cursor > chunk.end() as usize {
+        return None;
+    }
+
+    chunk.cursor = new_cursor as *mut u8;
+    Some((header, obj))
+}
+
+/// Immortal objects are finalized by the heap before it is dropped.
+impl Drop for ImmortalSpace {
+    fn drop(&mut self) {
+        for chunk in self.chunks.drain(..) {
+            unsafe { System.dealloc(chunk.start, chunk.layout) };
+        }
+    }
+}
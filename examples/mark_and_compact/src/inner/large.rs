@@ -0,0 +1,120 @@
+//! The large object space.
+//!
+//! Objects of at least [`LARGE_OBJECT_THRESHOLD`] bytes are allocated individually from the system
+//! allocator instead of within the compacted portion of the heap. Large objects are never moved, so
+//! collections only need to sweep them once marking is complete and return dead objects to the
+//! system allocator. They use the same [`ObjectHeader`] as every other object, so tracing does not
+//! need to distinguish between them.
+
+use crate::inner::heap::Compaction;
+use crate::inner::layout::{self, Object, ObjectHeader};
+use gc_api::error::{Error, ErrorKind};
+use gc_api::mark::Mark;
+use log::trace;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::mem::size_of;
+
+/// Objects at least this large are placed in the large object space.
+pub const LARGE_OBJECT_THRESHOLD: usize = 64 * 1024;
+
+struct LargeObject {
+    header: *mut ObjectHeader,
+    layout: Layout,
+}
+
+impl LargeObject {
+    fn obj(&self) -> *mut Object {
+        (self.header as usize + size_of::<ObjectHeader>()) as *mut Object
+    }
+
+    /// Return the memory of the object to the system allocator. The object must already have been
+    /// finalized.
+    unsafe fn free(self) {
+        System.dealloc(self.header as *mut u8, self.layout);
+    }
+}
+
+#[derive(Default)]
+pub struct LargeObjectSpace {
+    objects: Vec<LargeObject>,
+    bytes_used: usize,
+}
+
+impl LargeObjectSpace {
+    pub fn new() -> Self {
+        LargeObjectSpace::default()
+    }
+
+    /// The number of bytes used by large objects, including their headers.
+    pub fn bytes_used(&self) -> usize {
+        self.bytes_used
+    }
+
+    /// The number of bytes which would be used by an object of the given size.
+    pub fn required_len(size: usize) -> usize {
+        size_of::<ObjectHeader>() + size
+    }
+
+    /// Allocate space for an object of the given size. The caller is responsible for writing the
+    /// header of the new object.
+    pub fn alloc(&mut self, size: usize) -> Result<(*mut ObjectHeader, *mut Object), Error> {
+        let layout = Layout::from_size_align(Self::required_len(size), layout::FIXED_ALIGN)
+            .map_err(|_| Error::from(ErrorKind::AllocationTooLarge))?;
+
+        let header = unsafe { System.alloc(layout) } as *mut ObjectHeader;
+        if header.is_null() {
+            return Err(Error::from(ErrorKind::OutOfMemory));
+        }
+
+        trace!("Allocated large object at {:p} ({:?})", header, layout);
+        let object = LargeObject { header, layout };
+        let obj = object.obj();
+
+        self.bytes_used += layout.size();
+        self.objects.push(object);
+        Ok((header, obj))
+    }
+
+    /// Free all large objects which were not marked. `finalize` is called with the header of each
+    /// dead object before it is freed, so it can run its drop glue and release its slot.
+    ///
+    /// # Safety
+    /// Marking must be complete and every large object must have an initialized header.
+    pub unsafe fn sweep<F>(&mut self, mark_state: bool, mut finalize: F) -> Compaction
+    where
+        F: FnMut(*mut ObjectHeader, *mut Object),
+    {
+        let mut bytes_reclaimed = 0;
+        let mut index = 0;
+
+        while index < self.objects.len() {
+            let header = self.objects[index].header;
+
+            if (*header).mark.load_mark_state() == mark_state {
+                index += 1;
+                continue;
+            }
+
+            let object = self.objects.swap_remove(index);
+            bytes_reclaimed += object.layout.size();
+            finalize(header, object.obj());
+            object.free();
+        }
+
+        self.bytes_used -= bytes_reclaimed;
+
+        Compaction {
+            bytes_reclaimed,
+            survivors: self.objects.len(),
+        }
+    }
+}
+
+/// The objects left in the space are finalized by the heap before it is dropped.
+impl Drop for LargeObjectSpace {
+    fn drop(&mut self) {
+        for object in self.objects.drain(..) {
+            unsafe { object.free() }
+        }
+    }
+}
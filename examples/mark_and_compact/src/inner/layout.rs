@@ -1,4 +1,8 @@
 use crate::inner::mark::MarkWord;
+use gc_api::alloc::{DropGlue, IdentityHandle, RawMeta, TaggedHandle, TaggedSlot};
+use gc_api::error::Error;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::mem::size_of;
 use std::ptr::NonNull;
 
@@ -9,14 +13,113 @@ pub const FIXED_ALIGN: usize = 8;
 /// Type alias for readability. Really it is just a placeholder value for some bytes.
 pub type Object = u8;
 
-pub type ObjectHandle = NonNull<NonNull<Object>>;
+/// The raw handle used for all objects. Handles to unsized types also carry the pointer metadata
+/// required to rebuild a reference to the object.
+#[derive(Debug, Copy, Clone)]
+pub struct ObjectHandle {
+    tagged: TaggedHandle,
+    meta: RawMeta,
+}
+
+impl ObjectHandle {
+    pub fn new(tagged: TaggedHandle, meta: RawMeta) -> Self {
+        ObjectHandle { tagged, meta }
+    }
+
+    pub fn tagged(&self) -> &TaggedHandle {
+        &self.tagged
+    }
+
+    pub fn meta(&self) -> RawMeta {
+        self.meta
+    }
+
+    /// Create a handle to the same object, but with different pointer metadata.
+    pub fn with_meta(self, meta: RawMeta) -> Self {
+        ObjectHandle { meta, ..self }
+    }
+
+    /// # Safety
+    /// The heap this handle was allocated in must still be alive.
+    pub unsafe fn is_alive(&self) -> bool {
+        self.tagged.is_alive()
+    }
+
+    /// Get a pointer to the object or a [`gc_api::error::ErrorKind::UseAfterFree`] error if it has
+    /// been collected.
+    ///
+    /// # Safety
+    /// The heap this handle was allocated in must still be alive and `T` must be the type this
+    /// handle was created for.
+    pub unsafe fn get<T: ?Sized>(&self) -> Result<NonNull<T>, Error> {
+        Ok(self.meta.with_addr(self.tagged.get()?))
+    }
+
+    /// # Safety
+    /// The same requirements as [`ObjectHandle::get`] apply and the object must not have been
+    /// collected.
+    pub unsafe fn get_unchecked<T: ?Sized>(&self) -> NonNull<T> {
+        self.meta.with_addr(self.tagged.get_unchecked())
+    }
+}
+
+/// Handles are compared by the object they refer to, so the pointer metadata is not considered.
+impl PartialEq for ObjectHandle {
+    fn eq(&self, other: &Self) -> bool {
+        self.tagged == other.tagged
+    }
+}
+
+impl Eq for ObjectHandle {}
+
+impl Hash for ObjectHandle {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.tagged.hash(state)
+    }
+}
+
+/// The hash is derived from the reference table slot, so it is not affected by compaction.
+impl IdentityHandle for ObjectHandle {}
+
+/// Drop glue for the objects which need to be dropped, keyed by reference table slot. Most objects
+/// do not need to be dropped, so the glue is kept out of their headers.
+pub type DropTable = HashMap<*mut TaggedSlot, DropGlue>;
+
+/// The header placed directly before every object in the heap.
+#[repr(C)]
+pub struct ObjectHeader {
+    /// The reference table slot which points to this object. Keeping a pointer back to the slot
+    /// lets compaction update or free the slot without needing to search the reference table.
+    pub slot: *mut TaggedSlot,
+    pub mark: MarkWord,
+}
+
+impl ObjectHeader {
+    /// Run the drop glue (if any) for the object following this header.
+    ///
+    /// # Safety
+    /// The object must be dead and this must only be called once for the object.
+    pub unsafe fn finalize(&mut self, obj: *mut Object, drop_glue: &mut DropTable) {
+        if !self.mark.needs_drop() {
+            return;
+        }
+
+        if let Some(glue) = drop_glue.remove(&self.slot) {
+            glue.run(NonNull::new_unchecked(obj));
+        }
+    }
+}
 
 #[inline(always)]
-pub fn next_obj(pos: *mut Object) -> (*mut MarkWord, *mut Object) {
-    let offset = ((pos as usize + size_of::<MarkWord>()) as *mut u8).align_offset(FIXED_ALIGN);
+pub fn next_obj(pos: *mut Object) -> (*mut ObjectHeader, *mut Object) {
+    let offset = ((pos as usize + size_of::<ObjectHeader>()) as *mut u8).align_offset(FIXED_ALIGN);
 
-    let obj = (pos as usize + offset + size_of::<MarkWord>()) as *mut u8;
-    let mark_word = (obj as usize - size_of::<MarkWord>()) as *mut MarkWord;
+    let obj = (pos as usize + offset + size_of::<ObjectHeader>()) as *mut u8;
 
-    (mark_word, obj)
+    (header_of(obj), obj)
+}
+
+#[inline(always)]
+pub fn header_of(obj: *mut Object) -> *mut ObjectHeader {
+    (obj as usize - size_of::<ObjectHeader>()) as *mut ObjectHeader
 }
@@ -9,12 +9,17 @@ pub struct MarkWord {
 
 impl MarkWord {
     const MARK_BIT: usize = 1 << (usize::BITS - 1);
+    /// Set for objects which do not contain any GC handles and do not need to be traced.
+    const LEAF_BIT: usize = 1 << (usize::BITS - 2);
+    /// Set for objects with an entry in the drop glue table of the heap.
+    const DROP_BIT: usize = 1 << (usize::BITS - 3);
+    const LEN_MASK: usize = !(Self::MARK_BIT | Self::LEAF_BIT | Self::DROP_BIT);
 
     #[inline(always)]
     pub fn new(obj_len: usize, mark_state: bool) -> Self {
-        // This should be near impossible since it would require a single object cover over half of
-        // the address space.
-        debug_assert_eq!(obj_len & !Self::MARK_BIT, obj_len);
+        // This should be near impossible since it would require a single object cover over an
+        // eighth of the address space.
+        debug_assert_eq!(obj_len & Self::LEN_MASK, obj_len);
 
         let mark_value = obj_len | ((mark_state as usize) << Self::MARK_BIT.trailing_zeros());
 
@@ -24,7 +29,23 @@ impl MarkWord {
     }
 
     pub fn object_len(&self) -> usize {
-        self.mark.get() & !Self::MARK_BIT
+        self.mark.get() & Self::LEN_MASK
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.mark.get() & Self::LEAF_BIT != 0
+    }
+
+    pub fn set_leaf(&self) {
+        self.mark.set(self.mark.get() | Self::LEAF_BIT);
+    }
+
+    pub fn needs_drop(&self) -> bool {
+        self.mark.get() & Self::DROP_BIT != 0
+    }
+
+    pub fn set_needs_drop(&self) {
+        self.mark.set(self.mark.get() | Self::DROP_BIT);
     }
 }
 
@@ -35,9 +56,9 @@ impl Mark for MarkWord {
 
     fn store_mark_state(&self, state: bool) {
         if state {
-            self.mark.set(self.object_len() | Self::MARK_BIT);
+            self.mark.set(self.mark.get() | Self::MARK_BIT);
         } else {
-            self.mark.set(self.object_len());
+            self.mark.set(self.mark.get() & !Self::MARK_BIT);
         }
     }
 }
@@ -1,116 +1,761 @@
-use crate::trace::MarkCompactTracer;
-use gc_api::alloc::{Accessor, AccessorMut, Alloc};
-use gc_api::error::Error;
-use gc_api::trace::Trace;
+use crate::trace::{ImmortalRoot, MarkCompactTracer};
+use gc_api::alloc::{
+    check_flags, Alloc, AllocFlags, CoerceHandle, CollectionReport, CollectionType, DropGlue,
+    EmergencyReserve, GcEvent, GcObservers, GcStats, HeapStats, LivenessCheck, ObserveGc,
+    ObserverId, OomAction, OomContext, OomHandler, RawMeta, TaggedHandle, TaggedSlot,
+};
+use gc_api::error::{Error, ErrorKind};
+use gc_api::safepoint::Safepoint;
+use gc_api::trace::roots::UniformHandleRoots;
+use gc_api::trace::{Trace, Tracer};
 use log::{debug, trace};
 use std::alloc::Layout;
 use std::cell::RefCell;
-use std::ptr::NonNull;
+#[cfg(debug_assertions)]
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
 
 mod heap;
-mod layout;
+mod immortal;
+mod large;
+pub(crate) mod layout;
 mod mark;
 mod reference_table;
+mod region;
+mod sizing;
+mod tlab;
 
-use crate::inner::heap::MarkCompactImpl;
-pub use layout::{Object, ObjectHandle};
+use crate::inner::heap::{Compaction, MarkCompactImpl, TLAB_SIZE};
+use crate::inner::large::LARGE_OBJECT_THRESHOLD;
+use crate::inner::layout::ObjectHeader;
+use crate::inner::region::Region;
+use crate::inner::tlab::Tlab;
+pub use layout::ObjectHandle;
 pub use mark::MarkWord;
+pub use sizing::SizingPolicy;
 
-#[repr(transparent)]
-pub struct MarkCompactAlloc(MarkCompactImpl);
+/// Roots shared by every allocator for a heap.
+pub(crate) struct SharedRoots(pub UniformHandleRoots<MarkCompactAlloc, ObjectHandle>);
+
+// Roots are only accessed through a lock and are never dereferenced outside of a collection.
+unsafe impl Send for SharedRoots {}
+
+/// Objects allocated with [`AllocFlags::IMMORTAL`]. Nothing is guaranteed to refer to them, so they
+/// are traced along with the other roots during every collection.
+#[derive(Default)]
+pub(crate) struct ImmortalRoots(pub Vec<ImmortalRoot>);
+
+// Immortal objects are only traced during a collection, when every allocator has been stopped.
+unsafe impl Send for ImmortalRoots {}
+
+/// The portion of the heap which is shared between all allocators.
+pub(crate) struct SharedHeap {
+    space: Mutex<MarkCompactImpl>,
+    roots: Mutex<SharedRoots>,
+    immortal: Mutex<ImmortalRoots>,
+    safepoint: Arc<Safepoint>,
+    live_objects: AtomicUsize,
+    /// Observers are kept apart from the rest of the heap so they are never called while the heap
+    /// is locked.
+    observers: Mutex<GcObservers>,
+    /// The OOM handler used by allocators which do not have one of their own. Allocators take a
+    /// reference to the handler so the lock is not held while it runs.
+    oom_handler: Mutex<Option<Arc<Mutex<OomHandler>>>>,
+}
+
+impl SharedHeap {
+    pub fn new(sizing: SizingPolicy) -> Self {
+        SharedHeap {
+            space: Mutex::new(MarkCompactImpl::new(sizing)),
+            roots: Mutex::new(SharedRoots(Default::default())),
+            immortal: Mutex::new(ImmortalRoots::default()),
+            safepoint: Arc::new(Safepoint::new()),
+            live_objects: AtomicUsize::new(0),
+            observers: Mutex::new(GcObservers::new()),
+            oom_handler: Mutex::new(None),
+        }
+    }
+
+    fn space(&self) -> MutexGuard<'_, MarkCompactImpl> {
+        self.space.lock().unwrap()
+    }
+
+    pub fn roots(&self) -> MutexGuard<'_, SharedRoots> {
+        self.roots.lock().unwrap()
+    }
+
+    pub fn immortal(&self) -> MutexGuard<'_, ImmortalRoots> {
+        self.immortal.lock().unwrap()
+    }
+
+    pub fn safepoint(&self) -> &Arc<Safepoint> {
+        &self.safepoint
+    }
+
+    pub fn add_observer<F>(&self, observer: F) -> ObserverId
+    where
+        F: FnMut(&GcEvent) + Send + 'static,
+    {
+        self.observers().add(observer)
+    }
+
+    pub fn remove_observer(&self, id: ObserverId) -> bool {
+        self.observers().remove(id)
+    }
+
+    fn observers(&self) -> MutexGuard<'_, GcObservers> {
+        // An observer may panic, so the lock can not be left poisoned
+        self.observers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Notify observers of an event. This must not be called while the heap is locked.
+    fn emit(&self, event: &GcEvent) {
+        self.observers().emit(event);
+    }
+
+    pub fn pin(&self, slot: NonNull<TaggedSlot>) {
+        self.space().pin(slot)
+    }
+
+    pub fn unpin(&self, slot: NonNull<TaggedSlot>) {
+        self.space().unpin(slot)
+    }
+
+    pub fn set_oom_handler(&self, handler: Option<OomHandler>) -> Option<Arc<Mutex<OomHandler>>> {
+        let mut current = self
+            .oom_handler
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        std::mem::replace(
+            &mut *current,
+            handler.map(|handler| Arc::new(Mutex::new(handler))),
+        )
+    }
+
+    fn oom_handler(&self) -> Option<Arc<Mutex<OomHandler>>> {
+        self.oom_handler
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn set_emergency_reserve(&self, bytes: usize) -> Result<(), Error> {
+        self.space().set_emergency_reserve(bytes)
+    }
+
+    pub fn stats(&self) -> GcStats {
+        let space = self.space();
+
+        GcStats {
+            bytes_used: space.bytes_used(),
+            capacity: space.capacity(),
+            live_objects: self.live_objects.load(Ordering::Relaxed),
+            collections: space.collections,
+            bytes_reclaimed: space.bytes_reclaimed,
+            pause_time: space.pause_time,
+        }
+    }
+}
+
+/// The emergency reserve of a heap, as seen by an OOM handler.
+struct HeapReserve<'a>(&'a SharedHeap);
+
+impl EmergencyReserve for HeapReserve<'_> {
+    fn reserved(&self) -> usize {
+        self.0.space().emergency_reserve
+    }
+
+    fn release(&mut self, bytes: usize) -> usize {
+        let mut space = self.0.space();
+        let released = bytes.min(space.emergency_reserve);
+        space.emergency_reserve -= released;
+        released
+    }
+}
+
+/// The number of reference table slots an allocator claims at a time.
+const SLOT_BATCH: usize = 64;
+
+/// Objects larger than this are allocated directly in the shared heap instead of a TLAB.
+const MAX_TLAB_OBJECT_SIZE: usize = TLAB_SIZE / 4;
+
+/// An allocator into a [`crate::MarkCompactHeap`]. Objects are bump allocated within a TLAB, so the
+/// shared heap only needs to be locked when claiming a new TLAB or a new batch of reference table
+/// slots. Leaf objects are given a TLAB of their own so they end up grouped together.
+pub struct MarkCompactAlloc {
+    heap: Arc<SharedHeap>,
+    tlab: Tlab,
+    leaf_tlab: Tlab,
+    /// The regions of the currently active scopes, from outermost to innermost.
+    regions: Vec<Region>,
+    slots: Vec<NonNull<TaggedSlot>>,
+    /// Overrides the OOM handler of the heap for this allocator.
+    oom_handler: Option<OomHandler>,
+}
+
+// The TLAB and cached slots are owned by this allocator and are not shared with other threads.
+unsafe impl Send for MarkCompactAlloc {}
 
 impl MarkCompactAlloc {
-    pub fn with_capacity(capacity: usize) -> Self {
-        let gc_impl = MarkCompactImpl::with_capacity(capacity);
+    pub(crate) fn new(heap: Arc<SharedHeap>) -> Self {
+        MarkCompactAlloc {
+            heap,
+            tlab: Tlab::empty(),
+            leaf_tlab: Tlab::empty(),
+            regions: Vec::new(),
+            slots: Vec::new(),
+            oom_handler: None,
+        }
+    }
 
-        MarkCompactAlloc(gc_impl)
+    pub(crate) fn shared(&self) -> &Arc<SharedHeap> {
+        &self.heap
     }
 
     #[inline(always)]
-    pub fn should_perform_gc(&mut self) -> bool {
-        self.0.requested_gc
+    pub fn should_perform_gc(&self) -> bool {
+        self.heap.safepoint.is_stop_requested()
+    }
+
+    /// Cover the unused remainder of the current TLABs with filler objects. This must be done
+    /// before a collection can take place. Any active scoped regions are retired as well, so the
+    /// objects within them are left to the GC.
+    pub fn retire_tlab(&mut self) {
+        unsafe {
+            self.tlab.retire();
+            self.leaf_tlab.retire();
+        }
+
+        for region in &mut self.regions {
+            region.retire();
+        }
+    }
+
+    pub fn set_oom_handler(&mut self, handler: Option<OomHandler>) -> Option<OomHandler> {
+        std::mem::replace(&mut self.oom_handler, handler)
+    }
+
+    /// Pass a failed allocation to the OOM handler of this allocator or, if it does not have one,
+    /// the OOM handler of the heap.
+    pub fn handle_alloc_failure(&mut self, layout: Layout, error: &Error) -> OomAction {
+        let mut reserve = HeapReserve(&self.heap);
+        let mut context = OomContext::new(layout, error, &mut reserve);
+
+        if let Some(handler) = &mut self.oom_handler {
+            return handler(&mut context);
+        }
+
+        // The handler is taken out of the heap before it is called, so other threads can still
+        // replace it in the meantime. Only threads which fail at the same time wait for each other.
+        let handler = match self.heap.oom_handler() {
+            Some(handler) => handler,
+            None => return OomAction::Fail,
+        };
+
+        // If the handler runs out of memory itself, waiting on its lock would never return
+        let _running = match RunningOomHandler::enter(&handler) {
+            Some(running) => running,
+            None => return OomAction::Fail,
+        };
+
+        // A handler may panic to raise an error, so the lock can not be left poisoned
+        let mut handler = handler.lock().unwrap_or_else(PoisonError::into_inner);
+        handler(&mut context)
+    }
+
+    /// Start a new scoped region. Small objects allocated without [`AllocFlags::ESCAPE`] will be
+    /// placed in the region until the matching call to [`MarkCompactAlloc::exit_region`].
+    pub fn enter_region(&mut self) {
+        self.regions.push(Region::new());
+    }
+
+    /// Free every object allocated in the innermost scoped region.
+    ///
+    /// In debug builds, this panics if a root still points into the region. Shared roots are checked
+    /// exactly, while immortal objects and objects allocated with [`AllocFlags::ESCAPE`] while the
+    /// region was active are scanned conservatively since their types are not known. This is only a
+    /// heuristic: handles stored out of line (Ex: in a `Vec`) or in objects allocated before the
+    /// region are not found. The check is skipped if the thread is already panicking.
+    ///
+    /// # Safety
+    /// No handles to objects allocated within the region may be used afterwards.
+    pub unsafe fn exit_region(&mut self) {
+        let region = self
+            .regions
+            .pop()
+            .expect("Attempted to exit a region which was never entered");
+
+        #[cfg(debug_assertions)]
+        let mut region = region;
+
+        #[cfg(debug_assertions)]
+        if !std::thread::panicking() {
+            let slots = region.slots();
+            assert!(
+                !self.is_rooted(&region, &slots),
+                "A rooted handle points into a scoped region which is being freed"
+            );
+            assert!(
+                !self.is_escaped_into(&region, &slots),
+                "An object which escaped a scoped region holds a handle into it"
+            );
+        }
+
+        // Objects which escaped this region must not point into the enclosing regions either
+        #[cfg(debug_assertions)]
+        if let Some(parent) = self.regions.last_mut() {
+            parent.escaped.append(&mut region.escaped);
+        }
+
+        let freed = region.free(&mut self.heap.space());
+        self.heap.live_objects.fetch_sub(freed, Ordering::Relaxed);
+    }
+
+    /// Check if any of the shared roots point into the given region, or if an immortal object
+    /// appears to hold a handle to one of its `slots`.
+    #[cfg(debug_assertions)]
+    unsafe fn is_rooted(&self, region: &Region, slots: &HashMap<*mut TaggedSlot, usize>) -> bool {
+        let shared =
+            self.heap
+                .roots()
+                .0
+                .iter()
+                .any(|(_, root)| match root.raw_handle().tagged().get() {
+                    Ok(ptr) => region.contains(ptr.as_ptr()),
+                    Err(_) => false,
+                });
+
+        // Immortal objects are roots as well, but their types are not known here
+        let immortal = self
+            .heap
+            .immortal()
+            .0
+            .iter()
+            .any(|root| holds_handle_into(root.object().as_ptr(), slots));
+
+        shared || immortal
     }
 
+    /// Check if any object which escaped the given region appears to hold a handle to one of its
+    /// `slots`.
+    #[cfg(debug_assertions)]
+    unsafe fn is_escaped_into(
+        &self,
+        region: &Region,
+        slots: &HashMap<*mut TaggedSlot, usize>,
+    ) -> bool {
+        region.escaped.iter().any(|handle| match handle.get() {
+            Ok(ptr) => holds_handle_into(ptr.as_ptr(), slots),
+            // The object may have already been collected
+            Err(_) => false,
+        })
+    }
+
+    /// Retire the current TLAB and immediately perform a GC. Along with the roots held by the heap,
+    /// `roots` will also be traced.
+    ///
+    /// # Safety
+    /// No other allocators for this heap may be in use. They must either be dropped or parked at a
+    /// safepoint with their TLABs retired.
     #[cold]
     #[inline(never)]
-    pub fn perform_gc<T: Trace<Self>>(&mut self, roots: &T) -> usize {
-        debug!("Performing GC");
+    pub unsafe fn perform_gc<T: Trace<Self>>(&mut self, roots: &T) -> usize {
+        self.retire_tlab();
+        self.collect(roots)
+    }
+
+    /// Perform a GC without retiring the TLAB of this allocator.
+    ///
+    /// # Safety
+    /// Every allocator for this heap (including this one) must have retired its TLAB and no other
+    /// allocators may be in use.
+    pub(crate) unsafe fn collect<T: Trace<Self>>(&self, roots: &T) -> usize {
+        let start_time = Instant::now();
+        let mut space = self.heap.space();
+
+        space.global_mark_state = !space.global_mark_state;
+        let mark_state = space.global_mark_state;
+        let collection = space.requested_gc.take().unwrap_or(CollectionType::Full);
+        debug!("Performing GC: {:?}", collection);
+
+        // Every other allocator is stopped and marking does not touch the shared heap, so the lock
+        // can be released while observers are notified and objects are traced
+        drop(space);
+        self.heap.emit(&GcEvent::CollectionStart(collection));
+
         {
-            let MarkCompactAlloc(inner) = self;
+            let mut tracer = MarkCompactTracer::new(self, mark_state);
+            trace!("Tracing shared roots");
+            self.heap.roots().0.trace(&mut tracer);
+            self.heap.immortal().0.trace(&mut tracer);
+            roots.trace(&mut tracer);
+            tracer.process_ephemerons();
+            trace!("Found a total of {} objects", tracer.traced);
+        }
+        self.heap.emit(&GcEvent::MarkComplete);
+
+        let mut space = self.heap.space();
+        let compaction = space.perform_compact();
+        let sweep = space.sweep_large_objects();
+        debug!(
+            "Performed cleanup which cleared {} bytes of space and {} bytes of large objects",
+            compaction.bytes_reclaimed, sweep.bytes_reclaimed
+        );
+        let compaction = Compaction {
+            bytes_reclaimed: compaction.bytes_reclaimed + sweep.bytes_reclaimed,
+            survivors: compaction.survivors + sweep.survivors + space.immortal.objects(),
+        };
+        space.resize_after_collection();
+
+        self.heap
+            .live_objects
+            .store(compaction.survivors, Ordering::Relaxed);
+
+        let pause_time = start_time.elapsed();
+        space.collections.record(collection);
+        space.bytes_reclaimed += compaction.bytes_reclaimed as u64;
+        space.pause_time += pause_time;
 
-            inner.requested_gc = false;
-            inner.global_mark_state = !inner.global_mark_state;
+        let report = CollectionReport {
+            collection,
+            live_objects: compaction.survivors,
+            bytes_used: space.bytes_used(),
+            bytes_reclaimed: compaction.bytes_reclaimed,
+            pause_time,
+        };
+
+        let events = std::mem::take(&mut space.pending_events);
+        drop(space);
+
+        for event in &events {
+            self.heap.emit(event);
         }
+        self.heap.emit(&GcEvent::CollectionEnd(report));
 
-        let mut tracer = MarkCompactTracer::new(self, self.0.global_mark_state);
-        trace!("Tracing shared roots");
-        roots.trace(&mut tracer);
-        trace!("Found a total of {} objects", tracer.traced);
+        compaction.bytes_reclaimed
+    }
 
-        unsafe {
-            let bytes_cleared = self.0.perform_compact();
-            debug!(
-                "Performed cleanup which cleared {} bytes of space",
-                bytes_cleared
-            );
+    /// Request a GC at the next yield point. A pending request will not be replaced by a
+    /// suggestion.
+    pub fn gc_at_next_yield(&self, collection: CollectionType) {
+        let mut space = self.heap.space();
+
+        if space.requested_gc.is_none() || collection != CollectionType::Suggest {
+            space.requested_gc = Some(collection);
+        }
+
+        self.heap.safepoint.request_stop();
+    }
+
+    /// Ask for the heap to be grown by at least `additional` bytes at the next yield point. Returns
+    /// `false` if the heap is already at its maximum capacity.
+    pub fn request_growth(&self, additional: usize) -> bool {
+        let mut space = self.heap.space();
+        if space.capacity() >= space.sizing.max_capacity {
+            return false;
+        }
+
+        space.requested_growth = space.requested_growth.max(additional);
+        drop(space);
+
+        // Growing the heap moves every object, so it can only be done during a collection
+        self.gc_at_next_yield(CollectionType::Full);
+        true
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.heap.space().capacity()
+    }
 
-            bytes_cleared
+    unsafe fn alloc_small(
+        &mut self,
+        size: usize,
+        flags: AllocFlags,
+    ) -> Result<TaggedHandle, Error> {
+        // Pinned objects may be held past the end of a scope, so they are never placed in a region
+        let in_region = !flags.intersects(AllocFlags::ESCAPE | AllocFlags::PINNED);
+
+        let (header, obj, mark_state) = match self.regions.last_mut() {
+            Some(region) if in_region => {
+                let (header, obj) = region.bump(&self.heap, size)?;
+                (header, obj, region.mark_state())
+            }
+            _ => {
+                let tlab = match flags.contains(AllocFlags::NO_SCAN) {
+                    true => &mut self.leaf_tlab,
+                    false => &mut self.tlab,
+                };
+
+                let (header, obj) = match tlab.bump(size) {
+                    Some(claimed) => claimed,
+                    None => {
+                        refill_tlab(&self.heap, tlab, size)?;
+                        tlab.bump(size).unwrap()
+                    }
+                };
+                (header, obj, tlab.mark_state())
+            }
+        };
+
+        if self.slots.is_empty() {
+            self.heap.space().claim_slots(SLOT_BATCH, &mut self.slots);
         }
+
+        let slot = self.slots.pop().unwrap();
+        slot.as_ref().set(obj);
+
+        ptr::write(
+            header,
+            ObjectHeader {
+                slot: slot.as_ptr(),
+                mark: MarkWord::new(size, mark_state),
+            },
+        );
+
+        Ok(TaggedHandle::new(slot))
     }
+}
 
-    pub fn gc_at_next_yield(&mut self) {
-        let MarkCompactAlloc(inner) = self;
+fn refill_tlab(heap: &SharedHeap, tlab: &mut Tlab, size: usize) -> Result<(), Error> {
+    unsafe { tlab.retire() };
+    let mut space = heap.space();
 
-        inner.requested_gc = true;
+    let min_len = size + size_of::<ObjectHeader>() + layout::FIXED_ALIGN - 1;
+    match space.claim_chunk(min_len) {
+        Some(claimed) => {
+            *tlab = claimed;
+            Ok(())
+        }
+        None => Err(Error::from(ErrorKind::OutOfMemory)),
     }
 }
 
-impl<T: Sized> Alloc<T> for MarkCompactAlloc {
-    type MutTy = RefCell<T>;
-    type RawHandle = NonNull<NonNull<Object>>;
+impl Drop for MarkCompactAlloc {
+    fn drop(&mut self) {
+        self.retire_tlab();
+        let mut space = self.heap.space();
+
+        for slot in self.slots.drain(..) {
+            unsafe { space.ref_table.free_slot(slot) };
+        }
+    }
+}
+
+impl ObserveGc for MarkCompactAlloc {
+    fn add_observer<F>(&mut self, observer: F) -> ObserverId
+    where
+        F: FnMut(&GcEvent) + Send + 'static,
+    {
+        self.heap.add_observer(observer)
+    }
+
+    fn remove_observer(&mut self, id: ObserverId) -> bool {
+        self.heap.remove_observer(id)
+    }
+}
+
+/// Check if an object appears to hold a handle to any of the given `slots`, which map each slot to
+/// its current generation. The type of the object is not known, so it is scanned conservatively for
+/// a slot pointer next to the current generation of that slot.
+#[cfg(debug_assertions)]
+unsafe fn holds_handle_into(ptr: *mut u8, slots: &HashMap<*mut TaggedSlot, usize>) -> bool {
+    let len = (*layout::header_of(ptr)).mark.object_len() / size_of::<usize>();
+    let words = ptr as *const usize;
+
+    (0..len).any(|index| {
+        let word = ptr::read_volatile(words.add(index));
+        let generation = match slots.get(&(word as *mut TaggedSlot)) {
+            Some(&generation) => generation,
+            None => return false,
+        };
+
+        (index > 0 && ptr::read_volatile(words.add(index - 1)) == generation)
+            || (index + 1 < len && ptr::read_volatile(words.add(index + 1)) == generation)
+    })
+}
 
-    type Flags = Self;
+thread_local! {
+    /// The heap OOM handlers which are currently running on this thread.
+    static RUNNING_OOM_HANDLERS: RefCell<Vec<*const Mutex<OomHandler>>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+/// Marks a heap OOM handler as running on the current thread until it is dropped.
+struct RunningOomHandler(*const Mutex<OomHandler>);
+
+impl RunningOomHandler {
+    /// Returns `None` if the handler is already running further up the stack.
+    fn enter(handler: &Mutex<OomHandler>) -> Option<Self> {
+        let handler = handler as *const _;
+
+        RUNNING_OOM_HANDLERS.with_borrow_mut(|running| {
+            if running.contains(&handler) {
+                return None;
+            }
+
+            running.push(handler);
+            Some(RunningOomHandler(handler))
+        })
+    }
+}
+
+impl Drop for RunningOomHandler {
+    fn drop(&mut self) {
+        RUNNING_OOM_HANDLERS
+            .with_borrow_mut(|running| running.retain(|&handler| handler != self.0));
+    }
+}
+
+impl HeapStats for MarkCompactAlloc {
+    fn stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+}
+
+/// Build the check for [`Alloc::liveness_check`] outside of the generic impl, so it does not depend
+/// on the type of the object.
+fn liveness_check(handle: ObjectHandle) -> LivenessCheck<MarkCompactAlloc> {
+    // Only the slot address and generation are kept, so the check can move between threads with
+    // the allocator. The allocator passed to the check keeps the heap and its slots alive.
+    let slot = handle.tagged().slot().as_ptr() as usize;
+    let generation = handle.tagged().generation();
+    Box::new(move |_| unsafe { Some((*(slot as *const TaggedSlot)).generation() == generation) })
+}
+
+impl<T: ?Sized> Alloc<T> for MarkCompactAlloc {
+    type MutTy = RefCell<T>;
+    type RawHandle = ObjectHandle;
 
     unsafe fn try_alloc_layout(&mut self, layout: Layout) -> Result<Self::RawHandle, Error> {
-        let MarkCompactAlloc(inner) = self;
+        // There is no way to know the metadata of an unsized type at this point
+        if size_of::<*const T>() != size_of::<*const ()>() {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "Unsized types must be allocated along with their pointer metadata",
+            ));
+        }
+
+        <Self as Alloc<T>>::try_alloc_unsized(self, layout, RawMeta::THIN)
+    }
+
+    unsafe fn try_alloc_unsized(
+        &mut self,
+        layout: Layout,
+        meta: RawMeta,
+    ) -> Result<Self::RawHandle, Error> {
+        <Self as Alloc<T>>::try_alloc_with_flags(self, layout, meta, AllocFlags::empty())
+    }
 
-        inner.alloc(layout)
+    fn supported_flags(&self) -> AllocFlags {
+        AllocFlags::all()
+    }
+
+    unsafe fn try_alloc_with_flags(
+        &mut self,
+        layout: Layout,
+        meta: RawMeta,
+        flags: AllocFlags,
+    ) -> Result<Self::RawHandle, Error> {
+        check_flags(<Self as Alloc<T>>::supported_flags(self), flags)?;
+
+        if layout.align() > layout::FIXED_ALIGN {
+            return Err(Error::from(ErrorKind::UnsupportedAlignment));
+        }
+
+        // Immortal and large objects are never moved, so they are kept out of the compacted portion
+        // of the heap. Medium sized objects skip the TLAB so they do not waste the remainder of the buffer.
+        let is_large = flags.contains(AllocFlags::LARGE) || layout.size() >= LARGE_OBJECT_THRESHOLD;
+        let tagged = if flags.contains(AllocFlags::IMMORTAL) {
+            self.heap.space().alloc_immortal(layout)?
+        } else if is_large {
+            self.heap.space().alloc_large(layout)?
+        } else if layout.size() > MAX_TLAB_OBJECT_SIZE {
+            self.heap.space().alloc(layout)?
+        } else {
+            self.alloc_small(layout.size(), flags)?
+        };
+
+        // Remember objects which outlive the current scope so they can be checked once it ends
+        #[cfg(debug_assertions)]
+        if flags.intersects(AllocFlags::ESCAPE | AllocFlags::IMMORTAL) {
+            if let Some(region) = self.regions.last_mut() {
+                region.escaped.push(tagged);
+            }
+        }
+
+        if flags.contains(AllocFlags::ZEROED) {
+            ptr::write_bytes(tagged.get_unchecked().as_ptr(), 0, layout.size());
+        }
+
+        if flags.contains(AllocFlags::NO_SCAN) {
+            (*layout::header_of(tagged.get_unchecked().as_ptr()))
+                .mark
+                .set_leaf();
+        }
+
+        // Objects allocated as pinned hold a pin for their entire lifetime. Immortal objects are
+        // never moved, so they do not need to be pinned.
+        if flags.contains(AllocFlags::PINNED) && !flags.contains(AllocFlags::IMMORTAL) {
+            self.heap.space().pin(tagged.slot());
+        }
+
+        self.heap.live_objects.fetch_add(1, Ordering::Relaxed);
+        Ok(ObjectHandle::new(tagged, meta))
     }
 
     unsafe fn handle_ptr(&self, handle: &<Self as Alloc<T>>::RawHandle) -> NonNull<u8> {
-        handle.as_ptr().read().cast()
+        handle.tagged().get_unchecked()
     }
 
     unsafe fn handle_ref(&self, handle: &<Self as Alloc<T>>::RawHandle) -> &T {
-        <Self as Alloc<T>>::handle_ptr(self, handle).cast().as_ref()
+        handle.get_unchecked::<T>().as_ref()
     }
-}
 
-pub struct MarkCompactAccessor;
+    fn handle_is_alive(&self, handle: &<Self as Alloc<T>>::RawHandle) -> Option<bool> {
+        unsafe { Some(handle.is_alive()) }
+    }
 
-impl<T: 'static> Accessor<T, MarkCompactAlloc> for MarkCompactAccessor {
-    type Guard<'g> = &'g T;
+    fn liveness_check(
+        &self,
+        handle: &<Self as Alloc<T>>::RawHandle,
+    ) -> Option<LivenessCheck<Self>> {
+        Some(liveness_check(*handle))
+    }
 
-    unsafe fn access<'g>(
-        &'g self,
-        handle: &'g <MarkCompactAlloc as Alloc<T>>::RawHandle,
-    ) -> Result<Self::Guard<'g>, Error> {
-        Ok(&*handle.as_ptr().read().cast().as_ptr())
+    unsafe fn register_drop_glue(
+        &mut self,
+        handle: &<Self as Alloc<T>>::RawHandle,
+        glue: DropGlue,
+    ) {
+        let slot = handle.tagged().slot();
+        let header = layout::header_of(handle.tagged().get_unchecked().as_ptr());
+        (*header).mark.set_needs_drop();
+        self.heap.space().drop_glue.insert(slot.as_ptr(), glue);
     }
-}
 
-impl<T: 'static> AccessorMut<T, MarkCompactAlloc> for MarkCompactAccessor {
-    type GuardMut<'g> = std::cell::RefMut<'g, T>;
+    unsafe fn abandon_alloc(&mut self, handle: <Self as Alloc<T>>::RawHandle) {
+        // Turn the object into filler so it gets reclaimed by the next compaction
+        let header = layout::header_of(handle.tagged().get_unchecked().as_ptr());
+        (*header).slot = ptr::null_mut();
+
+        // The slot was never exposed, so it can be invalidated and reused by this allocator
+        let slot = handle.tagged().slot();
+        self.heap.space().pins.remove(&slot.as_ptr());
+        slot.as_ref().invalidate();
+        self.slots.push(slot);
+
+        self.heap.live_objects.fetch_sub(1, Ordering::Relaxed);
+    }
+}
 
-    unsafe fn access_mut<'g>(
-        &'g self,
-        handle: &'g <MarkCompactAlloc as Alloc<<MarkCompactAlloc as Alloc<T>>::MutTy>>::RawHandle,
-    ) -> Result<Self::GuardMut<'g>, Error> {
-        Ok((*handle
-            .as_ptr()
-            .read()
-            .cast::<<MarkCompactAlloc as Alloc<T>>::MutTy>()
-            .as_ptr())
-        .borrow_mut())
+unsafe impl<T, U: ?Sized> CoerceHandle<T, U> for MarkCompactAlloc {
+    unsafe fn coerce_handle(handle: ObjectHandle, meta: RawMeta) -> ObjectHandle {
+        handle.with_meta(meta)
     }
 }
@@ -1,6 +1,9 @@
 //! Honestly, it is a somewhat sloppy implementation, but I chose to just do a more c-like approach.
 
-use std::ptr::null_mut;
+use gc_api::alloc::TaggedSlot;
+use std::ptr::{null_mut, NonNull};
+
+const CHUNK_LEN: usize = 1024;
 
 /// A super simple arena which is used to act as a reference table. It functions similarly to the
 /// generational_arena crate but with the added pros/cons:
@@ -15,8 +18,11 @@ use std::ptr::null_mut;
 ///    unique, non-null, and points do a location outside of this data structure.
 ///  - Free. In this case, the pointer points to the next free position. If there are no more free
 ///    positions it remains null indicating that a new chunk must be allocated.
+///
+/// Every slot also carries a generation which is incremented each time it is freed. Handles record
+/// the generation at the time they were created so stale handles can be detected.
 pub struct PtrArena {
-    free_ptr: *mut *mut u8,
+    free_ptr: *mut TaggedSlot,
     chunks: Vec<PtrArenaChunk>,
 }
 
@@ -30,13 +36,13 @@ impl PtrArena {
         }
     }
 
-    pub fn contains_ptr(&self, ptr: *mut u8) -> bool {
+    pub fn contains_ptr(&self, ptr: *mut TaggedSlot) -> bool {
         self.chunks.iter().any(|x| x.contains_ptr(ptr))
     }
 
-    pub unsafe fn claim_slot(&mut self) -> *mut *mut u8 {
+    pub unsafe fn claim_slot(&mut self) -> NonNull<TaggedSlot> {
         let slot = self.free_ptr;
-        let next_slot = *slot as *mut *mut u8;
+        let next_slot = (*slot).get() as *mut TaggedSlot;
 
         if next_slot.is_null() {
             let new_slab = PtrArenaChunk::new_linked_block();
@@ -46,63 +52,48 @@ impl PtrArena {
             self.free_ptr = next_slot;
         }
 
-        assert!(self.contains_ptr(slot as *mut u8));
-        slot
+        debug_assert!(self.contains_ptr(slot));
+        NonNull::new_unchecked(slot)
     }
 
-    pub unsafe fn free_slot_by_value(&mut self, value: *mut u8) {
-        for chunk in &mut self.chunks {
-            for x in &mut *chunk.ptr {
-                if *x == value {
-                    *x = self.free_ptr as *mut u8;
-                    self.free_ptr = x as *mut *mut u8;
-                    return;
-                }
-            }
-        }
-
-        panic!("Failed to find slot to free")
-    }
+    /// Return a slot to the free list. This invalidates all existing handles to the slot.
+    pub unsafe fn free_slot(&mut self, slot: NonNull<TaggedSlot>) {
+        debug_assert!(self.contains_ptr(slot.as_ptr()));
 
-    pub unsafe fn update_slot_by_value(&mut self, previous: *mut u8, new: *mut u8) {
-        for chunk in &mut self.chunks {
-            for x in &mut *chunk.ptr {
-                if *x == previous {
-                    *x = new;
-                    return;
-                    // *x = self.free_ptr as *mut u8;
-                    // self.free_ptr = x as *mut *mut u8;
-                }
-            }
-        }
-        panic!("Failed to find slot to update")
+        let slot_ref = slot.as_ref();
+        slot_ref.invalidate();
+        slot_ref.set(self.free_ptr as *mut u8);
+        self.free_ptr = slot.as_ptr();
     }
 }
 
 #[repr(transparent)]
 struct PtrArenaChunk {
-    ptr: Box<[*mut u8; 1024]>,
+    ptr: Box<[TaggedSlot; CHUNK_LEN]>,
 }
 
 impl PtrArenaChunk {
-    pub fn contains_ptr(&self, ptr: *mut u8) -> bool {
-        ptr >= &self.ptr[0] as *const _ as *mut u8 && ptr <= &self.ptr[1023] as *const _ as *mut u8
+    pub fn contains_ptr(&self, ptr: *mut TaggedSlot) -> bool {
+        let range = self.ptr.as_ptr_range();
+        ptr as *const _ >= range.start && (ptr as *const _) < range.end
     }
 
-    fn start_ptr(&self) -> *mut *mut u8 {
-        &self.ptr[0] as *const _ as *mut *mut u8
+    fn start_ptr(&self) -> *mut TaggedSlot {
+        self.ptr.as_ptr() as *mut TaggedSlot
     }
 
     fn new_linked_block() -> Self {
         // Allocate chunk
-        let mut boxed_ptrs: Box<[*mut u8; 1024]> = vec![null_mut(); 1024]
+        let boxed_ptrs: Box<[TaggedSlot; CHUNK_LEN]> = (0..CHUNK_LEN)
+            .map(|_| TaggedSlot::new(null_mut()))
+            .collect::<Vec<_>>()
             .into_boxed_slice()
             .try_into()
-            .unwrap();
+            .unwrap_or_else(|_| unreachable!());
 
-        // Fill in pointers 0-1023 to point to next cell.
-        for index in 0..1023 {
-            boxed_ptrs[index] = &boxed_ptrs[index + 1] as *const _ as *mut u8;
+        // Fill in pointers 0-1022 to point to next cell.
+        for index in 0..CHUNK_LEN - 1 {
+            boxed_ptrs[index].set(&boxed_ptrs[index + 1] as *const _ as *mut u8);
         }
 
         PtrArenaChunk { ptr: boxed_ptrs }
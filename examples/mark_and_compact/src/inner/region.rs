@@ -0,0 +1,148 @@
+//! Scoped regions.
+//!
+//! While an [`Allocator::scope`](gc_api::alloc::Allocator::scope) is active, small objects are bump
+//! allocated within chunks claimed specifically for that scope. When the scope ends, every object in
+//! those chunks is finalized and has its slot freed at once. If a chunk is still at the end of the
+//! compacted portion of the heap, the heap cursor is simply reset to the start of the chunk.
+//! Otherwise, it is covered by a single filler object which is reclaimed by the next compaction.
+//!
+//! A collection can not take place while a region is partially filled, so the region is retired
+//! along with the TLABs of its allocator. Objects allocated before that point are left for the GC to
+//! collect like any other object.
+
+use crate::inner::heap::MarkCompactImpl;
+use crate::inner::layout::{self, Object, ObjectHeader};
+use crate::inner::tlab::Tlab;
+use crate::inner::{refill_tlab, MarkWord, SharedHeap};
+#[cfg(debug_assertions)]
+use gc_api::alloc::{TaggedHandle, TaggedSlot};
+use gc_api::error::Error;
+#[cfg(debug_assertions)]
+use std::collections::HashMap;
+use std::ptr::{self, NonNull};
+
+pub struct Region {
+    tlab: Tlab,
+    /// Every chunk claimed for this region, including the one backing the current TLAB.
+    chunks: Vec<(*mut u8, *mut u8)>,
+    /// Objects allocated outside of the region while it was active which are expected to outlive
+    /// it. These are checked for handles into the region before it is freed.
+    #[cfg(debug_assertions)]
+    pub escaped: Vec<TaggedHandle>,
+}
+
+impl Region {
+    pub fn new() -> Self {
+        Region {
+            tlab: Tlab::empty(),
+            chunks: Vec::new(),
+            #[cfg(debug_assertions)]
+            escaped: Vec::new(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn mark_state(&self) -> bool {
+        self.tlab.mark_state()
+    }
+
+    /// Claim space for an object of the given size, claiming a new chunk if required.
+    pub fn bump(
+        &mut self,
+        heap: &SharedHeap,
+        size: usize,
+    ) -> Result<(*mut ObjectHeader, *mut Object), Error> {
+        if let Some(claimed) = self.tlab.bump(size) {
+            return Ok(claimed);
+        }
+
+        refill_tlab(heap, &mut self.tlab, size)?;
+        self.chunks.push((self.tlab.cursor(), self.tlab.end()));
+        Ok(self.tlab.bump(size).unwrap())
+    }
+
+    /// Check if an object was allocated within this region.
+    #[cfg(debug_assertions)]
+    pub fn contains(&self, obj: *const u8) -> bool {
+        self.chunks
+            .iter()
+            .any(|&(start, end)| start as *const u8 <= obj && obj < end as *const u8)
+    }
+
+    /// Find the slot of every object in this region along with the generation of the slot. The
+    /// current TLAB is retired so the region can be walked.
+    ///
+    /// # Safety
+    /// The heap this region was claimed from must still be alive.
+    #[cfg(debug_assertions)]
+    pub unsafe fn slots(&mut self) -> HashMap<*mut TaggedSlot, usize> {
+        self.tlab.retire();
+        let mut slots = HashMap::new();
+
+        for &(start, end) in &self.chunks {
+            let mut cursor = start;
+
+            while cursor < end {
+                let (header, obj_ptr) = layout::next_obj(cursor);
+                if let Some(slot) = NonNull::new((*header).slot) {
+                    slots.insert(slot.as_ptr(), slot.as_ref().generation());
+                }
+
+                cursor = obj_ptr.add((*header).mark.object_len());
+            }
+        }
+
+        slots
+    }
+
+    /// Hand every object allocated so far over to the GC.
+    pub fn retire(&mut self) {
+        unsafe { self.tlab.retire() };
+        self.chunks.clear();
+    }
+
+    /// Finalize every object in this region and release the space it used. Returns the number of
+    /// objects which were freed.
+    ///
+    /// # Safety
+    /// No handles to objects within this region may be used after it is freed.
+    pub unsafe fn free(mut self, space: &mut MarkCompactImpl) -> usize {
+        self.tlab.retire();
+        let mut freed = 0;
+
+        // Later chunks are freed first so consecutive chunks at the end of the heap are all released
+        for &(start, end) in self.chunks.iter().rev() {
+            let mut cursor = start;
+
+            while cursor < end {
+                let (header, obj_ptr) = layout::next_obj(cursor);
+                (*header).finalize(obj_ptr, &mut space.drop_glue);
+
+                // Filler objects do not have a slot
+                if let Some(slot) = NonNull::new((*header).slot) {
+                    space.pins.remove(&slot.as_ptr());
+                    space.ref_table.free_slot(slot);
+                    freed += 1;
+                }
+
+                cursor = obj_ptr.add((*header).mark.object_len());
+            }
+
+            if space.cursor == end {
+                space.cursor = start;
+                continue;
+            }
+
+            let (header, obj) = layout::next_obj(start);
+            ptr::write(
+                header,
+                ObjectHeader {
+                    slot: ptr::null_mut(),
+                    mark: MarkWord::new(end as usize - obj as usize, space.global_mark_state),
+                },
+            );
+        }
+
+        freed
+    }
+}
@@ -0,0 +1,77 @@
+//! Deciding how large the heap should be.
+//!
+//! After every collection, the heap compares the number of bytes which survived against its
+//! capacity. If the heap is more occupied than the target live ratio allows, it is grown so the
+//! survivors make up the target ratio. The heap is only shrunk once it would be at most half as
+//! large as it currently is, so a heap hovering around the target does not resize after every
+//! collection.
+
+/// The bounds and target occupancy used to size a heap.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SizingPolicy {
+    /// The initial capacity of the heap. The heap is never shrunk below this size.
+    pub min_capacity: usize,
+    /// The heap is never grown beyond this size.
+    pub max_capacity: usize,
+    /// The fraction of the heap which should be in use directly after a collection.
+    pub target_live_ratio: f64,
+}
+
+impl SizingPolicy {
+    /// The target live ratio used unless one is given.
+    pub const DEFAULT_TARGET_LIVE_RATIO: f64 = 0.5;
+
+    /// A heap which may be resized between `min_capacity` and `max_capacity`.
+    pub fn new(min_capacity: usize, max_capacity: usize) -> Self {
+        assert!(
+            min_capacity <= max_capacity,
+            "The minimum capacity of a heap can not exceed its maximum capacity"
+        );
+
+        SizingPolicy {
+            min_capacity,
+            max_capacity,
+            target_live_ratio: Self::DEFAULT_TARGET_LIVE_RATIO,
+        }
+    }
+
+    /// A heap which always keeps the same capacity.
+    pub fn fixed(capacity: usize) -> Self {
+        SizingPolicy::new(capacity, capacity)
+    }
+
+    pub fn with_target_live_ratio(self, target_live_ratio: f64) -> Self {
+        assert!(
+            target_live_ratio > 0.0 && target_live_ratio <= 1.0,
+            "The target live ratio must be within (0, 1]"
+        );
+
+        SizingPolicy {
+            target_live_ratio,
+            ..self
+        }
+    }
+
+    pub fn is_fixed(&self) -> bool {
+        self.min_capacity == self.max_capacity
+    }
+
+    /// The capacity a heap of the given size should have after a collection left `live_bytes` in
+    /// use. At least `additional` bytes are added to the current capacity if the heap was asked to
+    /// grow. The result is clamped to the bounds of this policy.
+    pub fn target_capacity(&self, capacity: usize, live_bytes: usize, additional: usize) -> usize {
+        let ideal = (live_bytes as f64 / self.target_live_ratio).ceil() as usize;
+
+        let mut target = if ideal > capacity || ideal <= capacity / 2 {
+            ideal
+        } else {
+            capacity
+        };
+
+        if additional > 0 {
+            target = target.max(capacity.saturating_add(additional));
+        }
+
+        target.clamp(self.min_capacity, self.max_capacity)
+    }
+}
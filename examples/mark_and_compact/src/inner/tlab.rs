@@ -0,0 +1,101 @@
+//! Thread local allocation buffers (TLABs).
+//!
+//! Each allocator claims a chunk of the shared heap and bump allocates objects within it without
+//! needing to lock the heap. Compaction walks the heap object by object, so before a collection the
+//! unused remainder of every TLAB is retired by covering it with a filler object. Filler objects do
+//! not have a reference table slot and are never marked, so they are reclaimed by the next
+//! compaction.
+
+use crate::inner::layout::{self, Object, ObjectHeader};
+use crate::inner::MarkWord;
+use std::mem::size_of;
+use std::ptr::{self, null_mut};
+
+/// The amount of space which must be left at the end of a TLAB so there is always room for the
+/// header of a filler object.
+pub const FILLER_RESERVE: usize = size_of::<ObjectHeader>() + layout::FIXED_ALIGN - 1;
+
+pub struct Tlab {
+    cursor: *mut u8,
+    limit: *mut u8,
+    end: *mut u8,
+    /// The global mark state at the time this TLAB was claimed. A collection can not occur until
+    /// this TLAB is retired, so it will remain the same for all objects allocated within it.
+    mark_state: bool,
+}
+
+impl Tlab {
+    pub const fn empty() -> Self {
+        Tlab {
+            cursor: null_mut(),
+            limit: null_mut(),
+            end: null_mut(),
+            mark_state: false,
+        }
+    }
+
+    /// Create a TLAB covering `start..end`. The range must be at least [`FILLER_RESERVE`] bytes.
+    pub fn new(start: *mut u8, end: *mut u8, mark_state: bool) -> Self {
+        debug_assert!(end as usize - start as usize >= FILLER_RESERVE);
+
+        Tlab {
+            cursor: start,
+            limit: (end as usize - FILLER_RESERVE) as *mut u8,
+            end,
+            mark_state,
+        }
+    }
+
+    #[inline(always)]
+    pub fn mark_state(&self) -> bool {
+        self.mark_state
+    }
+
+    /// The start of the unused portion of this TLAB.
+    pub fn cursor(&self) -> *mut u8 {
+        self.cursor
+    }
+
+    pub fn end(&self) -> *mut u8 {
+        self.end
+    }
+
+    /// Claim space for an object of the given size.
+    #[inline(always)]
+    pub fn bump(&mut self, size: usize) -> Option<(*mut ObjectHeader, *mut Object)> {
+        if self.cursor.is_null() {
+            return None;
+        }
+
+        let (header, obj) = layout::next_obj(self.cursor);
+        let new_cursor = obj as usize + size;
+
+        if new_cursor > self.limit as usize {
+            return None;
+        }
+
+        self.cursor = new_cursor as *mut u8;
+        Some((header, obj))
+    }
+
+    /// Cover the unused remainder of this TLAB with a filler object and leave it empty.
+    ///
+    /// # Safety
+    /// The TLAB must still cover a valid portion of the heap.
+    pub unsafe fn retire(&mut self) {
+        if self.cursor.is_null() {
+            return;
+        }
+
+        let (header, obj) = layout::next_obj(self.cursor);
+        ptr::write(
+            header,
+            ObjectHeader {
+                slot: null_mut(),
+                mark: MarkWord::new(self.end as usize - obj as usize, self.mark_state),
+            },
+        );
+
+        *self = Tlab::empty();
+    }
+}
@@ -1,41 +1,216 @@
-use crate::inner::{MarkCompactAlloc, ObjectHandle};
 use crate::trace::MarkCompactTracer;
-use gc_api::alloc::{Accessor, Alloc, Allocator, CollectionType};
+use gc_api::alloc::{
+    Accessor, Alloc, Allocator, CollectionType, GcEvent, GcStats, HandleOom, HeapStats, ObserveGc,
+    ObserverId, OomAction, OomContext, TaggedSlot,
+};
 use gc_api::error::Error;
-use gc_api::trace::roots::{GcRootStorage, RootStorage, UniformHandleRoots};
+use gc_api::safepoint::Mutator;
+use gc_api::trace::roots::{GcRootStorage, RootStorage};
 use gc_api::trace::Trace;
-use gc_api::Gc;
+use gc_api::{Gc, Heap};
+use inner::SharedHeap;
 use log::trace;
+use std::alloc::Layout;
+use std::ops::Deref;
+use std::ptr::NonNull;
+use std::sync::Arc;
 
 mod inner;
 mod trace;
 
+pub use inner::{MarkCompactAlloc, ObjectHandle, SizingPolicy};
+
 #[cfg(test)]
 mod tests;
 
+/// A mark and compact heap which can be shared between threads. Each thread allocates through its
+/// own [`MarkCompactGC`] created by [`Heap::create_allocator`]. Roots are shared by all allocators.
+#[derive(Clone)]
+pub struct MarkCompactHeap {
+    shared: Arc<SharedHeap>,
+}
+
+impl MarkCompactHeap {
+    /// Create a new mark and compact heap with the given size. The heap will never be resized.
+    pub fn with_capacity(len: usize) -> Self {
+        MarkCompactHeap::with_sizing(SizingPolicy::fixed(len))
+    }
+
+    /// Create a new mark and compact heap which starts at the minimum capacity of the policy and is
+    /// resized after each collection. Resizing is skipped while any objects are pinned.
+    pub fn with_sizing(sizing: SizingPolicy) -> Self {
+        MarkCompactHeap {
+            shared: Arc::new(SharedHeap::new(sizing)),
+        }
+    }
+}
+
+impl Heap for MarkCompactHeap {
+    type Handle = Self;
+    type Allocator = MarkCompactGC;
+
+    /// Create a new allocator and register it as a mutator for this heap. Collections can only
+    /// occur once every allocator has reached a yield point, so allocators which are no longer in
+    /// use should be dropped.
+    fn create_allocator(&self) -> Self::Allocator {
+        MarkCompactGC {
+            alloc: MarkCompactAlloc::new(self.shared.clone()),
+            mutator: self.shared.safepoint().register(),
+        }
+    }
+
+    fn handle(&self) -> Self::Handle {
+        self.clone()
+    }
+}
+
+/// Objects read through the heap stay pinned until their guard is dropped, so the guard can be held
+/// across yield points. Pinning does not root the object, so it must remain reachable.
+impl<T: ?Sized + 'static> Accessor<T, MarkCompactAlloc> for MarkCompactHeap {
+    type Guard<'g> = HeapGuard<'g, T>;
+
+    unsafe fn access<'g>(
+        &'g self,
+        handle: &'g <MarkCompactAlloc as Alloc<T>>::RawHandle,
+    ) -> Result<Self::Guard<'g>, Error> {
+        Ok(HeapGuard {
+            shared: &self.shared,
+            slot: handle.tagged().slot(),
+            ptr: self.pin_handle(handle)?,
+        })
+    }
+
+    fn is_alive(&self, object: &Gc<T, MarkCompactAlloc>) -> Option<bool> {
+        unsafe { Some(object.as_raw().is_alive()) }
+    }
+
+    unsafe fn pin_handle(
+        &self,
+        handle: &<MarkCompactAlloc as Alloc<T>>::RawHandle,
+    ) -> Result<NonNull<T>, Error> {
+        // The object is pinned before its address is read so it can not be moved in between
+        self.shared.pin(handle.tagged().slot());
+
+        handle.get::<T>().inspect_err(|_| {
+            self.shared.unpin(handle.tagged().slot());
+        })
+    }
+
+    unsafe fn unpin_handle(&self, handle: &<MarkCompactAlloc as Alloc<T>>::RawHandle) {
+        self.shared.unpin(handle.tagged().slot());
+    }
+}
+
+/// A reference to an object read through a [`MarkCompactHeap`]. The object is pinned until the
+/// guard is dropped.
+pub struct HeapGuard<'g, T: ?Sized> {
+    shared: &'g SharedHeap,
+    slot: NonNull<TaggedSlot>,
+    ptr: NonNull<T>,
+}
+
+impl<T: ?Sized> Deref for HeapGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: ?Sized> Drop for HeapGuard<'_, T> {
+    fn drop(&mut self) {
+        self.shared.unpin(self.slot);
+    }
+}
+
+impl ObserveGc for MarkCompactHeap {
+    fn add_observer<F>(&mut self, observer: F) -> ObserverId
+    where
+        F: FnMut(&GcEvent) + Send + 'static,
+    {
+        self.shared.add_observer(observer)
+    }
+
+    fn remove_observer(&mut self, id: ObserverId) -> bool {
+        self.shared.remove_observer(id)
+    }
+}
+
+/// The handler installed on a heap is used by every allocator which does not have its own.
+impl HandleOom for MarkCompactHeap {
+    fn set_oom_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&mut OomContext<'_>) -> OomAction + Send + 'static,
+    {
+        self.shared.set_oom_handler(Some(Box::new(handler)));
+    }
+
+    fn remove_oom_handler(&mut self) -> bool {
+        self.shared.set_oom_handler(None).is_some()
+    }
+
+    fn set_emergency_reserve(&mut self, bytes: usize) -> Result<(), Error> {
+        self.shared.set_emergency_reserve(bytes)
+    }
+}
+
+impl HeapStats for MarkCompactHeap {
+    fn stats(&self) -> GcStats {
+        self.shared.stats()
+    }
+}
+
+/// A thread local allocator into a [`MarkCompactHeap`].
 pub struct MarkCompactGC {
+    // The allocator must be dropped first so its TLAB is retired before leaving the safepoint
     alloc: MarkCompactAlloc,
-    roots: UniformHandleRoots<MarkCompactAlloc, ObjectHandle>,
+    mutator: Mutator,
 }
 
 impl MarkCompactGC {
-    /// Create a new mark and compact GC with the given heap size.
+    /// Create a new mark and compact heap with the given size along with an allocator for it.
     pub fn with_capacity(len: usize) -> Self {
-        MarkCompactGC {
-            alloc: MarkCompactAlloc::with_capacity(len),
-            roots: Default::default(),
+        MarkCompactHeap::with_capacity(len).create_allocator()
+    }
+
+    /// Create a new resizable mark and compact heap along with an allocator for it.
+    pub fn with_sizing(sizing: SizingPolicy) -> Self {
+        MarkCompactHeap::with_sizing(sizing).create_allocator()
+    }
+
+    /// Get a handle to the heap this allocator belongs to.
+    pub fn heap(&self) -> MarkCompactHeap {
+        MarkCompactHeap {
+            shared: self.alloc.shared().clone(),
         }
     }
 }
 
-impl<T: 'static> Accessor<T, MarkCompactAlloc> for MarkCompactGC {
+impl<T: ?Sized + 'static> Accessor<T, MarkCompactAlloc> for MarkCompactGC {
     type Guard<'g> = &'g T;
 
     unsafe fn access<'g>(
         &'g self,
         handle: &'g <MarkCompactAlloc as Alloc<T>>::RawHandle,
     ) -> Result<Self::Guard<'g>, Error> {
-        Ok(&*handle.as_ptr().read().cast().as_ptr())
+        Ok(handle.get::<T>()?.as_ref())
+    }
+
+    fn is_alive(&self, object: &Gc<T, MarkCompactAlloc>) -> Option<bool> {
+        unsafe { Some(object.as_raw().is_alive()) }
+    }
+
+    unsafe fn pin_handle(
+        &self,
+        handle: &<MarkCompactAlloc as Alloc<T>>::RawHandle,
+    ) -> Result<NonNull<T>, Error> {
+        let ptr = handle.get::<T>()?;
+        self.alloc.shared().pin(handle.tagged().slot());
+        Ok(ptr)
+    }
+
+    unsafe fn unpin_handle(&self, handle: &<MarkCompactAlloc as Alloc<T>>::RawHandle) {
+        self.alloc.shared().unpin(handle.tagged().slot());
     }
 }
 
@@ -46,20 +221,118 @@ impl Allocator for MarkCompactGC {
     }
 
     fn yield_point(&mut self) {
-        if self.alloc.should_perform_gc() {
-            self.alloc.perform_gc(&self.roots);
+        if !self.alloc.should_perform_gc() {
+            return;
         }
+
+        let MarkCompactGC { alloc, mutator } = self;
+        alloc.retire_tlab();
+
+        // Every allocator retires its TLAB before parking, so it is safe to collect once all of
+        // them have arrived.
+        mutator.yield_point(|| unsafe {
+            alloc.collect(&());
+        });
+    }
+
+    async fn yield_point_async(&mut self) {
+        if !self.alloc.should_perform_gc() {
+            return;
+        }
+
+        let MarkCompactGC { alloc, mutator } = self;
+        alloc.retire_tlab();
+
+        // The allocator is moved into the closure so the future is `Send` along with the allocator
+        mutator
+            .yield_point_async(move || unsafe {
+                alloc.collect(&());
+            })
+            .await;
+    }
+
+    fn request_gc(&mut self, collect: CollectionType) {
+        trace!("Received request for GC: {:?}", collect);
+        self.alloc.gc_at_next_yield(collect);
     }
 
-    fn request_gc(&mut self, _collect: CollectionType) {
-        trace!("Received request for GC: {:?}", _collect);
-        self.alloc.gc_at_next_yield();
+    /// The heap can only be grown during a collection, so this blocks until the next collection has
+    /// completed.
+    fn try_grow(&mut self, additional: usize) -> bool {
+        let capacity = self.alloc.capacity();
+        if !self.alloc.request_growth(additional) {
+            return false;
+        }
+
+        self.yield_point();
+        self.alloc.capacity() > capacity
+    }
+
+    /// Other threads may collect while `f` runs, so the TLAB is retired beforehand.
+    fn blocking<R, F: FnOnce() -> R>(&mut self, f: F) -> R {
+        self.alloc.retire_tlab();
+        self.mutator.blocking(f)
+    }
+
+    fn handle_alloc_failure(&mut self, layout: Layout, error: &Error) -> OomAction {
+        self.alloc.handle_alloc_failure(layout, error)
+    }
+
+    /// Small objects allocated within the scope are placed in a dedicated region which is freed as
+    /// soon as the scope ends. Objects which are pinned or too large for a TLAB are allocated
+    /// normally. If a collection occurs during the scope, the objects allocated up to that point
+    /// are left for the GC to collect instead.
+    fn enter_scope(&mut self) {
+        self.alloc.enter_region();
+    }
+
+    /// In debug builds, this panics if a root or an object which escaped the scope still points
+    /// into the region. See [`MarkCompactAlloc::exit_region`].
+    unsafe fn exit_scope(&mut self) {
+        self.alloc.exit_region();
+    }
+}
+
+impl ObserveGc for MarkCompactGC {
+    fn add_observer<F>(&mut self, observer: F) -> ObserverId
+    where
+        F: FnMut(&GcEvent) + Send + 'static,
+    {
+        self.alloc.add_observer(observer)
+    }
+
+    fn remove_observer(&mut self, id: ObserverId) -> bool {
+        self.alloc.remove_observer(id)
+    }
+}
+
+/// A handler installed on an allocator takes priority over the handler of the heap.
+impl HandleOom for MarkCompactGC {
+    fn set_oom_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&mut OomContext<'_>) -> OomAction + Send + 'static,
+    {
+        self.alloc.set_oom_handler(Some(Box::new(handler)));
+    }
+
+    fn remove_oom_handler(&mut self) -> bool {
+        self.alloc.set_oom_handler(None).is_some()
+    }
+
+    fn set_emergency_reserve(&mut self, bytes: usize) -> Result<(), Error> {
+        self.alloc.shared().set_emergency_reserve(bytes)
+    }
+}
+
+impl HeapStats for MarkCompactGC {
+    fn stats(&self) -> GcStats {
+        self.alloc.stats()
     }
 }
 
 impl Trace<MarkCompactAlloc> for MarkCompactGC {
     fn trace(&self, tracer: &mut MarkCompactTracer) {
-        self.roots.trace(tracer);
+        self.alloc.shared().roots().0.trace(tracer);
     }
 }
 
@@ -67,16 +340,16 @@ impl RootStorage<MarkCompactAlloc> for MarkCompactGC {
     type Index = usize;
 
     fn remove_root(&mut self, index: Self::Index) -> bool {
-        self.roots.remove_root(index)
+        self.alloc.shared().roots().0.remove_root(index)
     }
 }
 
 impl<T> GcRootStorage<T, MarkCompactAlloc> for MarkCompactGC
 where
-    T: Trace<MarkCompactAlloc>,
+    T: ?Sized + Trace<MarkCompactAlloc>,
     MarkCompactAlloc: Alloc<T, RawHandle = ObjectHandle>,
 {
     fn add_root(&mut self, root: &Gc<T, MarkCompactAlloc>) -> Self::Index {
-        self.roots.add_root(root)
+        self.alloc.shared().roots().0.add_root(root)
     }
 }
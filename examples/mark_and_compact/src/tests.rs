@@ -1,9 +1,34 @@
-use crate::MarkCompactGC;
-use gc_api::alloc::{Allocator, CollectionType};
+use crate::trace::MarkCompactTracer;
+use crate::{MarkCompactAlloc, MarkCompactGC, MarkCompactHeap, ObjectHandle, SizingPolicy};
+use gc_api::alloc::profile::{Measure, Profiler, Sample};
+use gc_api::alloc::quota::Quota;
+use gc_api::alloc::{
+    check_flags, Accessor, AllocFlags, Allocator, BackOff, CollectionType, Escalate, GcEvent,
+    HandleOom, HeapStats, NoRetry, ObserveGc, OomAction, RetryAction, RetryPolicy,
+};
+use gc_api::error::{Error, ErrorKind};
+use gc_api::safepoint::Safepoint;
+use gc_api::trace::ephemeron::GcWeakMap;
+use gc_api::trace::roots::{GcRootStorage, RootStorage};
+use gc_api::trace::{NoTrace, Trace};
+use gc_api::{gc_coerce, Gc, GcWeak, Heap};
 use gc_benchmark_utils::tree::Node;
+use std::alloc::Layout;
+use std::collections::HashSet;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Barrier, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
 
-// Use a heap of 1MB for tests due to simplicity.
-const HEAP_SIZE: usize = 1 << 20;
+// Use a heap of 4MB for tests due to simplicity. Handles carry a generation tag and pointer
+// metadata, so a fully live tree of height 14 (32767 nodes at roughly 112 bytes each including the
+// object header) no longer fits in 1MB.
+const HEAP_SIZE: usize = 1 << 22;
 
 #[test]
 pub fn build_tree() {
@@ -24,7 +49,7 @@ pub fn gc_tree() {
     let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
 
     // Create a simple node, but do not root it
-    let node = Node::build_tree_bottom_up(&mut heap, 14);
+    let _node = Node::build_tree_bottom_up(&mut heap, 14);
 
     // Perform a full garbage collection
     heap.request_gc(CollectionType::Full);
@@ -50,3 +75,2228 @@ pub fn many_small() {
         }
     }
 }
+
+#[test]
+pub fn detect_use_after_free() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let rooted = heap.alloc(1u32);
+    heap.add_root(&rooted);
+    let unrooted = heap.alloc(2u32);
+
+    assert_eq!(heap.is_alive(&rooted), Some(true));
+    assert_eq!(heap.is_alive(&unrooted), Some(true));
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    assert_eq!(heap.is_alive(&rooted), Some(true));
+    assert_eq!(*rooted.get(&heap), 1);
+
+    assert_eq!(heap.is_alive(&unrooted), Some(false));
+    let err = unrooted.try_get(&heap).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UseAfterFree);
+}
+
+#[test]
+pub fn stale_handle_after_slot_reuse() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let stale = heap.alloc(3u32);
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    // The freed slot is eventually reused by a new allocation, but the old handle must not see it
+    let fresh = (0..1024u32)
+        .map(|x| heap.alloc(x))
+        .find(|x| x.as_raw().tagged().slot() == stale.as_raw().tagged().slot())
+        .expect("Freed slot was never reused");
+
+    assert_eq!(heap.is_alive(&stale), Some(false));
+    assert!(stale.try_get(&heap).is_err());
+    assert_eq!(heap.is_alive(&fresh), Some(true));
+}
+
+struct Observer {
+    target: GcWeak<u32, MarkCompactAlloc>,
+}
+
+impl Trace<MarkCompactAlloc> for Observer {
+    fn trace(&self, tracer: &mut MarkCompactTracer) {
+        self.target.trace(tracer);
+    }
+}
+
+#[test]
+pub fn upgrade_weak() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let rooted = heap.alloc(5u32);
+    heap.add_root(&rooted);
+    let weak_rooted = rooted.downgrade();
+    let weak_unrooted = heap.alloc(6u32).downgrade();
+
+    let upgraded = weak_unrooted.upgrade(&heap).unwrap();
+    assert_eq!(*upgraded.get(&heap), 6);
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    let upgraded = weak_rooted.upgrade(&heap).unwrap();
+    assert_eq!(*upgraded.get(&heap), 5);
+    assert!(weak_unrooted.upgrade(&heap).is_none());
+}
+
+#[test]
+pub fn weak_edges_do_not_retain() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let target = heap.alloc(7u32);
+    let observer = heap.alloc(Observer {
+        target: target.downgrade(),
+    });
+    heap.add_root(&observer);
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    assert_eq!(heap.is_alive(&observer), Some(true));
+    assert_eq!(heap.is_alive(&target), Some(false));
+    assert!(observer.get(&heap).target.upgrade(&heap).is_none());
+}
+
+type WeakMap = GcWeakMap<u32, Gc<u32, MarkCompactAlloc>, MarkCompactAlloc>;
+
+#[test]
+pub fn ephemeron_values_follow_keys() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let live_key = heap.alloc(1u32);
+    heap.add_root(&live_key);
+    let live_value = heap.alloc(10u32);
+
+    let dead_key = heap.alloc(2u32);
+    let dead_value = heap.alloc(20u32);
+
+    // A value referring back to its own key must not keep the entry alive
+    let self_key = heap.alloc(3u32);
+
+    let mut map = WeakMap::new();
+    map.insert(&live_key, live_value);
+    map.insert(&dead_key, dead_value);
+    map.insert(&self_key, self_key);
+
+    let map = heap.alloc(map);
+    heap.add_root(&map);
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    assert_eq!(heap.is_alive(&live_value), Some(true));
+    assert_eq!(*map.get(&heap).get(&live_key).unwrap().get(&heap), 10);
+
+    assert_eq!(heap.is_alive(&dead_key), Some(false));
+    assert_eq!(heap.is_alive(&dead_value), Some(false));
+    assert_eq!(heap.is_alive(&self_key), Some(false));
+}
+
+#[test]
+pub fn ephemeron_chain_reaches_fixpoint() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let root_key = heap.alloc(1u32);
+    heap.add_root(&root_key);
+    let middle_key = heap.alloc(2u32);
+    let last_key = heap.alloc(3u32);
+    let last_value = heap.alloc(4u32);
+
+    // Entries are inserted so that each key only becomes reachable after the previous entry
+    let mut map = WeakMap::new();
+    map.insert(&last_key, last_value);
+    map.insert(&middle_key, last_key);
+    map.insert(&root_key, middle_key);
+
+    let map = heap.alloc(map);
+    heap.add_root(&map);
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    assert_eq!(heap.is_alive(&middle_key), Some(true));
+    assert_eq!(heap.is_alive(&last_key), Some(true));
+    assert_eq!(heap.is_alive(&last_value), Some(true));
+    assert_eq!(*last_value.get(&heap), 4);
+}
+
+#[test]
+pub fn purge_collected_keys() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let live_key = heap.alloc(1u32);
+    let dead_key = heap.alloc(2u32);
+    let value = heap.alloc(3u32);
+
+    let mut map = WeakMap::new();
+    map.insert(&live_key, value);
+    map.insert(&dead_key, value);
+
+    // Trace the map and one of its keys as the only roots
+    unsafe { heap.alloc.perform_gc(&(&map, live_key)) };
+
+    assert_eq!(heap.is_alive(&value), Some(true));
+    map.purge(&heap);
+
+    assert_eq!(map.len(), 1);
+    assert!(map.contains_key(&live_key));
+    assert!(!map.contains_key(&dead_key));
+}
+
+#[test]
+pub fn identity_survives_compaction() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    // Leave some garbage at the start of the heap so the live objects get moved
+    for i in 0..64u32 {
+        heap.alloc(i);
+    }
+
+    let a = heap.alloc(7u32);
+    let b = heap.alloc(7u32);
+    heap.add_root(&a);
+    heap.add_root(&b);
+
+    assert!(a.ptr_eq(&a));
+    assert!(!a.ptr_eq(&b));
+    assert!(a != b);
+
+    let mut set = HashSet::new();
+    set.insert(a);
+    set.insert(b);
+    set.insert(a);
+    assert_eq!(set.len(), 2);
+
+    let address = unsafe { a.as_raw().get_unchecked::<u32>() };
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    assert_ne!(unsafe { a.as_raw().get_unchecked::<u32>() }, address);
+    assert!(set.contains(&a));
+    assert!(set.contains(&b));
+    assert!(a.downgrade() == a.downgrade());
+    assert!(a.downgrade() != b.downgrade());
+}
+
+/// Counts the number of times it has been dropped
+struct DropCounter(Arc<AtomicUsize>);
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl Trace<MarkCompactAlloc> for DropCounter {
+    fn trace(&self, _: &mut MarkCompactTracer) {}
+}
+
+#[test]
+pub fn finalize_collected_objects() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let rooted = heap.alloc(DropCounter(drops.clone()));
+    heap.add_root(&rooted);
+    heap.alloc(DropCounter(drops.clone()));
+    heap.alloc(vec![
+        DropCounter(drops.clone()),
+        DropCounter(drops.clone()),
+        DropCounter(drops.clone()),
+    ]);
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    assert_eq!(drops.load(Ordering::SeqCst), 4);
+
+    // Objects which survived compaction must still be dropped exactly once with the heap
+    drop(heap);
+    assert_eq!(drops.load(Ordering::SeqCst), 5);
+}
+
+#[test]
+pub fn plain_objects_do_not_pay_for_drop_glue() {
+    use crate::inner::layout::ObjectHeader;
+    use std::mem::size_of;
+
+    // Drop glue lives in a side table, so the header stays at a slot pointer and a mark word
+    assert_eq!(size_of::<ObjectHeader>(), 2 * size_of::<usize>());
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+    let a = heap.alloc(1u64);
+    let b = heap.alloc(2u64);
+    let _ = heap.alloc(DropCounter(drops.clone()));
+    let c = heap.alloc(3u64);
+
+    let address = |object: &Gc<u64, MarkCompactAlloc>| unsafe {
+        object.as_raw().get_unchecked::<u64>().as_ptr() as usize
+    };
+    let stride = size_of::<ObjectHeader>() + size_of::<u64>();
+    assert_eq!(address(&b) - address(&a), stride);
+
+    // Objects which need dropping are laid out exactly like plain ones
+    let counter = size_of::<ObjectHeader>() + size_of::<DropCounter>();
+    assert_eq!(address(&c) - address(&b), stride + counter);
+
+    drop(heap);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+pub fn finalize_owned_buffers() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let text = heap.alloc(String::from("Hello, World!"));
+    heap.add_root(&text);
+
+    for index in 0..1000u32 {
+        heap.alloc(vec![index; 64]);
+        heap.alloc(format!("garbage {}", index));
+    }
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    assert_eq!(text.get(&heap).as_str(), "Hello, World!");
+}
+
+trait Shape: Trace<MarkCompactAlloc> {
+    fn area(&self, heap: &MarkCompactGC) -> u32;
+}
+
+struct Square(u32);
+
+impl Trace<MarkCompactAlloc> for Square {
+    fn trace(&self, _: &mut MarkCompactTracer) {}
+}
+
+impl Shape for Square {
+    fn area(&self, _: &MarkCompactGC) -> u32 {
+        self.0 * self.0
+    }
+}
+
+/// A shape which keeps its dimensions in separate objects
+struct Rect {
+    width: Gc<u32, MarkCompactAlloc>,
+    height: Gc<u32, MarkCompactAlloc>,
+}
+
+impl Trace<MarkCompactAlloc> for Rect {
+    fn trace(&self, tracer: &mut MarkCompactTracer) {
+        self.width.trace(tracer);
+        self.height.trace(tracer);
+    }
+}
+
+impl Shape for Rect {
+    fn area(&self, heap: &MarkCompactGC) -> u32 {
+        *self.width.get(heap) * *self.height.get(heap)
+    }
+}
+
+#[test]
+pub fn coerce_to_trait_object() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    // Leave some garbage at the start of the heap so compaction moves the shapes
+    heap.alloc(0u64);
+
+    let square: Gc<dyn Shape, _> = gc_coerce!(heap.alloc(Square(3)) => dyn Shape);
+    let rect = Rect {
+        width: heap.alloc(4),
+        height: heap.alloc(5),
+    };
+    let rect: Gc<dyn Shape, _> = gc_coerce!(heap.alloc(rect) => dyn Shape);
+
+    heap.add_root(&square);
+    heap.add_root(&rect);
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    // The contents of the rect are only reachable through the trait object
+    assert_eq!(square.get(&heap).area(&heap), 9);
+    assert_eq!(rect.get(&heap).area(&heap), 20);
+}
+
+#[test]
+pub fn coerce_array_to_slice() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let array = heap.alloc([1u32, 2, 3, 4]);
+    let slice: Gc<[u32], _> = gc_coerce!(array => [u32]);
+
+    assert_eq!(slice.get(&heap).len(), 4);
+    assert_eq!(slice.get(&heap).iter().sum::<u32>(), 10);
+    assert_eq!(*array.get(&heap), [1, 2, 3, 4]);
+}
+
+#[test]
+pub fn alloc_slices_and_strings() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    // Garbage at the start of the heap ensures everything below gets moved
+    heap.alloc_slice_fill_copy(100, 0u8);
+
+    let copied = heap.alloc_slice_copy(&[1u64, 2, 3, 4, 5]);
+    let filled = heap.alloc_slice_fill_with(3, |index| format!("item {}", index));
+    let text = heap.alloc_str("hello world");
+    let empty = heap.alloc_slice_copy::<u32>(&[]);
+    let large = heap.alloc_slice_fill_copy(64 * 1024, 7u8);
+
+    heap.add_root(&copied);
+    heap.add_root(&filled);
+    heap.add_root(&text);
+    heap.add_root(&empty);
+    heap.add_root(&large);
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    assert_eq!(*copied.get(&heap), [1, 2, 3, 4, 5]);
+    assert_eq!(*filled.get(&heap), ["item 0", "item 1", "item 2"]);
+    assert_eq!(text.get(&heap), "hello world");
+    assert!(empty.get(&heap).is_empty());
+    assert_eq!(large.get(&heap).len(), 64 * 1024);
+    assert!(large.get(&heap).iter().all(|x| *x == 7));
+}
+
+#[test]
+pub fn finalize_collected_slices() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let rooted = heap.alloc_slice_fill_with(2, |_| DropCounter(drops.clone()));
+    heap.add_root(&rooted);
+    heap.alloc_slice_fill_with(3, |_| DropCounter(drops.clone()));
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    assert_eq!(drops.load(Ordering::SeqCst), 3);
+    assert_eq!(rooted.get(&heap).len(), 2);
+
+    drop(heap);
+    assert_eq!(drops.load(Ordering::SeqCst), 5);
+}
+
+static ZST_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+/// A zero sized type which counts the number of times it has been dropped
+struct ZstDropCounter;
+
+impl Drop for ZstDropCounter {
+    fn drop(&mut self) {
+        ZST_DROPS.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl Trace<MarkCompactAlloc> for ZstDropCounter {
+    fn trace(&self, _: &mut MarkCompactTracer) {}
+}
+
+#[test]
+pub fn finalize_zero_sized_slices() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let rooted = heap.alloc_slice_fill_with(2, |_| ZstDropCounter);
+    heap.add_root(&rooted);
+    heap.alloc_slice_fill_with(3, |_| ZstDropCounter);
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    assert_eq!(ZST_DROPS.load(Ordering::SeqCst), 3);
+    assert_eq!(rooted.get(&heap).len(), 2);
+
+    drop(heap);
+    assert_eq!(ZST_DROPS.load(Ordering::SeqCst), 5);
+}
+
+/// Clones successfully until the given number of clones have been made
+struct PanicOnClone {
+    drops: Arc<AtomicUsize>,
+    clones_left: Arc<AtomicUsize>,
+}
+
+impl Clone for PanicOnClone {
+    fn clone(&self) -> Self {
+        if self.clones_left.fetch_sub(1, Ordering::SeqCst) == 0 {
+            panic!("Injected panic while cloning");
+        }
+
+        PanicOnClone {
+            drops: self.drops.clone(),
+            clones_left: self.clones_left.clone(),
+        }
+    }
+}
+
+impl Drop for PanicOnClone {
+    fn drop(&mut self) {
+        self.drops.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl Trace<MarkCompactAlloc> for PanicOnClone {
+    fn trace(&self, _: &mut MarkCompactTracer) {}
+}
+
+/// Check that the heap is still usable and only holds the rooted object after a failed allocation
+fn assert_heap_intact(heap: &mut MarkCompactGC, rooted: &Gc<[u64], MarkCompactAlloc>) {
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    assert_eq!(heap.stats().live_objects, 1);
+    assert_eq!(*rooted.get(heap), [1, 2, 3]);
+
+    let fresh = heap.alloc_slice_copy(&[4u64, 5]);
+    assert_eq!(*fresh.get(heap), [4, 5]);
+}
+
+#[test]
+pub fn panic_during_slice_fill() {
+    const LEN: usize = 5;
+
+    for panic_at in 0..LEN {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+        let rooted = heap.alloc_slice_copy(&[1u64, 2, 3]);
+        heap.add_root(&rooted);
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            heap.alloc_slice_fill_with(LEN, |index| {
+                if index == panic_at {
+                    panic!("Injected panic at index {}", index);
+                }
+
+                DropCounter(drops.clone())
+            })
+        }));
+        assert!(result.is_err());
+
+        // Only the elements written before the panic are dropped, and only once
+        assert_eq!(drops.load(Ordering::SeqCst), panic_at);
+        assert_heap_intact(&mut heap, &rooted);
+
+        drop(heap);
+        assert_eq!(drops.load(Ordering::SeqCst), panic_at);
+    }
+}
+
+#[test]
+pub fn panic_during_slice_clone() {
+    const LEN: usize = 4;
+
+    for panic_at in 0..LEN {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+        let rooted = heap.alloc_slice_copy(&[1u64, 2, 3]);
+        heap.add_root(&rooted);
+
+        let source = (0..LEN)
+            .map(|_| PanicOnClone {
+                drops: drops.clone(),
+                clones_left: Arc::new(AtomicUsize::new(0)),
+            })
+            .collect::<Vec<_>>();
+        source[panic_at].clones_left.store(0, Ordering::SeqCst);
+        for item in &source[..panic_at] {
+            item.clones_left.store(1, Ordering::SeqCst);
+        }
+
+        let result = catch_unwind(AssertUnwindSafe(|| heap.alloc_slice_clone(&source)));
+        assert!(result.is_err());
+        assert_eq!(drops.load(Ordering::SeqCst), panic_at);
+        assert_heap_intact(&mut heap, &rooted);
+
+        drop(heap);
+        drop(source);
+        assert_eq!(drops.load(Ordering::SeqCst), panic_at + LEN);
+    }
+}
+
+#[test]
+pub fn panic_during_alloc_with() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let rooted = heap.alloc_slice_copy(&[1u64, 2, 3]);
+    heap.add_root(&rooted);
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        heap.alloc_with(|| -> DropCounter { panic!("Injected panic") })
+    }));
+    assert!(result.is_err());
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        heap.try_gc_alloc_setup(None, |counter: &mut Option<DropCounter>| {
+            *counter = Some(DropCounter(drops.clone()));
+            panic!("Injected panic");
+        })
+    }));
+    assert!(result.is_err());
+
+    // The partially initialized value is dropped exactly once
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    assert_heap_intact(&mut heap, &rooted);
+
+    drop(heap);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+pub fn failed_alloc_returns_value() {
+    let mut small = MarkCompactGC::with_capacity(16 * 1024);
+    let mut large = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let array = [3u64; 4 * 1024];
+
+    // Allocations through try_alloc would retry until the heap is collected, so give up instead
+    let err = small
+        .try_gc_alloc_with(NoRetry, || array)
+        .err()
+        .expect("Allocation should fail");
+    assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+    assert_eq!(err.into_inner()(), array);
+
+    // The closure is handed back without being called
+    let calls = AtomicUsize::new(0);
+    let err = small
+        .try_gc_alloc_slice_fill_with(NoRetry, 4 * 1024, |index| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            index as u64
+        })
+        .err()
+        .expect("Allocation should fail");
+    assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+    // Fall back to another heap with the returned value
+    let fallback = match small.try_gc_alloc_with(NoRetry, || array) {
+        Ok(_) => panic!("Allocation should not fit in the heap"),
+        Err(err) => large.alloc_with(err.into_inner()),
+    };
+    assert_eq!(*fallback.get(&large), array);
+}
+
+#[test]
+pub fn heap_stats() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let empty = heap.stats();
+    assert_eq!(empty.capacity, HEAP_SIZE);
+    assert_eq!(empty.bytes_used, 0);
+    assert_eq!(empty.live_objects, 0);
+    assert_eq!(empty.collections.total(), 0);
+
+    let root = heap.alloc(1u64);
+    heap.add_root(&root);
+    for i in 0..10u64 {
+        heap.alloc(i);
+    }
+
+    let before = heap.stats();
+    assert_eq!(before.live_objects, 11);
+    assert!(before.bytes_used > 0);
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    heap.request_gc(CollectionType::Suggest);
+    heap.yield_point();
+
+    let after = heap.stats();
+    assert_eq!(after.live_objects, 1);
+    assert_eq!(after.collections.get(CollectionType::Full), 1);
+    assert_eq!(after.collections.get(CollectionType::Suggest), 1);
+    assert_eq!(after.collections.total(), 2);
+    assert_eq!(
+        after.bytes_reclaimed,
+        (before.bytes_used - after.bytes_used) as u64
+    );
+    assert!(after.pause_time > empty.pause_time);
+}
+
+#[test]
+pub fn observe_collection_events() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let id = heap.add_observer(move |event: &GcEvent| recorded.lock().unwrap().push(*event));
+
+    let root = heap.alloc(1u64);
+    heap.add_root(&root);
+    heap.alloc(2u64);
+
+    heap.request_gc(CollectionType::Partial);
+    heap.yield_point();
+
+    {
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0], GcEvent::CollectionStart(CollectionType::Partial));
+        assert_eq!(events[1], GcEvent::MarkComplete);
+
+        match events[2] {
+            GcEvent::CollectionEnd(report) => {
+                assert_eq!(report.collection, CollectionType::Partial);
+                assert_eq!(report.live_objects, 1);
+                assert!(report.bytes_reclaimed > 0);
+                assert_eq!(report.bytes_used, heap.stats().bytes_used);
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+
+    assert!(heap.remove_observer(id));
+    assert!(!heap.remove_observer(id));
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    assert_eq!(events.lock().unwrap().len(), 3);
+}
+
+#[test]
+pub fn observers_run_while_mutators_are_stopped() {
+    let mut gc = MarkCompactGC::with_capacity(HEAP_SIZE);
+    let mut other = gc.heap().create_allocator();
+    let progress = Arc::new(AtomicUsize::new(0));
+    let done = AtomicBool::new(false);
+
+    // Observers do not touch the heap, they only record what they were told
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let (observed, recorded_seen, recorded_reports) =
+        (progress.clone(), seen.clone(), reports.clone());
+    gc.add_observer(move |event: &GcEvent| {
+        let progress = observed.load(Ordering::SeqCst);
+        match event {
+            GcEvent::CollectionStart(_) => recorded_seen.lock().unwrap().push(progress),
+            GcEvent::CollectionEnd(report) => {
+                recorded_seen.lock().unwrap().push(progress);
+                recorded_reports.lock().unwrap().push(*report);
+            }
+            _ => {}
+        }
+    });
+
+    let rooted = gc.alloc(1u64);
+    gc.add_root(&rooted);
+    gc.alloc(2u64);
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            while !done.load(Ordering::SeqCst) {
+                progress.fetch_add(1, Ordering::SeqCst);
+                other.yield_point();
+            }
+            drop(other);
+        });
+
+        gc.request_gc(CollectionType::Full);
+        gc.yield_point();
+        done.store(true, Ordering::SeqCst);
+    });
+
+    // The other mutator could not make progress while the observer was running
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 2);
+    assert_eq!(seen[0], seen[1]);
+
+    // Once the mutators have resumed, the report agrees with the heap
+    let stats = gc.stats();
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].collection, CollectionType::Full);
+    assert_eq!(reports[0].live_objects, stats.live_objects);
+    assert_eq!(reports[0].bytes_used, stats.bytes_used);
+    assert_eq!(stats.collections.total(), 1);
+}
+
+#[test]
+pub fn safepoint_stops_all_mutators() {
+    const THREADS: usize = 4;
+    const ITERATIONS: usize = 1000;
+
+    let safepoint = Arc::new(Safepoint::new());
+    let working = Arc::new(AtomicUsize::new(0));
+    let collections = Arc::new(AtomicUsize::new(0));
+
+    // All mutators must register before any of them start so a stop can not complete early
+    let barrier = Arc::new(Barrier::new(THREADS + 1));
+    let (idle_tx, idle_rx) = channel::<()>();
+    let idle = {
+        let (safepoint, barrier) = (safepoint.clone(), barrier.clone());
+        thread::spawn(move || {
+            let mutator = safepoint.register();
+            barrier.wait();
+
+            // Sits in a blocking region for the entire test, so it must not hold up collections
+            mutator.blocking(|| idle_rx.recv().unwrap_err());
+        })
+    };
+
+    let workers = (1..THREADS)
+        .map(|id| {
+            let (safepoint, barrier) = (safepoint.clone(), barrier.clone());
+            let (working, collections) = (working.clone(), collections.clone());
+
+            thread::spawn(move || {
+                let mutator = safepoint.register();
+                barrier.wait();
+
+                for i in 0..ITERATIONS {
+                    working.fetch_add(1, Ordering::SeqCst);
+                    thread::yield_now();
+                    working.fetch_sub(1, Ordering::SeqCst);
+
+                    if i % (50 * id) == 0 {
+                        safepoint.request_stop();
+                    }
+
+                    mutator.yield_point(|| {
+                        // No other mutator may be running while collecting
+                        assert_eq!(working.load(Ordering::SeqCst), 0);
+                        collections.fetch_add(1, Ordering::SeqCst);
+                    });
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    barrier.wait();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    drop(idle_tx);
+    idle.join().unwrap();
+
+    let collections = collections.load(Ordering::SeqCst) as u64;
+    assert!(collections > 0);
+    assert_eq!(safepoint.epoch(), collections);
+    assert_eq!(safepoint.mutators(), 0);
+}
+
+#[test]
+pub fn shared_heap_across_threads() {
+    const THREADS: usize = 4;
+
+    let heap = MarkCompactHeap::with_capacity(HEAP_SIZE);
+
+    // Allocators must be created up front so no thread can finish before the others register
+    let workers = (0..THREADS)
+        .map(|id| (id as u64, heap.create_allocator()))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|(id, mut gc)| {
+            thread::spawn(move || {
+                let mut kept = Vec::new();
+
+                for i in 0..2000u64 {
+                    let value = gc.alloc(id << 32 | i);
+                    if i % 10 == 0 {
+                        gc.add_root(&value);
+                        kept.push((i, value));
+                    }
+
+                    // Large enough to bypass the TLAB
+                    if i % 500 == 0 {
+                        gc.alloc([i as u8; 16 * 1024]);
+                    }
+
+                    if i % 300 == 0 {
+                        gc.request_gc(CollectionType::Full);
+                    }
+                    gc.yield_point();
+                }
+
+                for (i, value) in kept {
+                    assert_eq!(*value.get(&gc), id << 32 | i);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let stats = heap.stats();
+    assert!(stats.collections.total() > 0);
+
+    // Only the rooted values remain after a final collection
+    let mut gc = heap.create_allocator();
+    gc.request_gc(CollectionType::Full);
+    gc.yield_point();
+    assert_eq!(heap.stats().live_objects, THREADS * 200);
+}
+
+#[test]
+pub fn objects_survive_tlab_boundaries() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    // Odd sizes ensure TLABs are retired with filler objects of various lengths
+    let mut kept = Vec::new();
+    for i in 0..5000u32 {
+        let value = heap.alloc((i, [i as u8; 13]));
+        if i % 3 == 0 {
+            heap.add_root(&value);
+            kept.push((i, value));
+        }
+    }
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    assert_eq!(heap.stats().live_objects, kept.len());
+    for (i, value) in kept {
+        assert_eq!(*value.get(&heap), (i, [i as u8; 13]));
+    }
+}
+
+#[test]
+pub fn alloc_flags() {
+    assert_eq!(format!("{:?}", AllocFlags::empty()), "AllocFlags(empty)");
+    assert_eq!(
+        format!("{:?}", AllocFlags::PINNED | AllocFlags::ZEROED),
+        "AllocFlags(PINNED | ZEROED)"
+    );
+    assert_eq!(
+        (AllocFlags::PINNED | AllocFlags::LARGE).requirements(),
+        AllocFlags::PINNED
+    );
+    assert!(!AllocFlags::HINTS.intersects(AllocFlags::NO_SCAN | AllocFlags::IMMORTAL));
+    assert!(AllocFlags::NO_SCAN.requirements().is_empty());
+}
+
+#[test]
+pub fn zeroed_alloc() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    // Dirty the heap so the zeroed allocation can not just reuse fresh memory
+    for _ in 0..64 {
+        heap.alloc_slice_fill_copy(256, u64::MAX);
+    }
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    let array = unsafe {
+        heap.try_gc_alloc_init_with_flags::<_, [u64; 256], _>(
+            None,
+            Layout::new::<[u64; 256]>(),
+            |_| {},
+            AllocFlags::ZEROED,
+        )
+        .unwrap_or_else(|err| panic!("{}", err))
+    };
+    assert!(array.get(&heap).iter().all(|x| *x == 0));
+}
+
+#[test]
+pub fn large_flag_skips_tlab() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let large = heap.alloc_with_flags([7u8; 64], AllocFlags::LARGE);
+    assert!(heap.stats().bytes_used < 1024);
+
+    let small = heap.alloc([8u8; 64]);
+    assert!(heap.stats().bytes_used > 1024);
+
+    assert_eq!(*large.get(&heap), [7u8; 64]);
+    assert_eq!(*small.get(&heap), [8u8; 64]);
+}
+
+#[repr(align(64))]
+struct Overaligned(u64);
+
+impl Trace<MarkCompactAlloc> for Overaligned {
+    fn trace(&self, _: &mut MarkCompactTracer) {}
+}
+
+#[test]
+pub fn unsupported_flags() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+    assert_eq!(heap.supported_flags::<u64>(), AllocFlags::all());
+
+    // Pinned allocations used to be rejected, but are now honored by keeping the object in place
+    heap.alloc([0u8; 256]);
+    let pinned = heap
+        .try_alloc_with_flags(5u64, AllocFlags::PINNED)
+        .unwrap_or_else(|err| panic!("{}", err));
+    let address = unsafe { pinned.as_raw().get_unchecked::<u64>() };
+    heap.add_root(&pinned);
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    assert_eq!(unsafe { pinned.as_raw().get_unchecked::<u64>() }, address);
+
+    // Allocations the heap can not honor are still rejected and hand back the value
+    let err = heap
+        .try_alloc_with_flags(Overaligned(7), AllocFlags::PINNED)
+        .err()
+        .expect("Over-aligned allocations are not supported");
+    assert_eq!(err.kind(), ErrorKind::UnsupportedAlignment);
+    assert_eq!(err.into_inner().0, 7);
+
+    let err = check_flags(AllocFlags::HINTS, AllocFlags::PINNED | AllocFlags::LARGE)
+        .expect_err("Pinning is not supported");
+    assert_eq!(err.kind(), ErrorKind::UnsupportedFlags);
+
+    // Hints which can not be honored are ignored
+    assert!(check_flags(AllocFlags::empty(), AllocFlags::HINTS).is_ok());
+}
+
+#[test]
+pub fn pinned_objects_do_not_move() {
+    let mut gc = MarkCompactGC::with_capacity(HEAP_SIZE);
+    let heap = gc.heap();
+
+    // Leave garbage on both sides of the pinned object so objects around it get moved
+    for i in 0..64u32 {
+        gc.alloc(i);
+    }
+    let pinned = gc.alloc([5u64; 16]);
+    for i in 0..64u32 {
+        gc.alloc(i);
+    }
+    let after = gc.alloc(9u64);
+    gc.add_root(&pinned);
+    gc.add_root(&after);
+
+    let guard = heap.pin(&pinned);
+    let address = guard.as_ptr();
+    let after_address = unsafe { after.as_raw().get_unchecked::<u64>() };
+
+    gc.request_gc(CollectionType::Full);
+    gc.yield_point();
+
+    assert_eq!(guard.as_ptr(), address);
+    assert_eq!(*guard, [5u64; 16]);
+    assert_ne!(
+        unsafe { after.as_raw().get_unchecked::<u64>() },
+        after_address
+    );
+    assert_eq!(*after.get(&gc), 9);
+    drop(guard);
+
+    // The gap left before the pinned object is reclaimed once it is unpinned
+    let used = gc.stats().bytes_used;
+    gc.request_gc(CollectionType::Full);
+    gc.yield_point();
+
+    assert!(gc.stats().bytes_used < used);
+    let new_address = unsafe { pinned.as_raw().get_unchecked::<[u64; 16]>() };
+    assert_ne!(new_address.as_ptr() as *const _, address);
+    assert_eq!(*pinned.get(&gc), [5u64; 16]);
+    assert_eq!(*after.get(&gc), 9);
+}
+
+#[test]
+pub fn heap_reads_pin_objects() {
+    let mut gc = MarkCompactGC::with_capacity(HEAP_SIZE);
+    let heap = gc.heap();
+
+    for i in 0..64u32 {
+        gc.alloc(i);
+    }
+    let object = gc.alloc(0xdeadbeef_u64);
+    gc.add_root(&object);
+
+    // A read through the heap does not borrow the allocator, so it may be held across yield points
+    let guard = object.get(&heap);
+    let address = &*guard as *const u64;
+    gc.request_gc(CollectionType::Full);
+    gc.yield_point();
+
+    assert_eq!(*guard, 0xdeadbeef);
+    assert_eq!(
+        unsafe { object.as_raw().get_unchecked::<u64>() }.as_ptr() as *const u64,
+        address
+    );
+    drop(guard);
+
+    // Once the guard is dropped, the object is free to move again
+    gc.request_gc(CollectionType::Full);
+    gc.yield_point();
+    assert_ne!(
+        unsafe { object.as_raw().get_unchecked::<u64>() }.as_ptr() as *const u64,
+        address
+    );
+    assert_eq!(*object.get(&heap), 0xdeadbeef);
+}
+
+#[test]
+pub fn alloc_pinned() {
+    let mut gc = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    for i in 0..64u32 {
+        gc.alloc(i);
+    }
+    let pinned = gc.alloc_with_flags(3u64, AllocFlags::PINNED);
+    let moved = gc.alloc(4u64);
+    gc.add_root(&pinned);
+    gc.add_root(&moved);
+
+    let address = unsafe { pinned.as_raw().get_unchecked::<u64>() };
+    for _ in 0..3 {
+        gc.request_gc(CollectionType::Full);
+        gc.yield_point();
+    }
+
+    assert_eq!(unsafe { pinned.as_raw().get_unchecked::<u64>() }, address);
+    assert_eq!(*pinned.get(&gc), 3);
+    assert_eq!(*moved.get(&gc), 4);
+
+    // Nested pins through an allocator
+    {
+        let a = gc.pin(&moved);
+        let b = gc.pin(&moved);
+        assert_eq!(a.as_ptr(), b.as_ptr());
+        assert_eq!(*a, 4);
+    }
+
+    // Pinning is not supported by the stateless accessor
+    let err = MarkCompactAccessor.try_pin(&moved).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+}
+
+/// An accessor which does not borrow the heap it reads from. The references it hands out are not
+/// tied to the heap at all, so it is only suitable for tests.
+struct MarkCompactAccessor;
+
+impl<T: ?Sized + 'static> Accessor<T, MarkCompactAlloc> for MarkCompactAccessor {
+    type Guard<'g> = &'g T;
+
+    unsafe fn access<'g>(&'g self, handle: &'g ObjectHandle) -> Result<Self::Guard<'g>, Error> {
+        Ok(handle.get::<T>()?.as_ref())
+    }
+
+    fn is_alive(&self, object: &Gc<T, MarkCompactAlloc>) -> Option<bool> {
+        unsafe { Some(object.as_raw().is_alive()) }
+    }
+}
+
+#[test]
+pub fn large_objects_are_not_moved() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    for i in 0..64u32 {
+        heap.alloc(i);
+    }
+    let large = heap.alloc_slice_fill_copy(128 * 1024, 1u8);
+    heap.alloc_slice_fill_copy(256 * 1024, 2u8);
+    let small = heap.alloc(3u32);
+    heap.add_root(&large);
+    heap.add_root(&small);
+
+    let address = unsafe { large.as_raw().get_unchecked::<u8>() };
+    let small_address = unsafe { small.as_raw().get_unchecked::<u32>() };
+    let used = heap.stats().bytes_used;
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    assert_eq!(unsafe { large.as_raw().get_unchecked::<u8>() }, address);
+    assert_ne!(
+        unsafe { small.as_raw().get_unchecked::<u32>() },
+        small_address
+    );
+    assert!(large.get(&heap).iter().all(|x| *x == 1));
+    assert_eq!(*small.get(&heap), 3);
+
+    let stats = heap.stats();
+    assert!(used - stats.bytes_used >= 256 * 1024);
+    assert_eq!(stats.live_objects, 2);
+}
+
+#[test]
+pub fn large_objects_are_freed() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut heap = MarkCompactGC::with_capacity(1 << 20);
+
+    // Each buffer takes up a third of the heap, so space must be reclaimed between allocations
+    for _ in 0..16 {
+        heap.try_gc_alloc_slice_fill_with(Some(1), 320 * 1024, |_| 0u8)
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+    assert!(heap.stats().collections.alloc_at_least > 0);
+
+    let len = 64 * 1024 / std::mem::size_of::<DropCounter>();
+    let rooted = heap.alloc_slice_fill_with(len, |_| DropCounter(drops.clone()));
+    heap.add_root(&rooted);
+    heap.alloc_slice_fill_with(len, |_| DropCounter(drops.clone()));
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    assert_eq!(drops.load(Ordering::SeqCst), len);
+    assert_eq!(rooted.get(&heap).len(), len);
+
+    drop(heap);
+    assert_eq!(drops.load(Ordering::SeqCst), 2 * len);
+}
+
+#[test]
+pub fn immortal_objects() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    for i in 0..64u32 {
+        heap.alloc(i);
+    }
+    let child = heap.alloc(5u32);
+    let immortal = heap.alloc_immortal((child, 6u32));
+    heap.alloc_immortal(DropCounter(drops.clone()));
+    let string = heap.alloc_str_with_flags("symbol", AllocFlags::IMMORTAL);
+
+    let address = unsafe {
+        immortal
+            .as_raw()
+            .get_unchecked::<(Gc<u32, MarkCompactAlloc>, u32)>()
+    };
+    let child_address = unsafe { child.as_raw().get_unchecked::<u32>() };
+
+    for _ in 0..3 {
+        heap.request_gc(CollectionType::Full);
+        heap.yield_point();
+    }
+
+    // Immortal objects stay in place, but the objects they refer to are still compacted
+    assert_eq!(
+        unsafe {
+            immortal
+                .as_raw()
+                .get_unchecked::<(Gc<u32, MarkCompactAlloc>, u32)>()
+        },
+        address
+    );
+    assert_ne!(
+        unsafe { child.as_raw().get_unchecked::<u32>() },
+        child_address
+    );
+    let (child, value) = *immortal.get(&heap);
+    assert_eq!(*child.get(&heap), 5);
+    assert_eq!(value, 6);
+
+    // Immortal objects do not need to be rooted to survive
+    assert_eq!(string.get(&heap), "symbol");
+    assert_eq!(heap.stats().live_objects, 4);
+
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+    drop(heap);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+pub fn immortal_objects_keep_children_alive() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    // Nothing is rooted, so the child is only reachable through the immortal object
+    let child = heap.alloc(5u32);
+    let parent = heap.alloc_with_flags((child, 6u32), AllocFlags::IMMORTAL);
+    let items: Vec<_> = (0..4u64).map(|i| heap.alloc(i)).collect();
+    let slice = heap.alloc_slice_copy_with_flags(&items, AllocFlags::IMMORTAL);
+    drop(items);
+
+    for _ in 0..3 {
+        heap.request_gc(CollectionType::Full);
+        heap.yield_point();
+    }
+
+    let (child, value) = *parent.get(&heap);
+    assert_eq!(*child.get(&heap), 5);
+    assert_eq!(value, 6);
+    for (i, item) in slice.get(&heap).iter().enumerate() {
+        assert_eq!(*item.get(&heap), i as u64);
+    }
+}
+
+static LEAF_TRACES: AtomicUsize = AtomicUsize::new(0);
+
+struct CountTraces(u64);
+
+impl Trace<MarkCompactAlloc> for CountTraces {
+    fn trace(&self, _: &mut MarkCompactTracer) {
+        LEAF_TRACES.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+unsafe impl NoTrace for CountTraces {}
+
+#[test]
+pub fn leaf_objects_are_not_traced() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let leaf = heap.alloc_leaf(CountTraces(1));
+    let traced = heap.alloc(CountTraces(2));
+    // Safe allocation methods can not trust NO_SCAN, so it is ignored
+    let flagged = heap.alloc_with_flags(CountTraces(3), AllocFlags::NO_SCAN);
+    let other_leaf = heap.alloc_leaf(CountTraces(4));
+    let bytes = heap.alloc_slice_copy_leaf(&[5u8; 32]);
+    heap.add_root(&leaf);
+    heap.add_root(&traced);
+    heap.add_root(&flagged);
+    heap.add_root(&other_leaf);
+    heap.add_root(&bytes);
+
+    // Leaf objects are grouped together apart from other objects
+    let leaf_address = unsafe { leaf.as_raw().get_unchecked::<CountTraces>() };
+    let other_address = unsafe { other_leaf.as_raw().get_unchecked::<CountTraces>() };
+    let traced_address = unsafe { traced.as_raw().get_unchecked::<CountTraces>() };
+    let distance = other_address.as_ptr() as usize - leaf_address.as_ptr() as usize;
+    assert!(distance < 64);
+    assert!(traced_address.as_ptr() as usize > other_address.as_ptr() as usize + 1024);
+
+    LEAF_TRACES.store(0, Ordering::SeqCst);
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    assert_eq!(LEAF_TRACES.load(Ordering::SeqCst), 2);
+
+    assert_eq!(leaf.get(&heap).0, 1);
+    assert_eq!(traced.get(&heap).0, 2);
+    assert_eq!(flagged.get(&heap).0, 3);
+    assert_eq!(other_leaf.get(&heap).0, 4);
+    assert_eq!(*bytes.get(&heap), [5u8; 32]);
+    assert_eq!(heap.stats().live_objects, 5);
+}
+
+#[test]
+pub fn scoped_objects_are_freed() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+    let rooted = heap.alloc(1u64);
+    heap.add_root(&rooted);
+
+    let used = heap.stats().bytes_used;
+    let (inner, escaped) = unsafe {
+        heap.scope(|heap| {
+            // Enough objects to need more than one chunk
+            for i in 0..2048u64 {
+                heap.alloc(i);
+            }
+            heap.alloc(DropCounter(drops.clone()));
+            (heap.alloc(5u64), heap.alloc_escaping(6u64))
+        })
+    };
+
+    // The region was at the end of the heap, so all of its space is released immediately
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    assert_eq!(heap.stats().bytes_used, used);
+    assert_eq!(heap.stats().live_objects, 2);
+    assert_eq!(
+        inner.try_get(&heap).err().unwrap().kind(),
+        ErrorKind::UseAfterFree
+    );
+
+    heap.add_root(&escaped);
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    assert_eq!(*rooted.get(&heap), 1);
+    assert_eq!(*escaped.get(&heap), 6);
+}
+
+#[test]
+pub fn nested_scopes() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let (outer, inner, medium) = unsafe {
+        heap.scope(|heap| {
+            let outer = heap.alloc(1u64);
+            let inner = heap.scope(|heap| {
+                let inner = heap.alloc(2u64);
+                assert_eq!(*outer.get(heap), 1);
+                inner
+            });
+            assert!(inner.try_get(heap).is_err());
+            assert_eq!(*outer.get(heap), 1);
+
+            // Objects too large for a TLAB are allocated normally, so the region is no longer at
+            // the end of the heap
+            let medium = heap.alloc_slice_fill_copy(2048, 3u64);
+            (outer, inner, medium)
+        })
+    };
+
+    assert!(outer.try_get(&heap).is_err());
+    assert!(inner.try_get(&heap).is_err());
+    assert_eq!(heap.stats().live_objects, 1);
+
+    // The space of the region is reclaimed by the next compaction
+    heap.add_root(&medium);
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    assert!(medium.get(&heap).iter().all(|x| *x == 3));
+    assert!(heap.stats().bytes_used < 2048 * 8 + 1024);
+}
+
+#[test]
+pub fn collection_during_scope() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let (before, after) = unsafe {
+        heap.scope(|heap| {
+            let before = heap.alloc(1u64);
+            let index = heap.add_root(&before);
+            heap.request_gc(CollectionType::Full);
+            heap.yield_point();
+            heap.remove_root(index);
+
+            let after = heap.alloc(2u64);
+            (before, after)
+        })
+    };
+
+    // Objects allocated before the collection were handed over to the GC
+    assert_eq!(*before.get(&heap), 1);
+    assert!(after.try_get(&heap).is_err());
+    assert_eq!(heap.stats().live_objects, 1);
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    assert!(before.try_get(&heap).is_err());
+    assert_eq!(heap.stats().live_objects, 0);
+}
+
+#[test]
+#[should_panic(expected = "A rooted handle points into a scoped region")]
+#[cfg(debug_assertions)]
+pub fn rooted_scoped_object() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    unsafe {
+        heap.scope(|heap| {
+            let object = heap.alloc(1u64);
+            heap.add_root(&object);
+        });
+    }
+}
+
+#[test]
+#[should_panic(expected = "An object which escaped a scoped region holds a handle into it")]
+#[cfg(debug_assertions)]
+pub fn escaped_object_points_into_scope() {
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    unsafe {
+        heap.scope(|heap| {
+            let object = heap.alloc(1u64);
+            heap.alloc_escaping((object, 2u64));
+        });
+    }
+}
+
+#[test]
+#[should_panic(expected = "A rooted handle points into a scoped region")]
+#[cfg(debug_assertions)]
+pub fn immortal_object_points_into_scope() {
+    use std::cell::RefCell;
+
+    /// A reference which is filled in after its holder was allocated
+    struct LateRef(RefCell<Option<Gc<u64, MarkCompactAlloc>>>);
+
+    impl Trace<MarkCompactAlloc> for LateRef {
+        fn trace(&self, tracer: &mut MarkCompactTracer) {
+            self.0.borrow().trace(tracer);
+        }
+    }
+
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+    let immortal = heap.alloc_immortal(LateRef(RefCell::new(None)));
+
+    unsafe {
+        heap.scope(|heap| {
+            let object = heap.alloc(1u64);
+            *immortal.get(heap).0.borrow_mut() = Some(object);
+        });
+    }
+}
+
+#[test]
+pub fn scope_exits_on_panic() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut heap = MarkCompactGC::with_capacity(HEAP_SIZE);
+
+    let result = catch_unwind(AssertUnwindSafe(|| unsafe {
+        heap.scope(|heap| {
+            heap.alloc(DropCounter(drops.clone()));
+            panic!("Scope was interrupted");
+        })
+    }));
+    assert!(result.is_err());
+
+    // The region was freed while unwinding, so later objects are allocated normally
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    assert_eq!(heap.stats().live_objects, 0);
+
+    let object = heap.alloc(3u64);
+    heap.add_root(&object);
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    assert_eq!(*object.get(&heap), 3);
+}
+
+#[test]
+pub fn wrappers_forward_scopes() {
+    let gc = MarkCompactGC::with_capacity(HEAP_SIZE);
+    let mut heap = Quota::new(Profiler::new(gc, 1), 1 << 20);
+
+    let (scoped, escaped) =
+        unsafe { heap.scope(|heap| (heap.alloc(1u64), heap.alloc_escaping(2u64))) };
+
+    assert!(scoped.try_get(&heap).is_err());
+    assert_eq!(*escaped.get(&heap), 2);
+    assert_eq!(heap.stats().live_objects, 1);
+}
+
+/// Counts the number of times it has been woken
+#[derive(Default)]
+struct CountWakes(AtomicUsize);
+
+impl Wake for CountWakes {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Unparks the thread blocked in [`block_on`]
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// A minimal executor which polls a future on the current thread until it completes
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+pub fn async_yield_point() {
+    let mut gc = MarkCompactGC::with_capacity(HEAP_SIZE);
+    let mut other = gc.heap().create_allocator();
+    let wakes = Arc::new(CountWakes::default());
+    let waker = Waker::from(wakes.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    // The future stays parked until the other mutator arrives and performs the collection
+    gc.request_gc(CollectionType::Full);
+    {
+        let mut future = pin!(gc.yield_point_async());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 0);
+
+        other.yield_point();
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+        assert!(future.as_mut().poll(&mut cx).is_ready());
+    }
+    assert_eq!(other.stats().collections.full, 1);
+
+    // When the last mutator to arrive is async, it performs the collection while being polled
+    other.request_gc(CollectionType::Full);
+    {
+        let mut parked = pin!(other.yield_point_async());
+        assert!(parked.as_mut().poll(&mut cx).is_pending());
+
+        assert!(pin!(gc.yield_point_async()).poll(&mut cx).is_ready());
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 2);
+        assert!(parked.as_mut().poll(&mut cx).is_ready());
+    }
+    assert_eq!(gc.stats().collections.full, 2);
+
+    // Without a pending request, the future resolves immediately
+    block_on(gc.yield_point_async());
+    assert_eq!(gc.stats().collections.full, 2);
+}
+
+#[test]
+pub fn async_yield_point_across_threads() {
+    fn assert_send<F: Future + Send>(future: F) -> F {
+        future
+    }
+
+    let mut gc = MarkCompactGC::with_capacity(HEAP_SIZE);
+    let mut other = gc.heap().create_allocator();
+    let rooted = gc.alloc(5u64);
+    gc.add_root(&rooted);
+
+    // The future is created on this thread, but parks and completes on another one
+    gc.request_gc(CollectionType::Full);
+    let future = assert_send(async {
+        let other = &mut other;
+        other.yield_point_async().await;
+        let value = other.alloc_async(7u64).await;
+        *value.get(other)
+    });
+
+    let value = thread::scope(|scope| {
+        let mutator = scope.spawn(move || block_on(future));
+        gc.yield_point();
+        mutator.join().unwrap()
+    });
+
+    assert_eq!(value, 7);
+    assert_eq!(gc.stats().collections.full, 1);
+    assert_eq!(*rooted.get(&gc), 5);
+
+    // Wrappers keep the futures of the allocator they wrap sendable
+    let mut quota = Quota::new(gc, 1 << 20);
+    let value = thread::scope(|scope| {
+        let future = assert_send(async {
+            let quota = &mut quota;
+            let value = quota.alloc_async(9u64).await;
+            *value.get(quota)
+        });
+        scope.spawn(move || block_on(future)).join().unwrap()
+    });
+    assert_eq!(value, 9);
+    assert_eq!(quota.usage(), 8);
+}
+
+#[test]
+pub fn async_alloc_awaits_collection() {
+    let mut heap = MarkCompactGC::with_capacity(1 << 16);
+    let rooted = block_on(heap.alloc_async(7u64));
+    heap.add_root(&rooted);
+
+    for i in 0..4096u64 {
+        let value = block_on(heap.alloc_async([i; 8]));
+        assert_eq!(value.get(&heap)[0], i);
+    }
+
+    assert!(heap.stats().collections.suggest > 0);
+    assert_eq!(*rooted.get(&heap), 7);
+
+    let err = block_on(unsafe {
+        heap.try_gc_alloc_init_async::<_, [u8; 1 << 17], _>(
+            Some(1),
+            Layout::new::<[u8; 1 << 17]>(),
+            |_| {},
+        )
+    })
+    .err()
+    .unwrap();
+    assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+}
+
+#[test]
+pub fn allocation_quota() {
+    let mut heap = Quota::new(MarkCompactGC::with_capacity(HEAP_SIZE), 1024);
+
+    let a = heap.alloc([1u8; 600]);
+    assert_eq!(heap.usage(), 600);
+
+    // Exceeding the budget fails without attempting a collection
+    let err = heap.try_alloc([2u8; 600]).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::QuotaExceeded);
+    assert_eq!(err.into_inner(), [2u8; 600]);
+    assert_eq!(heap.usage(), 600);
+    assert_eq!(heap.stats().collections.total(), 0);
+
+    // Budgets can be adjusted while in use
+    heap.budget().set_limit(2048);
+    let b = heap.alloc([2u8; 600]);
+    assert_eq!(heap.usage(), 1200);
+    assert_eq!(heap.budget().remaining(), 848);
+    assert_eq!(*a.get(&heap), [1u8; 600]);
+    assert_eq!(*b.get(&heap), [2u8; 600]);
+
+    heap.budget().reset();
+    assert_eq!(heap.usage(), 0);
+
+    // Usage is tracked separately for each allocator
+    let mut other = Quota::new(heap.heap().create_allocator(), 1024);
+    other.alloc(3u64);
+    assert_eq!(other.usage(), 8);
+    assert_eq!(heap.usage(), 0);
+}
+
+#[test]
+pub fn quota_gc_threshold() {
+    let mut heap = Quota::new(MarkCompactGC::with_capacity(HEAP_SIZE), 4096);
+    heap.budget().set_gc_threshold(Some(1000));
+
+    heap.alloc([0u8; 600]);
+    heap.yield_point();
+    assert_eq!(heap.stats().collections.suggest, 0);
+
+    // Crossing the threshold requests a collection before the hard limit is reached
+    heap.alloc([0u8; 600]);
+    heap.yield_point();
+    assert_eq!(heap.stats().collections.suggest, 1);
+
+    heap.alloc([0u8; 600]);
+    heap.yield_point();
+    assert_eq!(heap.stats().collections.suggest, 1);
+}
+
+#[test]
+pub fn quota_credits_collections() {
+    let mut heap = Quota::new(MarkCompactGC::with_capacity(HEAP_SIZE), 1024);
+    let rooted = heap.alloc(7u64);
+    heap.add_root(&rooted);
+
+    heap.alloc([0u8; 600]);
+    assert_eq!(heap.usage(), 608);
+    assert_eq!(
+        heap.try_alloc([0u8; 600]).err().unwrap().kind(),
+        ErrorKind::QuotaExceeded
+    );
+
+    // Without an observer, collections leave the usage untouched
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    assert_eq!(heap.usage(), 608);
+
+    // Once credited, most of the usage is reclaimed along with the garbage
+    let id = heap.credit_collections();
+    heap.alloc([0u8; 400]);
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+    assert!(heap.usage() < 100);
+    heap.alloc([0u8; 600]);
+    assert_eq!(*rooted.get(&heap), 7);
+
+    assert!(heap.remove_observer(id));
+}
+
+/// Records each failure and requests a full collection once before giving up
+#[derive(Default)]
+struct RecordFailures(Vec<u32>);
+
+impl RetryPolicy for RecordFailures {
+    fn on_failure(&mut self, attempt: u32, _: Layout) -> RetryAction {
+        self.0.push(attempt);
+
+        match attempt {
+            0 => RetryAction::Collect(CollectionType::Full),
+            1 => RetryAction::Grow(1024),
+            _ => RetryAction::GiveUp,
+        }
+    }
+}
+
+#[test]
+pub fn custom_retry_policy() {
+    let mut heap = MarkCompactGC::with_capacity(1 << 16);
+    let rooted = heap.alloc_slice_fill_copy(7 * 1024, 1u64);
+    heap.add_root(&rooted);
+
+    let mut policy = RecordFailures::default();
+    let err = heap
+        .try_gc_alloc_with(&mut policy, || [0u64; 2048])
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+    assert_eq!(policy.0, [0, 1, 2]);
+    assert_eq!(heap.stats().collections.full, 1);
+
+    // Without retrying, no collection is requested
+    assert!(heap.try_gc_alloc_with(NoRetry, || [0u64; 2048]).is_err());
+    assert_eq!(heap.stats().collections.total(), 1);
+
+    // The space is available again once the rooted object is freed
+    heap.remove_root(0);
+    let mut policy = RecordFailures::default();
+    let array = heap
+        .try_gc_alloc_with(&mut policy, || [2u64; 2048])
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(policy.0, [0]);
+    assert_eq!(array.get(&heap)[0], 2);
+}
+
+#[test]
+pub fn back_off_does_not_stall_collections() {
+    let mut gc = MarkCompactGC::with_capacity(1 << 16);
+    let mut other = gc.heap().create_allocator();
+    let (started_tx, started_rx) = channel();
+
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            let policy = BackOff {
+                initial: Duration::from_secs(2),
+                retries: 1,
+            };
+            started_tx.send(()).unwrap();
+
+            let err = other
+                .try_gc_alloc_with(policy, || [0u8; 1 << 17])
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+        });
+
+        // Give the other thread time to start backing off. The collection must not wait for it to
+        // finish sleeping.
+        started_rx.recv().unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        let start = Instant::now();
+        gc.request_gc(CollectionType::Full);
+        gc.yield_point();
+        assert!(start.elapsed() < Duration::from_secs(1));
+    });
+
+    assert_eq!(gc.stats().collections.full, 1);
+}
+
+/// Records the delays async allocations wait out instead of sleeping
+struct RecordSleeps {
+    inner: MarkCompactGC,
+    sleeps: Vec<Duration>,
+}
+
+impl Allocator for RecordSleeps {
+    type Alloc = MarkCompactAlloc;
+
+    fn as_raw_allocator(&mut self) -> &mut Self::Alloc {
+        self.inner.as_raw_allocator()
+    }
+
+    fn yield_point(&mut self) {
+        self.inner.yield_point()
+    }
+
+    fn request_gc(&mut self, request: CollectionType) {
+        self.inner.request_gc(request)
+    }
+
+    fn sleep_async(&mut self, delay: Duration) -> Option<impl Future<Output = ()>> {
+        self.sleeps.push(delay);
+        Some(std::future::ready(()))
+    }
+}
+
+#[test]
+pub fn async_back_off_waits_on_timer() {
+    let policy = BackOff {
+        initial: Duration::from_millis(1),
+        retries: 3,
+    };
+
+    let mut timed = RecordSleeps {
+        inner: MarkCompactGC::with_capacity(1 << 16),
+        sleeps: Vec::new(),
+    };
+    let err = block_on(timed.try_gc_alloc_with_async(policy, || [0u8; 1 << 17]))
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+    assert_eq!(timed.sleeps, [1, 2, 4].map(Duration::from_millis).to_vec());
+
+    // Without a timer, async allocations give up instead of retrying without waiting
+    let mut heap = MarkCompactGC::with_capacity(1 << 16);
+    let policy = BackOff {
+        initial: Duration::from_secs(60),
+        retries: u32::MAX,
+    };
+    let start = Instant::now();
+    let err = block_on(heap.try_gc_alloc_with_async(policy, || [0u8; 1 << 17]))
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+/// Root 4KB arrays until the heap is full
+fn fill_heap(heap: &mut MarkCompactGC) {
+    while let Ok(array) = heap.try_gc_alloc_with(Escalate::new(), || [1u64; 512]) {
+        heap.add_root(&array);
+    }
+}
+
+#[test]
+pub fn oom_handler_releases_reserve() {
+    let mut heap = MarkCompactGC::with_capacity(1 << 17);
+    heap.set_emergency_reserve(16 * 1024)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(
+        heap.set_emergency_reserve(1 << 20).err().unwrap().kind(),
+        ErrorKind::OutOfMemory
+    );
+
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let recorded = failures.clone();
+    heap.set_oom_handler(move |context| {
+        let released = context.reserve().release_all();
+        recorded
+            .lock()
+            .unwrap()
+            .push((context.layout().size(), context.error().kind(), released));
+
+        match released {
+            0 => OomAction::Fail,
+            _ => OomAction::Retry,
+        }
+    });
+
+    fill_heap(&mut heap);
+    let array = heap.alloc([2u64; 512]);
+    assert_eq!(array.get(&heap)[0], 2);
+    assert_eq!(
+        *failures.lock().unwrap(),
+        [(4096, ErrorKind::OutOfMemory, 16 * 1024)]
+    );
+
+    // Once the reserve is used up, the handler lets the allocation fail
+    fill_heap(&mut heap);
+    let result = catch_unwind(AssertUnwindSafe(|| heap.alloc([3u64; 512])));
+    assert!(result.is_err());
+    assert_eq!(failures.lock().unwrap().len(), 2);
+}
+
+#[test]
+pub fn reentrant_oom_handler() {
+    let mut gc = MarkCompactGC::with_capacity(1 << 17);
+    let mut heap = gc.heap();
+    let nested = heap.clone();
+    let (nested_tx, nested_rx) = channel();
+
+    // The handler allocates through another allocator, which runs out of memory in turn
+    heap.set_oom_handler(move |_| {
+        let mut quota = Quota::new(nested.create_allocator(), 0);
+        let result = catch_unwind(AssertUnwindSafe(|| quota.alloc(1u64)));
+        nested_tx.send(result.is_err()).unwrap();
+        OomAction::Fail
+    });
+
+    // The nested failure is not handed back to the handler which is already running
+    fill_heap(&mut gc);
+    let result = catch_unwind(AssertUnwindSafe(|| gc.alloc([0u64; 512])));
+    assert!(result.is_err());
+    assert!(nested_rx.try_recv().unwrap());
+    assert!(nested_rx.try_recv().is_err());
+
+    assert!(heap.remove_oom_handler());
+}
+
+/// The payload of a panic raised by an OOM handler
+struct OutOfMemoryException(usize);
+
+#[test]
+pub fn heap_oom_handler() {
+    let mut gc = MarkCompactGC::with_capacity(1 << 17);
+    let mut heap = gc.heap();
+    heap.set_oom_handler(|context| {
+        std::panic::panic_any(OutOfMemoryException(context.layout().size()))
+    });
+
+    fill_heap(&mut gc);
+    let err = catch_unwind(AssertUnwindSafe(|| gc.alloc_slice_copy(&[0u8; 8192])))
+        .err()
+        .unwrap();
+    assert_eq!(err.downcast_ref::<OutOfMemoryException>().unwrap().0, 8192);
+
+    // A handler on the allocator takes priority over the handler of the heap
+    gc.set_oom_handler(|_| OomAction::Fail);
+    let text = "a".repeat(8192);
+    let err = catch_unwind(AssertUnwindSafe(|| gc.alloc_str(&text)))
+        .err()
+        .unwrap();
+    assert!(err.downcast_ref::<OutOfMemoryException>().is_none());
+
+    assert!(gc.remove_oom_handler());
+    assert!(!gc.remove_oom_handler());
+    let err = catch_unwind(AssertUnwindSafe(|| gc.alloc([5u64; 1024])))
+        .err()
+        .unwrap();
+    assert_eq!(err.downcast_ref::<OutOfMemoryException>().unwrap().0, 8192);
+}
+
+#[test]
+pub fn heap_oom_handler_across_threads() {
+    let mut gc = MarkCompactGC::with_capacity(1 << 16);
+    let mut heap = gc.heap();
+    let mut other = heap.create_allocator();
+
+    // The handler stays inside its call until the main thread has handled a failure of its own
+    let released = Arc::new(AtomicUsize::new(0));
+    let (entered_tx, entered_rx) = channel();
+    let (release_tx, release_rx) = channel::<()>();
+    heap.set_oom_handler({
+        let released = released.clone();
+        move |_| {
+            entered_tx.send(()).unwrap();
+            if release_rx.recv_timeout(Duration::from_secs(5)).is_ok() {
+                released.fetch_add(1, Ordering::SeqCst);
+            }
+            OomAction::Fail
+        }
+    });
+
+    thread::scope(|scope| {
+        let failing = scope.spawn(move || {
+            catch_unwind(AssertUnwindSafe(|| other.alloc_slice_copy(&[0u8; 1 << 17]))).is_err()
+        });
+        gc.blocking(|| entered_rx.recv().unwrap());
+
+        // Neither replacing the handler nor calling it waits for the handler which is still running
+        heap.set_oom_handler(|_| OomAction::Retry);
+        let error = Error::new(ErrorKind::OutOfMemory, "Out of memory");
+        assert_eq!(
+            gc.handle_alloc_failure(Layout::new::<u64>(), &error),
+            OomAction::Retry
+        );
+
+        release_tx.send(()).unwrap();
+        assert!(failing.join().unwrap());
+    });
+
+    assert_eq!(released.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+pub fn heap_grows_and_shrinks() {
+    let sizing = SizingPolicy::new(1 << 16, 1 << 20);
+    let mut heap = MarkCompactGC::with_sizing(sizing);
+    assert_eq!(heap.stats().capacity, 1 << 16);
+
+    let resizes = Arc::new(Mutex::new(Vec::new()));
+    let recorded = resizes.clone();
+    heap.add_observer(move |event: &GcEvent| {
+        if let GcEvent::HeapResized {
+            old_capacity,
+            new_capacity,
+        } = *event
+        {
+            recorded.lock().unwrap().push((old_capacity, new_capacity));
+        }
+    });
+
+    // Keep far more alive than the initial capacity can hold
+    let arrays: Vec<_> = (0..64u64)
+        .map(|i| {
+            let array = heap.alloc([i; 512]);
+            heap.add_root(&array);
+            array
+        })
+        .collect();
+
+    let grown = heap.stats().capacity;
+    assert!(grown > 256 * 1024);
+    assert!(grown <= 1 << 20);
+    for (i, array) in arrays.iter().enumerate() {
+        assert_eq!(array.get(&heap)[511], i as u64);
+    }
+
+    {
+        let resizes = resizes.lock().unwrap();
+        assert!(!resizes.is_empty());
+        assert_eq!(resizes[0].0, 1 << 16);
+        assert!(resizes.iter().all(|(old, new)| new > old));
+        assert_eq!(resizes.last().unwrap().1, grown);
+    }
+
+    // Once most objects die, the heap shrinks back towards its minimum capacity
+    for index in 0..63 {
+        heap.remove_root(index);
+    }
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    assert_eq!(heap.stats().capacity, 1 << 16);
+    assert_eq!(*resizes.lock().unwrap().last().unwrap(), (grown, 1 << 16));
+    assert_eq!(arrays[63].get(&heap)[0], 63);
+}
+
+#[test]
+pub fn target_live_ratio() {
+    let sizing = SizingPolicy::new(1 << 16, 1 << 22).with_target_live_ratio(0.25);
+    let mut heap = MarkCompactGC::with_sizing(sizing);
+
+    for _ in 0..32 {
+        let array = heap.alloc([0u64; 512]);
+        heap.add_root(&array);
+    }
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    let stats = heap.stats();
+    assert!(stats.bytes_used * 4 <= stats.capacity);
+    assert!(stats.bytes_used * 2 > stats.capacity / 4);
+}
+
+#[test]
+pub fn try_grow_heap() {
+    let mut fixed = MarkCompactGC::with_capacity(1 << 16);
+    assert!(!fixed.try_grow(4096));
+
+    let mut heap = MarkCompactGC::with_sizing(SizingPolicy::new(1 << 16, 1 << 18));
+    let kept = heap.alloc(7u64);
+    heap.add_root(&kept);
+
+    assert!(heap.try_grow(1 << 16));
+    assert!(heap.stats().capacity >= 1 << 17);
+    assert_eq!(*kept.get(&heap), 7);
+
+    // Growth is limited by the maximum capacity
+    assert!(heap.try_grow(1 << 20));
+    assert_eq!(heap.stats().capacity, 1 << 18);
+    assert!(!heap.try_grow(4096));
+
+    // Pinned objects can not be moved, so the heap can not be resized while they are held
+    let mut heap = MarkCompactGC::with_sizing(SizingPolicy::new(1 << 16, 1 << 18));
+    let pinned = heap.alloc(3u64);
+    heap.add_root(&pinned);
+    let handle = heap.heap();
+    let guard = handle.pin(&pinned);
+    assert!(!heap.try_grow(4096));
+    assert_eq!(heap.stats().capacity, 1 << 16);
+    drop(guard);
+    assert!(heap.try_grow(4096));
+}
+
+#[test]
+pub fn escalate_with_growth() {
+    // A full heap never exceeds a target live ratio of 1, so collections alone never grow it
+    let sizing = SizingPolicy::new(1 << 16, 1 << 18).with_target_live_ratio(1.0);
+    let mut heap = MarkCompactGC::with_sizing(sizing);
+    fill_heap(&mut heap);
+    let capacity = heap.stats().capacity;
+    assert_eq!(capacity, 1 << 16);
+
+    let array = heap
+        .try_gc_alloc_with(Escalate::new().with_growth(), || [4u64; 512])
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(array.get(&heap)[0], 4);
+    assert!(heap.stats().capacity > capacity);
+}
+
+#[test]
+pub fn sampling_profiler() {
+    let mut heap = Profiler::new(MarkCompactGC::with_capacity(HEAP_SIZE), 1);
+
+    for i in 0..10u64 {
+        let kept = heap.alloc(i);
+        heap.add_root(&kept);
+    }
+    for i in 0..20u64 {
+        heap.alloc([i; 4]);
+    }
+
+    assert_eq!(heap.samples().len(), 30);
+
+    let profile = heap.report();
+    assert_eq!(profile.total_bytes(), 10 * 8 + 20 * 32);
+    assert_eq!(profile.live_bytes(), profile.total_bytes());
+
+    heap.request_gc(CollectionType::Full);
+    heap.yield_point();
+
+    let profile = heap.report();
+    assert_eq!(profile.sites().len(), 2);
+    assert_eq!(profile.total_bytes(), 10 * 8 + 20 * 32);
+    assert_eq!(profile.live_bytes(), 10 * 8);
+
+    let kept = &profile.sites()[0];
+    assert_eq!(kept.type_name, "u64");
+    assert_eq!(
+        (kept.samples, kept.live_bytes, kept.total_bytes),
+        (10, 80, 80)
+    );
+    assert!(kept
+        .frames
+        .iter()
+        .any(|frame| frame.contains("sampling_profiler")));
+
+    // Frames from within the allocation methods are left out
+    assert!(!kept.frames[0].contains("gc_api::alloc"));
+
+    let garbage = &profile.sites()[1];
+    assert_eq!(garbage.type_name, "[u64; 4]");
+    assert_eq!((garbage.live_bytes, garbage.total_bytes), (0, 640));
+
+    let text = profile.to_string();
+    assert!(text.contains("80 bytes live, 720 bytes allocated across 2 sites"));
+    assert!(text.contains("0 bytes live, 640 bytes allocated, 20 samples: [u64; 4]"));
+
+    let live = profile.collapsed(Measure::Live);
+    assert_eq!(live.lines().count(), 1);
+    assert!(live.ends_with(";u64 80\n"));
+
+    let total = profile.collapsed(Measure::Total);
+    assert_eq!(total.lines().count(), 2);
+    assert!(total.lines().any(|line| line.ends_with(";[u64:4] 640")));
+    assert!(total.lines().all(|line| line.contains("sampling_profiler")));
+
+    heap.clear();
+    assert_eq!(heap.report().sites().len(), 0);
+
+    // Every allocation method is sampled, not just the ones which initialize memory directly
+    heap.alloc_slice_copy(&[0u8; 16]);
+    heap.alloc_str("sampled");
+    block_on(heap.alloc_async(1u8));
+    let types: Vec<_> = heap.samples().iter().map(Sample::type_name).collect();
+    assert_eq!(types, ["[u8]", "str", "u8"]);
+}
+
+#[test]
+pub fn profiler_moves_between_threads() {
+    // Samples do not hold the profiler back from being as thread safe as the allocator it wraps
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Sample<MarkCompactAlloc>>();
+
+    let mut heap = Profiler::new(MarkCompactGC::with_capacity(HEAP_SIZE), 1);
+    let kept = heap.alloc(1u64);
+    heap.add_root(&kept);
+
+    // Samples taken on one thread can still be checked for liveness on another
+    let mut heap = thread::spawn(move || {
+        let mut heap = heap;
+        heap.alloc(2u64);
+        heap.request_gc(CollectionType::Full);
+        heap.yield_point();
+        heap
+    })
+    .join()
+    .unwrap();
+
+    let profile = heap.report();
+    assert_eq!(profile.total_bytes(), 16);
+    assert_eq!(profile.live_bytes(), 8);
+}
+
+#[test]
+pub fn sample_interval() {
+    let mut heap = Profiler::new(MarkCompactGC::with_capacity(HEAP_SIZE), 64);
+
+    for i in 0..100u64 {
+        heap.alloc(i);
+    }
+    assert_eq!(heap.samples().len(), 800 / 64);
+    assert!(heap.samples().iter().all(|sample| sample.weight() == 64));
+
+    // An allocation spanning several intervals stands in for all of them
+    heap.alloc([0u64; 64]);
+    let sample = heap.samples().last().unwrap();
+    assert_eq!(sample.layout(), Layout::new::<[u64; 64]>());
+    assert_eq!(sample.weight(), 512);
+
+    let profile = heap.report();
+    assert_eq!(profile.total_bytes(), 12 * 64 + 512);
+}
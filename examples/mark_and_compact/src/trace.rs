@@ -1,13 +1,23 @@
-use crate::inner::{MarkCompactAlloc, MarkWord};
-use gc_api::alloc::Alloc;
+use crate::inner::layout::ObjectHeader;
+use crate::inner::{layout, MarkCompactAlloc};
+use gc_api::alloc::{Alloc, RawMeta};
 use gc_api::mark::Mark;
 use gc_api::trace::{Trace, Tracer, TracingAllocator};
-use gc_api::Gc;
-use std::mem::size_of;
+use gc_api::{Gc, GcWeak};
+use std::mem;
+use std::ptr::NonNull;
+
+/// An ephemeron whose key had not been marked at the time it was traced.
+struct DeferredEphemeron<'a> {
+    key: *mut ObjectHeader,
+    value: *const (),
+    trace: unsafe fn(*const (), &mut MarkCompactTracer<'a>),
+}
 
 pub struct MarkCompactTracer<'a> {
     gc: &'a MarkCompactAlloc,
     mark_state: bool,
+    ephemerons: Vec<DeferredEphemeron<'a>>,
     pub traced: usize,
 }
 
@@ -16,13 +26,71 @@ impl<'a> MarkCompactTracer<'a> {
         MarkCompactTracer {
             gc,
             mark_state,
+            ephemerons: Vec::new(),
             traced: 0,
         }
     }
+
+    unsafe fn is_marked(&self, header: *mut ObjectHeader) -> bool {
+        (*header).mark.load_mark_state() == self.mark_state
+    }
+}
+
+/// An object allocated with [`AllocFlags::IMMORTAL`](gc_api::alloc::AllocFlags::IMMORTAL). Immortal
+/// objects are never moved, so they can be traced through a pointer to their data.
+pub(crate) struct ImmortalRoot {
+    ptr: NonNull<u8>,
+    meta: RawMeta,
+    trace: unsafe fn(NonNull<u8>, RawMeta, &mut MarkCompactTracer),
+}
+
+impl ImmortalRoot {
+    /// A pointer to the data of the immortal object.
+    #[cfg(debug_assertions)]
+    pub fn object(&self) -> NonNull<u8> {
+        self.ptr
+    }
+}
+
+impl Trace<MarkCompactAlloc> for ImmortalRoot {
+    fn trace(&self, tracer: &mut MarkCompactTracer) {
+        unsafe { (self.trace)(self.ptr, self.meta, tracer) }
+    }
+}
+
+unsafe fn trace_immortal<T: ?Sized + Trace<MarkCompactAlloc>>(
+    ptr: NonNull<u8>,
+    meta: RawMeta,
+    tracer: &mut MarkCompactTracer,
+) {
+    (*meta.with_addr::<T>(ptr).as_ptr()).trace(tracer)
+}
+
+unsafe fn trace_deferred<V: Trace<MarkCompactAlloc>>(
+    value: *const (),
+    tracer: &mut MarkCompactTracer,
+) {
+    (*(value as *const V)).trace(tracer)
 }
 
 impl TracingAllocator for MarkCompactAlloc {
     type Tracer<'a> = MarkCompactTracer<'a>;
+
+    fn register_immortal<T>(&mut self, object: &Gc<T, Self>)
+    where
+        Self: Alloc<T>,
+        T: ?Sized + Trace<Self>,
+    {
+        unsafe {
+            let data = <Self as Alloc<T>>::handle_ref(self, object.as_raw()) as *const T;
+
+            self.shared().immortal().0.push(ImmortalRoot {
+                ptr: NonNull::new_unchecked(data as *mut u8),
+                meta: RawMeta::of(data),
+                trace: trace_immortal::<T>,
+            });
+        }
+    }
 }
 
 impl<'a> Tracer<'a, MarkCompactAlloc> for MarkCompactTracer<'a> {
@@ -33,17 +101,79 @@ impl<'a> Tracer<'a, MarkCompactAlloc> for MarkCompactTracer<'a> {
     {
         unsafe {
             let ptr = <MarkCompactAlloc as Alloc<T>>::handle_ptr(self.gc, obj.as_raw()).as_ptr();
-            let mark_ptr = (ptr as usize - size_of::<MarkWord>()) as *mut MarkWord;
+            let header = layout::header_of(ptr);
 
             // Ensure this object is marked and return early if it is.
-            if (*mark_ptr).swap_mark_state(self.mark_state) == self.mark_state {
+            if (*header).mark.swap_mark_state(self.mark_state) == self.mark_state {
                 return;
             }
+
+            self.traced += 1;
+
+            // Leaf objects do not contain any handles, so there is nothing to trace
+            if (*header).mark.is_leaf() {
+                return;
+            }
+
+            <MarkCompactAlloc as Alloc<T>>::handle_ref(self.gc, obj.as_raw()).trace(self);
+        }
+    }
+
+    fn trace_ephemeron<K: ?Sized, V: Trace<MarkCompactAlloc>>(
+        &mut self,
+        key: &GcWeak<K, MarkCompactAlloc>,
+        value: &V,
+    ) where
+        MarkCompactAlloc: Alloc<K>,
+    {
+        // The key was collected in a previous GC so the value is unreachable
+        if <MarkCompactAlloc as Alloc<K>>::handle_is_alive(self.gc, key.as_raw()) == Some(false) {
+            return;
         }
 
-        self.traced += 1;
         unsafe {
-            <MarkCompactAlloc as Alloc<T>>::handle_ref(self.gc, obj.as_raw()).trace(self);
+            let ptr = <MarkCompactAlloc as Alloc<K>>::handle_ptr(self.gc, key.as_raw()).as_ptr();
+            let header = layout::header_of(ptr);
+
+            if self.is_marked(header) {
+                value.trace(self);
+            } else {
+                // Objects do not move until marking completes, so the value will remain valid
+                self.ephemerons.push(DeferredEphemeron {
+                    key: header,
+                    value: value as *const V as *const (),
+                    trace: trace_deferred::<V>,
+                });
+            }
         }
     }
+
+    fn process_ephemerons(&mut self) {
+        loop {
+            let pending = mem::take(&mut self.ephemerons);
+            let pending_len = pending.len();
+
+            let mut remaining = Vec::with_capacity(pending_len);
+            for entry in pending {
+                unsafe {
+                    if self.is_marked(entry.key) {
+                        (entry.trace)(entry.value, self);
+                    } else {
+                        remaining.push(entry);
+                    }
+                }
+            }
+
+            // Tracing a value may have deferred further ephemerons
+            let made_progress = remaining.len() < pending_len;
+            self.ephemerons.append(&mut remaining);
+
+            if !made_progress {
+                break;
+            }
+        }
+
+        // Anything left over has an unreachable key
+        self.ephemerons.clear();
+    }
 }
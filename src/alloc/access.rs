@@ -21,9 +21,10 @@
 //!
 
 use crate::alloc::AllocMut;
-use crate::error::Error;
-use crate::{Alloc, Gc, GcMut};
+use crate::error::{Error, ErrorKind};
+use crate::{Alloc, Gc, GcMut, GcWeak};
 use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
 
 pub trait Accessor<T: ?Sized, A>: Sized
 where
@@ -51,6 +52,44 @@ where
         None
     }
 
+    /// Attempt to upgrade a weak handle into a strong handle. This will only succeed if the object
+    /// is known to still be alive, so garbage collectors which do not support [`Accessor::is_alive`]
+    /// will always return `None`.
+    fn upgrade(&self, weak: &GcWeak<T, A>) -> Option<Gc<T, A>>
+    where
+        <A as Alloc<T>>::RawHandle: Clone,
+    {
+        let strong = unsafe { Gc::from_raw(weak.as_raw().clone()) };
+
+        match self.is_alive(&strong) {
+            Some(true) => Some(strong),
+            _ => None,
+        }
+    }
+
+    /// Pin an object so it will not be moved by the GC until the returned guard is dropped. This
+    /// makes it safe to hand the address of the object to foreign code for the lifetime of the
+    /// guard. Pinning does not root the object, so it must remain reachable to avoid being
+    /// collected.
+    #[inline(always)]
+    fn pin<'g>(&'g self, object: &'g Gc<T, A>) -> PinGuard<'g, T, A, Self> {
+        self.try_pin(object)
+            .unwrap_or_else(|err| failed_access(err))
+    }
+
+    #[inline(always)]
+    fn try_pin<'g>(&'g self, object: &'g Gc<T, A>) -> Result<PinGuard<'g, T, A, Self>, Error> {
+        unsafe {
+            let ptr = self.pin_handle(&object.handle)?;
+
+            Ok(PinGuard {
+                accessor: self,
+                handle: &object.handle,
+                ptr,
+            })
+        }
+    }
+
     /// Creates a guard which can be used to read the data associated with this handle.
     ///
     /// # Safety
@@ -59,6 +98,77 @@ where
         &'g self,
         handle: &'g <A as Alloc<T>>::RawHandle,
     ) -> Result<Self::Guard<'g>, Error>;
+
+    /// Prevent the object referenced by this handle from being moved and get its current address.
+    /// Pins may be nested, so every successful call must be balanced by a call to
+    /// [`Accessor::unpin_handle`]. GCs which never move objects can simply return the address of
+    /// the object. By default, pinning is not supported and an [`ErrorKind::Unsupported`] error is
+    /// returned.
+    ///
+    /// # Safety
+    /// The handle must corespond to a valid GC object in the heap used by this accessor.
+    unsafe fn pin_handle(&self, _handle: &<A as Alloc<T>>::RawHandle) -> Result<NonNull<T>, Error> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "Accessor does not support pinning objects",
+        ))
+    }
+
+    /// Release a pin previously acquired through [`Accessor::pin_handle`].
+    ///
+    /// # Safety
+    /// The handle must have been successfully pinned by this accessor and not yet unpinned.
+    unsafe fn unpin_handle(&self, _handle: &<A as Alloc<T>>::RawHandle) {}
+}
+
+/// A guard which keeps an object at a stable address until it is dropped. See [`Accessor::pin`].
+pub struct PinGuard<'g, T, A, Acc>
+where
+    T: ?Sized,
+    A: Alloc<T>,
+    Acc: Accessor<T, A>,
+{
+    accessor: &'g Acc,
+    handle: &'g <A as Alloc<T>>::RawHandle,
+    ptr: NonNull<T>,
+}
+
+impl<'g, T, A, Acc> PinGuard<'g, T, A, Acc>
+where
+    T: ?Sized,
+    A: Alloc<T>,
+    Acc: Accessor<T, A>,
+{
+    /// The address of the pinned object. It will remain valid until this guard is dropped.
+    #[inline(always)]
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr.as_ptr()
+    }
+}
+
+impl<'g, T, A, Acc> Deref for PinGuard<'g, T, A, Acc>
+where
+    T: ?Sized,
+    A: Alloc<T>,
+    Acc: Accessor<T, A>,
+{
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<'g, T, A, Acc> Drop for PinGuard<'g, T, A, Acc>
+where
+    T: ?Sized,
+    A: Alloc<T>,
+    Acc: Accessor<T, A>,
+{
+    fn drop(&mut self) {
+        unsafe { self.accessor.unpin_handle(self.handle) }
+    }
 }
 
 pub trait AccessorMut<T: ?Sized, A>: Accessor<T, A>
@@ -1,14 +1,17 @@
 use std::alloc::Layout;
 use std::fmt::Debug;
-use std::ptr;
+use std::future::Future;
+use std::marker::PhantomData;
 use std::ptr::NonNull;
+use std::time::Duration;
+use std::{mem, ptr, thread};
 
-use crate::error::Error;
-use crate::error::ErrorKind::OutOfMemory;
+use crate::alloc::{AllocFlags, Escalate, Finalize, OomAction, RawMeta, RetryAction, RetryPolicy};
+use crate::error::ErrorKind::{AllocationTooLarge, OutOfMemory};
+use crate::error::{AllocError, Error};
+use crate::trace::{NoTrace, Trace, TracingAllocator};
 use crate::{Alloc, AllocMut, Gc, GcMut};
 
-pub const DEFAULT_ALLOC_RETRY_LIMIT: Option<u32> = Some(3);
-
 /// Notes:
 /// - Encode metadata in Gc pointer
 /// - Swap error out with trait system?
@@ -23,8 +26,25 @@ pub trait Allocator {
     /// does not guarantee garbage collection will occur.
     ///
     /// For garbage collectors that do not require yield points, this will be treated as a no-op.
+    /// Multithreaded garbage collectors can use [`crate::safepoint`] to coordinate with the other
+    /// mutator threads.
     fn yield_point(&mut self);
 
+    /// An async counterpart to [`Allocator::yield_point`]. Instead of blocking the current thread
+    /// until the other mutators have parked, the returned future resolves once this allocator has
+    /// been safely parked and the collection is complete. See
+    /// [`Mutator::yield_point_async`](crate::safepoint::Mutator::yield_point_async).
+    ///
+    /// By default, this performs a regular [`Allocator::yield_point`] when first polled.
+    ///
+    /// The futures returned by this method and the `_async` allocation methods hold nothing but
+    /// the allocator and the value being allocated across await points, so they are [`Send`]
+    /// whenever the concrete allocator's futures are. This lets them be driven on a different
+    /// thread than the one which created them.
+    fn yield_point_async(&mut self) -> impl Future<Output = ()> {
+        async move { self.yield_point() }
+    }
+
     /// Request that garbage collection is performed at the next `yield_point`. This function should
     /// only be called if recommended by the underlying implementation. Extraneous calls to this
     /// function may have an adverse effect on performance.
@@ -34,12 +54,141 @@ pub trait Allocator {
     /// can or will be performed.
     fn request_gc(&mut self, request: CollectionType);
 
+    /// Run `f` within a scoped region. Objects allocated inside the scope may be placed in a region
+    /// which is freed all at once when the scope ends, without waiting for a collection. Objects
+    /// which need to outlive the scope must be allocated with [`AllocFlags::ESCAPE`] (See
+    /// [`Allocator::alloc_escaping`]). Scopes may be nested, in which case each scope frees the
+    /// objects allocated since it was entered.
+    ///
+    /// This runs `f` between calls to [`Allocator::enter_scope`] and [`Allocator::exit_scope`]. The
+    /// scope is exited even if `f` panics.
+    ///
+    /// # Safety
+    /// Once the scope ends, no handle to an object allocated within it (other than those allocated
+    /// with [`AllocFlags::ESCAPE`]) may be reachable from a root or another object. Heaps may check
+    /// for escaping handles in debug builds, but such checks are heuristics which can miss handles,
+    /// so passing them does not make a scope sound.
+    unsafe fn scope<R, F>(&mut self, f: F) -> R
+    where
+        Self: Sized,
+        F: FnOnce(&mut Self) -> R,
+    {
+        self.enter_scope();
+        let guard = ScopeGuard { allocator: self };
+        f(&mut *guard.allocator)
+    }
+
+    /// Start a new scope. Each call must be matched by a later call to [`Allocator::exit_scope`].
+    /// Allocators which wrap another allocator should forward both hooks to it. See
+    /// [`Allocator::scope`].
+    ///
+    /// By default, this does nothing and leaves everything to the GC.
+    #[inline(always)]
+    fn enter_scope(&mut self) {}
+
+    /// End the innermost scope started by [`Allocator::enter_scope`].
+    ///
+    /// # Safety
+    /// See [`Allocator::scope`].
+    #[inline(always)]
+    unsafe fn exit_scope(&mut self) {}
+
+    /// Called once before each allocation made through this trait. Returning an error fails the
+    /// allocation without attempting a collection. If the allocation later fails, the charge is
+    /// returned through [`Allocator::refund_alloc`].
+    ///
+    /// Wrappers such as [`Quota`](crate::alloc::quota::Quota) use this to enforce allocation
+    /// budgets. By default, every allocation is accepted.
+    #[inline(always)]
+    fn charge_alloc(&mut self, _layout: Layout) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Return the charge for an allocation which was accepted by [`Allocator::charge_alloc`], but
+    /// could not be completed.
+    #[inline(always)]
+    fn refund_alloc(&mut self, _layout: Layout) {}
+
+    /// Called once after each allocation made through this trait has been initialized, before it
+    /// is returned. The object is only borrowed, so implementations which need to check on it
+    /// later should do so through [`Alloc::liveness_check`].
+    ///
+    /// Wrappers such as [`Profiler`](crate::alloc::profile::Profiler) use this to sample
+    /// allocations. By default, this does nothing.
+    #[inline(always)]
+    fn record_alloc<T>(&mut self, _layout: Layout, _object: &Gc<T, Self::Alloc>)
+    where
+        T: ?Sized,
+        Self::Alloc: Alloc<T>,
+    {
+    }
+
+    /// Called when an infallible allocation method (Ex: [`Allocator::alloc`]) fails once its retry
+    /// policy has given up. Returning [`OomAction::Retry`] attempts the allocation again, while
+    /// [`OomAction::Fail`] causes the allocation to panic. See [`crate::alloc::oom`].
+    ///
+    /// By default, every failure is fatal.
+    fn handle_alloc_failure(&mut self, _layout: Layout, _error: &Error) -> OomAction {
+        OomAction::Fail
+    }
+
+    /// Attempt to grow the heap so at least `additional` more bytes can be allocated. Returns
+    /// `true` if the heap was grown. This is used when a [`RetryPolicy`] decides to
+    /// [grow](RetryAction::Grow) the heap instead of collecting.
+    ///
+    /// By default, heaps can not be grown.
+    fn try_grow(&mut self, _additional: usize) -> bool {
+        false
+    }
+
+    /// Run a function which may block for an extended period of time. Allocators which coordinate
+    /// with other mutators should let collections proceed while `f` runs (Ex: with
+    /// [`Mutator::blocking`](crate::safepoint::Mutator::blocking)), so the heap must not be
+    /// accessed by `f`. This is used to wait out a [`RetryAction::BackOff`].
+    ///
+    /// By default, this simply calls `f`.
+    fn blocking<R, F: FnOnce() -> R>(&mut self, f: F) -> R {
+        f()
+    }
+
+    /// A timer for async allocations to wait out a [`RetryAction::BackOff`]. The returned future
+    /// must resolve once `delay` has passed and, like [`Allocator::blocking`], should let
+    /// collections proceed while it is pending. Returns `None` if no timer is available, in which
+    /// case async allocations treat a back off as [`RetryAction::GiveUp`].
+    ///
+    /// By default, there is no timer.
+    fn sleep_async(&mut self, _delay: Duration) -> Option<impl Future<Output = ()>> {
+        None::<std::future::Ready<()>>
+    }
+
+    /// Check which [`AllocFlags`] can be honored when allocating a `T`. See
+    /// [`Alloc::supported_flags`].
+    fn supported_flags<T>(&mut self) -> AllocFlags
+    where
+        T: ?Sized,
+        Self::Alloc: Alloc<T>,
+    {
+        Alloc::<T>::supported_flags(self.as_raw_allocator())
+    }
+
     #[inline(always)]
     fn alloc<T>(&mut self, val: T) -> Gc<T, Self::Alloc>
     where
         Self::Alloc: Alloc<T>,
     {
-        self.alloc_with(|| val)
+        alloc_value(self, || val, AllocFlags::empty())
+    }
+
+    /// Allocate a value with the given flags. Objects allocated with [`AllocFlags::IMMORTAL`] are
+    /// handed to [`TracingAllocator::register_immortal`] once initialized, so the `_with_flags`
+    /// methods require the value to be traceable.
+    #[inline(always)]
+    fn alloc_with_flags<T>(&mut self, val: T, flags: AllocFlags) -> Gc<T, Self::Alloc>
+    where
+        T: Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<T>,
+    {
+        self.alloc_with_flags_fn(|| val, flags)
     }
 
     #[inline(always)]
@@ -48,15 +197,97 @@ pub trait Allocator {
         Self::Alloc: AllocMut<T>,
         <Self::Alloc as Alloc<T>>::MutTy: From<T>,
     {
-        self.alloc_with::<_, <Self::Alloc as Alloc<T>>::MutTy>(|| val.into())
+        alloc_value::<_, _, <Self::Alloc as Alloc<T>>::MutTy>(
+            self,
+            || val.into(),
+            AllocFlags::empty(),
+        )
+    }
+
+    #[inline(always)]
+    fn alloc_mut_with_flags<T>(&mut self, val: T, flags: AllocFlags) -> GcMut<T, Self::Alloc>
+    where
+        Self::Alloc: TracingAllocator + AllocMut<T>,
+        <Self::Alloc as Alloc<T>>::MutTy: From<T> + Trace<Self::Alloc>,
+    {
+        self.alloc_with_flags_fn::<_, <Self::Alloc as Alloc<T>>::MutTy>(|| val.into(), flags)
+    }
+
+    /// Allocate a value which will never be moved or collected, such as an interned symbol or
+    /// another runtime constant. Any handles held by the object are kept alive for as long as the
+    /// heap exists.
+    ///
+    /// This is a shorthand for allocating with [`AllocFlags::IMMORTAL`].
+    #[inline(always)]
+    fn alloc_immortal<T>(&mut self, val: T) -> Gc<T, Self::Alloc>
+    where
+        T: Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<T>,
+    {
+        self.alloc_with_flags(val, AllocFlags::IMMORTAL)
     }
 
+    /// Allocate a value which must outlive the current [`Allocator::scope`]. This is a shorthand for
+    /// allocating with [`AllocFlags::ESCAPE`].
     #[inline(always)]
-    fn try_alloc<T>(&mut self, val: T) -> Result<Gc<T, Self::Alloc>, Error>
+    fn alloc_escaping<T>(&mut self, val: T) -> Gc<T, Self::Alloc>
     where
         Self::Alloc: Alloc<T>,
     {
-        self.try_alloc_with(|| val)
+        alloc_value(self, || val, AllocFlags::ESCAPE)
+    }
+
+    /// Allocate a value which does not contain any GC handles. The allocator is told the object is
+    /// a leaf (See [`AllocFlags::NO_SCAN`]) so it can be skipped during tracing.
+    #[inline(always)]
+    fn alloc_leaf<T>(&mut self, val: T) -> Gc<T, Self::Alloc>
+    where
+        T: NoTrace,
+        Self::Alloc: Alloc<T>,
+    {
+        let layout = Layout::new::<T>();
+        let init = |ptr: NonNull<u8>| unsafe { ptr::write(ptr.cast::<T>().as_ptr(), val) };
+
+        alloc_or_handle(self, layout, init, |this, init| unsafe {
+            try_alloc_init(
+                this,
+                Escalate::new(),
+                layout,
+                RawMeta::THIN,
+                init,
+                AllocFlags::NO_SCAN,
+            )
+        })
+    }
+
+    /// Attempt to allocate a value, collecting and retrying for as long as the heap is out of
+    /// memory. If allocation fails for any other reason, the value is returned along with the
+    /// error. Use [`Allocator::try_gc_alloc_with`] with a [`RetryPolicy`] to give up sooner.
+    #[inline(always)]
+    fn try_alloc<T>(&mut self, val: T) -> Result<Gc<T, Self::Alloc>, AllocError<T>>
+    where
+        Self::Alloc: Alloc<T>,
+    {
+        self.try_alloc_with(|| val).map_err(|err| {
+            let (error, f) = err.into_parts();
+            AllocError::new(error, f())
+        })
+    }
+
+    #[inline(always)]
+    fn try_alloc_with_flags<T>(
+        &mut self,
+        val: T,
+        flags: AllocFlags,
+    ) -> Result<Gc<T, Self::Alloc>, AllocError<T>>
+    where
+        T: Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<T>,
+    {
+        self.try_alloc_with_flags_fn(|| val, flags).map_err(|err| {
+            let (error, f) = err.into_parts();
+            AllocError::new(error, f())
+        })
     }
 
     #[inline(always)]
@@ -65,76 +296,268 @@ pub trait Allocator {
         F: FnOnce() -> T,
         Self::Alloc: Alloc<T>,
     {
-        self.try_gc_alloc_with(DEFAULT_ALLOC_RETRY_LIMIT, f)
-            .unwrap_or_else(|err| failed_allocation(err))
+        alloc_value(self, f, AllocFlags::empty())
+    }
+
+    #[inline(always)]
+    fn alloc_with_flags_fn<F, T>(&mut self, f: F, flags: AllocFlags) -> Gc<T, Self::Alloc>
+    where
+        F: FnOnce() -> T,
+        T: Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<T>,
+    {
+        let object = alloc_value(self, f, flags);
+        register_immortal(self, &object, flags);
+        object
     }
 
+    /// Attempt to allocate the value produced by `f`. The function is only called once space has
+    /// been allocated, so it is returned unused if allocation fails.
     #[inline(always)]
-    fn try_gc_alloc_with<F, T>(
+    fn try_gc_alloc_with<F, T, P>(
         &mut self,
-        retry_limit: Option<u32>,
+        policy: P,
         f: F,
-    ) -> Result<Gc<T, Self::Alloc>, Error>
+    ) -> Result<Gc<T, Self::Alloc>, AllocError<F>>
     where
         F: FnOnce() -> T,
         Self::Alloc: Alloc<T>,
+        P: RetryPolicy,
     {
-        let layout = Layout::new::<T>();
+        try_alloc_value(self, policy, f, AllocFlags::empty())
+    }
 
-        unsafe {
-            self.try_gc_alloc_init(retry_limit, layout, |ptr| {
-                ptr::write(ptr.as_ptr() as *mut T, f())
-            })
-        }
+    #[inline(always)]
+    fn try_gc_alloc_with_flags_fn<F, T, P>(
+        &mut self,
+        policy: P,
+        f: F,
+        flags: AllocFlags,
+    ) -> Result<Gc<T, Self::Alloc>, AllocError<F>>
+    where
+        F: FnOnce() -> T,
+        T: Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<T>,
+        P: RetryPolicy,
+    {
+        let object = try_alloc_value(self, policy, f, flags)?;
+        register_immortal(self, &object, flags);
+        Ok(object)
     }
 
     /// This function attempts to allocate a new object on the heap in accordance to the given
-    /// layout. The caller can then choose how they would like to initialize that memory.
+    /// layout. The caller can then choose how they would like to initialize that memory. Once
+    /// initialized, the drop glue for `T` is registered with the allocator (if required). When the
+    /// heap runs out of memory, `policy` decides how to recover (See [`RetryPolicy`]). If allocation
+    /// fails, the init function is returned without being called.
+    ///
+    /// This function can not provide pointer metadata, so DSTs should instead be allocated with
+    /// [`Allocator::try_gc_alloc_init_unsized`].
     ///
     /// # Safety
     /// The caller must fully initialize the object data via the init function. Failing to do so may
     /// result in undefined behavior comparable to calling [`std::mem::MaybeUninit::assume_init`]
     /// without fully initializing the type.
     #[inline(always)]
-    unsafe fn try_gc_alloc_init<F, T>(
+    unsafe fn try_gc_alloc_init<F, T, P>(
         &mut self,
-        mut retry_limit: Option<u32>,
+        policy: P,
         layout: Layout,
         init: F,
-    ) -> Result<Gc<T, Self::Alloc>, Error>
+    ) -> Result<Gc<T, Self::Alloc>, AllocError<F>>
     where
-        T: ?Sized,
+        T: ?Sized + Finalize,
         F: FnOnce(NonNull<u8>),
         Self::Alloc: Alloc<T>,
+        P: RetryPolicy,
     {
-        let handle = loop {
-            match unsafe { Alloc::<T>::try_alloc_layout(self.as_raw_allocator(), layout) } {
-                Ok(handle) => break handle,
-                Err(err) if err.kind() == OutOfMemory => {
-                    // Decrement retry counter
-                    match &mut retry_limit {
-                        None => {}
-                        Some(0) => return Err(err),
-                        Some(x) => *x -= 1,
-                    }
+        try_alloc_init(
+            self,
+            policy,
+            layout,
+            RawMeta::THIN,
+            init,
+            AllocFlags::empty(),
+        )
+    }
 
-                    // Request that a GC be performed and attempt to yield
-                    self.request_gc(CollectionType::AllocAtLeast(layout));
-                    self.yield_point();
-                }
-                Err(err) => return Err(err),
-            };
-        };
+    /// # Safety
+    /// See [`Allocator::try_gc_alloc_init`].
+    ///
+    /// If `flags` contains [`AllocFlags::NO_SCAN`], the object must not hold any GC handles since
+    /// the allocator may never trace it.
+    #[inline(always)]
+    unsafe fn try_gc_alloc_init_with_flags<F, T, P>(
+        &mut self,
+        policy: P,
+        layout: Layout,
+        init: F,
+        flags: AllocFlags,
+    ) -> Result<Gc<T, Self::Alloc>, AllocError<F>>
+    where
+        T: ?Sized + Finalize + Trace<Self::Alloc>,
+        F: FnOnce(NonNull<u8>),
+        Self::Alloc: TracingAllocator + Alloc<T>,
+        P: RetryPolicy,
+    {
+        self.try_gc_alloc_init_unsized_with_flags(policy, layout, RawMeta::THIN, init, flags)
+    }
 
-        unsafe {
-            let data_ptr = Alloc::<T>::handle_ptr(self.as_raw_allocator(), &handle);
-            debug_assert!(
-                data_ptr.as_ptr() as usize & (layout.align() - 1) == 0,
-                "GC allocation did not meet required alignment"
-            );
-
-            init(data_ptr);
-            Ok(Gc::from_raw(handle))
+    /// The same as [`Allocator::try_gc_alloc_init`], but for DSTs which require pointer metadata
+    /// (Ex: the length of a slice).
+    ///
+    /// # Safety
+    /// The layout must be the layout of a `T` with the given metadata and the caller must fully
+    /// initialize the object data via the init function.
+    #[inline(always)]
+    unsafe fn try_gc_alloc_init_unsized<F, T, P>(
+        &mut self,
+        policy: P,
+        layout: Layout,
+        meta: RawMeta,
+        init: F,
+    ) -> Result<Gc<T, Self::Alloc>, AllocError<F>>
+    where
+        T: ?Sized + Finalize,
+        F: FnOnce(NonNull<u8>),
+        Self::Alloc: Alloc<T>,
+        P: RetryPolicy,
+    {
+        try_alloc_init(self, policy, layout, meta, init, AllocFlags::empty())
+    }
+
+    /// # Safety
+    /// See [`Allocator::try_gc_alloc_init_unsized`].
+    ///
+    /// If `flags` contains [`AllocFlags::NO_SCAN`], the object must not hold any GC handles since
+    /// the allocator may never trace it.
+    #[inline(always)]
+    unsafe fn try_gc_alloc_init_unsized_with_flags<F, T, P>(
+        &mut self,
+        policy: P,
+        layout: Layout,
+        meta: RawMeta,
+        init: F,
+        flags: AllocFlags,
+    ) -> Result<Gc<T, Self::Alloc>, AllocError<F>>
+    where
+        T: ?Sized + Finalize + Trace<Self::Alloc>,
+        F: FnOnce(NonNull<u8>),
+        Self::Alloc: TracingAllocator + Alloc<T>,
+        P: RetryPolicy,
+    {
+        let object = try_alloc_init(self, policy, layout, meta, init, flags)?;
+        register_immortal(self, &object, flags);
+        Ok(object)
+    }
+
+    /// Allocate a value, awaiting [`Allocator::yield_point_async`] whenever a collection is needed
+    /// to make space for it instead of blocking the current thread.
+    #[inline(always)]
+    fn alloc_async<T>(&mut self, val: T) -> impl Future<Output = Gc<T, Self::Alloc>>
+    where
+        Self::Alloc: Alloc<T>,
+    {
+        alloc_value_async(self, val, AllocFlags::empty())
+    }
+
+    #[inline(always)]
+    fn alloc_async_with_flags<T>(
+        &mut self,
+        val: T,
+        flags: AllocFlags,
+    ) -> impl Future<Output = Gc<T, Self::Alloc>>
+    where
+        T: Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<T>,
+    {
+        async move {
+            let object = alloc_value_async(self, val, flags).await;
+            register_immortal(self, &object, flags);
+            object
+        }
+    }
+
+    /// The async counterpart to [`Allocator::try_gc_alloc_with`]. Each retry awaits the collection
+    /// through [`Allocator::yield_point_async`].
+    #[inline(always)]
+    fn try_gc_alloc_with_async<F, T, P>(
+        &mut self,
+        policy: P,
+        f: F,
+    ) -> impl Future<Output = Result<Gc<T, Self::Alloc>, AllocError<F>>>
+    where
+        F: FnOnce() -> T,
+        Self::Alloc: Alloc<T>,
+        P: RetryPolicy,
+    {
+        try_alloc_value_async(self, policy, f, AllocFlags::empty())
+    }
+
+    #[inline(always)]
+    fn try_gc_alloc_with_async_with_flags<F, T, P>(
+        &mut self,
+        policy: P,
+        f: F,
+        flags: AllocFlags,
+    ) -> impl Future<Output = Result<Gc<T, Self::Alloc>, AllocError<F>>>
+    where
+        F: FnOnce() -> T,
+        T: Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<T>,
+        P: RetryPolicy,
+    {
+        async move {
+            let object = try_alloc_value_async(self, policy, f, flags).await?;
+            register_immortal(self, &object, flags);
+            Ok(object)
+        }
+    }
+
+    /// The async counterpart to [`Allocator::try_gc_alloc_init`]. Each retry awaits the collection
+    /// through [`Allocator::yield_point_async`].
+    ///
+    /// # Safety
+    /// See [`Allocator::try_gc_alloc_init`].
+    #[inline(always)]
+    unsafe fn try_gc_alloc_init_async<F, T, P>(
+        &mut self,
+        policy: P,
+        layout: Layout,
+        init: F,
+    ) -> impl Future<Output = Result<Gc<T, Self::Alloc>, AllocError<F>>>
+    where
+        T: ?Sized + Finalize,
+        F: FnOnce(NonNull<u8>),
+        Self::Alloc: Alloc<T>,
+        P: RetryPolicy,
+    {
+        try_alloc_init_async(self, policy, layout, init, AllocFlags::empty())
+    }
+
+    /// # Safety
+    /// See [`Allocator::try_gc_alloc_init`].
+    ///
+    /// If `flags` contains [`AllocFlags::NO_SCAN`], the object must not hold any GC handles since
+    /// the allocator may never trace it.
+    #[inline(always)]
+    unsafe fn try_gc_alloc_init_async_with_flags<F, T, P>(
+        &mut self,
+        policy: P,
+        layout: Layout,
+        init: F,
+        flags: AllocFlags,
+    ) -> impl Future<Output = Result<Gc<T, Self::Alloc>, AllocError<F>>>
+    where
+        T: ?Sized + Finalize + Trace<Self::Alloc>,
+        F: FnOnce(NonNull<u8>),
+        Self::Alloc: TracingAllocator + Alloc<T>,
+        P: RetryPolicy,
+    {
+        async move {
+            let object = try_alloc_init_async(self, policy, layout, init, flags).await?;
+            register_immortal(self, &object, flags);
+            Ok(object)
         }
     }
 
@@ -142,35 +565,61 @@ pub trait Allocator {
     /// unsafe code the unitialized value is first initialized to its default value before being
     /// handed to the user.
     #[inline(always)]
-    fn try_gc_alloc_setup<F, T>(
+    fn try_gc_alloc_setup<F, T, P>(
         &mut self,
-        retry_limit: Option<u32>,
+        policy: P,
         init: F,
-    ) -> Result<Gc<T, Self::Alloc>, Error>
+    ) -> Result<Gc<T, Self::Alloc>, AllocError<F>>
     where
-        T: ?Sized + Default,
+        T: Default,
         F: FnOnce(&mut T),
         Self::Alloc: Alloc<T>,
+        P: RetryPolicy,
     {
-        let layout = Layout::new::<T>();
+        try_alloc_setup(self, policy, init, AllocFlags::empty())
+    }
 
-        unsafe {
-            self.try_gc_alloc_init(retry_limit, layout, |ptr| {
-                let mut_ref = &mut *ptr.cast().as_ptr();
-                // Hopefully the compiler will understand that this call can be optimized away
-                *mut_ref = T::default();
-                init(mut_ref);
-            })
-        }
+    #[inline(always)]
+    fn try_gc_alloc_setup_with_flags<F, T, P>(
+        &mut self,
+        policy: P,
+        init: F,
+        flags: AllocFlags,
+    ) -> Result<Gc<T, Self::Alloc>, AllocError<F>>
+    where
+        T: Default + Trace<Self::Alloc>,
+        F: FnOnce(&mut T),
+        Self::Alloc: TracingAllocator + Alloc<T>,
+        P: RetryPolicy,
+    {
+        let object = try_alloc_setup(self, policy, init, flags)?;
+        register_immortal(self, &object, flags);
+        Ok(object)
     }
 
+    /// Attempt to allocate the value produced by `f`, collecting and retrying for as long as the
+    /// heap is out of memory. See [`Allocator::try_alloc`] and [`Allocator::try_gc_alloc_with`].
     #[inline(always)]
-    fn try_alloc_with<F, T>(&mut self, f: F) -> Result<Gc<T, Self::Alloc>, Error>
+    fn try_alloc_with<F, T>(&mut self, f: F) -> Result<Gc<T, Self::Alloc>, AllocError<F>>
     where
         F: FnOnce() -> T,
         Self::Alloc: Alloc<T>,
     {
-        self.try_gc_alloc_with(None, f)
+        try_alloc_value(self, None::<u32>, f, AllocFlags::empty())
+    }
+
+    #[inline(always)]
+    fn try_alloc_with_flags_fn<F, T>(
+        &mut self,
+        f: F,
+        flags: AllocFlags,
+    ) -> Result<Gc<T, Self::Alloc>, AllocError<F>>
+    where
+        F: FnOnce() -> T,
+        T: Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<T>,
+    {
+        self.try_gc_alloc_with_flags_fn(None::<u32>, f, flags)
     }
 
     #[inline(always)]
@@ -179,14 +628,34 @@ pub trait Allocator {
         T: Copy,
         Self::Alloc: Alloc<[T]>,
     {
-        let layout = Layout::new::<T>();
+        unsafe { alloc_copied_slice(self, src, AllocFlags::empty()) }
+    }
 
-        unsafe {
-            self.try_gc_alloc_init(DEFAULT_ALLOC_RETRY_LIMIT, layout, |ptr| {
-                ptr::copy_nonoverlapping(src.as_ptr(), ptr.as_ptr() as *mut T, src.len());
-            })
-            .unwrap_or_else(|err| failed_allocation(err))
-        }
+    #[inline(always)]
+    fn alloc_slice_copy_with_flags<T>(
+        &mut self,
+        src: &[T],
+        flags: AllocFlags,
+    ) -> Gc<[T], Self::Alloc>
+    where
+        T: Copy + Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<[T]>,
+    {
+        let flags = safe_flags(flags);
+        let object = unsafe { alloc_copied_slice(self, src, flags) };
+        register_immortal(self, &object, flags);
+        object
+    }
+
+    /// The same as [`Allocator::alloc_slice_copy`], but the allocator is told the slice is a leaf.
+    /// See [`Allocator::alloc_leaf`].
+    #[inline(always)]
+    fn alloc_slice_copy_leaf<T>(&mut self, src: &[T]) -> Gc<[T], Self::Alloc>
+    where
+        T: Copy + NoTrace,
+        Self::Alloc: Alloc<[T]>,
+    {
+        unsafe { alloc_copied_slice(self, src, AllocFlags::NO_SCAN) }
     }
 
     #[inline(always)]
@@ -198,19 +667,45 @@ pub trait Allocator {
         self.alloc_slice_fill_with(src.len(), |index| src[index].clone())
     }
 
+    #[inline(always)]
+    fn alloc_slice_clone_with_flags<T>(
+        &mut self,
+        src: &[T],
+        flags: AllocFlags,
+    ) -> Gc<[T], Self::Alloc>
+    where
+        T: Clone + Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<[T]>,
+    {
+        self.alloc_slice_fill_with_flags_fn(src.len(), |index| src[index].clone(), flags)
+    }
+
     #[inline(always)]
     fn alloc_str(&mut self, src: &str) -> Gc<str, Self::Alloc>
+    where
+        Self::Alloc: Alloc<str>,
+    {
+        self.alloc_str_with_flags(src, AllocFlags::empty())
+    }
+
+    /// Strings never hold any GC handles, so unlike the other `_with_flags` methods, immortal
+    /// strings do not need to be registered with the allocator.
+    #[inline(always)]
+    fn alloc_str_with_flags(&mut self, src: &str, flags: AllocFlags) -> Gc<str, Self::Alloc>
     where
         Self::Alloc: Alloc<str>,
     {
         let layout = Layout::for_value(src.as_bytes());
+        let meta = RawMeta::slice::<u8>(src.len());
 
-        unsafe {
-            self.try_gc_alloc_init(DEFAULT_ALLOC_RETRY_LIMIT, layout, |ptr| {
-                ptr::copy_nonoverlapping(src.as_ptr(), ptr.as_ptr(), src.len());
-            })
-            .unwrap_or_else(|err| failed_allocation(err))
-        }
+        let flags = flags | AllocFlags::NO_SCAN;
+        let init = |ptr: NonNull<u8>| unsafe {
+            ptr::copy_nonoverlapping(src.as_ptr(), ptr.as_ptr(), src.len());
+        };
+
+        alloc_or_handle(self, layout, init, |this, init| unsafe {
+            try_alloc_init(this, Escalate::new(), layout, meta, init, flags)
+        })
     }
 
     #[inline(always)]
@@ -219,43 +714,90 @@ pub trait Allocator {
         F: FnMut(usize) -> T,
         Self::Alloc: Alloc<[T]>,
     {
-        self.try_alloc_slice_fill_with(len, f)
-            .unwrap_or_else(|err| failed_allocation(err))
+        alloc_slice(self, len, f, AllocFlags::empty())
     }
 
+    #[inline(always)]
+    fn alloc_slice_fill_with_flags_fn<T, F>(
+        &mut self,
+        len: usize,
+        f: F,
+        flags: AllocFlags,
+    ) -> Gc<[T], Self::Alloc>
+    where
+        F: FnMut(usize) -> T,
+        T: Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<[T]>,
+    {
+        let object = alloc_slice(self, len, f, flags);
+        register_immortal(self, &object, flags);
+        object
+    }
+
+    /// Attempt to allocate a slice where each element is produced by `f`, collecting and retrying
+    /// for as long as the heap is out of memory. See [`Allocator::try_alloc`].
     #[inline(always)]
     fn try_alloc_slice_fill_with<T, F>(
         &mut self,
         len: usize,
         f: F,
-    ) -> Result<Gc<[T], Self::Alloc>, Error>
+    ) -> Result<Gc<[T], Self::Alloc>, AllocError<F>>
     where
         F: FnMut(usize) -> T,
         Self::Alloc: Alloc<[T]>,
     {
-        self.try_gc_alloc_slice_fill_with(Some(0), len, f)
+        try_alloc_slice(self, None::<u32>, len, f, AllocFlags::empty())
+    }
+
+    #[inline(always)]
+    fn try_alloc_slice_fill_with_flags_fn<T, F>(
+        &mut self,
+        len: usize,
+        f: F,
+        flags: AllocFlags,
+    ) -> Result<Gc<[T], Self::Alloc>, AllocError<F>>
+    where
+        F: FnMut(usize) -> T,
+        T: Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<[T]>,
+    {
+        self.try_gc_alloc_slice_fill_with_flags_fn(None::<u32>, len, f, flags)
     }
 
+    /// Attempt to allocate a slice where each element is produced by `f`. The function is only
+    /// called once space has been allocated, so it is returned unused if allocation fails.
     #[inline(always)]
-    fn try_gc_alloc_slice_fill_with<T, F>(
+    fn try_gc_alloc_slice_fill_with<T, F, P>(
         &mut self,
-        retry_limit: Option<u32>,
+        policy: P,
         len: usize,
-        mut f: F,
-    ) -> Result<Gc<[T], Self::Alloc>, Error>
+        f: F,
+    ) -> Result<Gc<[T], Self::Alloc>, AllocError<F>>
     where
         F: FnMut(usize) -> T,
         Self::Alloc: Alloc<[T]>,
+        P: RetryPolicy,
     {
-        let layout = Layout::array::<T>(len).unwrap_or_else(|err| failed_allocation(err));
+        try_alloc_slice(self, policy, len, f, AllocFlags::empty())
+    }
 
-        unsafe {
-            self.try_gc_alloc_init(retry_limit, layout, |ptr| {
-                for index in 0..len {
-                    ptr::write(ptr.cast::<T>().as_ptr().add(index), f(index));
-                }
-            })
-        }
+    #[inline(always)]
+    fn try_gc_alloc_slice_fill_with_flags_fn<T, F, P>(
+        &mut self,
+        policy: P,
+        len: usize,
+        f: F,
+        flags: AllocFlags,
+    ) -> Result<Gc<[T], Self::Alloc>, AllocError<F>>
+    where
+        F: FnMut(usize) -> T,
+        T: Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<[T]>,
+        P: RetryPolicy,
+    {
+        let object = try_alloc_slice(self, policy, len, f, flags)?;
+        register_immortal(self, &object, flags);
+        Ok(object)
     }
 
     #[inline(always)]
@@ -267,6 +809,20 @@ pub trait Allocator {
         self.alloc_slice_fill_with(len, |_| value)
     }
 
+    #[inline(always)]
+    fn alloc_slice_fill_copy_with_flags<T>(
+        &mut self,
+        len: usize,
+        value: T,
+        flags: AllocFlags,
+    ) -> Gc<[T], Self::Alloc>
+    where
+        T: Copy + Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<[T]>,
+    {
+        self.alloc_slice_fill_with_flags_fn(len, |_| value, flags)
+    }
+
     #[inline(always)]
     fn alloc_slice_fill_clone<T>(&mut self, len: usize, value: &T) -> Gc<[T], Self::Alloc>
     where
@@ -276,6 +832,20 @@ pub trait Allocator {
         self.alloc_slice_fill_with(len, |_| value.clone())
     }
 
+    #[inline(always)]
+    fn alloc_slice_fill_clone_with_flags<T>(
+        &mut self,
+        len: usize,
+        value: &T,
+        flags: AllocFlags,
+    ) -> Gc<[T], Self::Alloc>
+    where
+        T: Clone + Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<[T]>,
+    {
+        self.alloc_slice_fill_with_flags_fn(len, |_| value.clone(), flags)
+    }
+
     #[inline(always)]
     fn alloc_slice_fill_iter<T, I>(&mut self, iter: I) -> Gc<[T], Self::Alloc>
     where
@@ -290,6 +860,27 @@ pub trait Allocator {
         })
     }
 
+    #[inline(always)]
+    fn alloc_slice_fill_iter_with_flags<T, I>(
+        &mut self,
+        iter: I,
+        flags: AllocFlags,
+    ) -> Gc<[T], Self::Alloc>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+        T: Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<[T]>,
+    {
+        let mut iter = iter.into_iter();
+
+        self.alloc_slice_fill_with_flags_fn(
+            iter.len(),
+            |_| iter.next().expect("Iterator supplied too few elements"),
+            flags,
+        )
+    }
+
     #[inline(always)]
     fn alloc_slice_fill_default<T>(&mut self, len: usize) -> Gc<[T], Self::Alloc>
     where
@@ -298,6 +889,19 @@ pub trait Allocator {
     {
         self.alloc_slice_fill_with(len, |_| T::default())
     }
+
+    #[inline(always)]
+    fn alloc_slice_fill_default_with_flags<T>(
+        &mut self,
+        len: usize,
+        flags: AllocFlags,
+    ) -> Gc<[T], Self::Alloc>
+    where
+        T: Default + Trace<Self::Alloc>,
+        Self::Alloc: TracingAllocator + Alloc<[T]>,
+    {
+        self.alloc_slice_fill_with_flags_fn(len, |_| T::default(), flags)
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -319,8 +923,529 @@ pub enum CollectionType {
     Custom(u64),
 }
 
+/// Register an object with [`TracingAllocator::register_immortal`] if it was allocated with
+/// [`AllocFlags::IMMORTAL`].
+///
+/// The allocation helpers below leave this to the `_with_flags` methods of [`Allocator`], since
+/// they are the only methods which can be given the flag and the only ones which can require the
+/// object to be traceable.
+#[inline(always)]
+fn register_immortal<A, T>(allocator: &mut A, object: &Gc<T, A::Alloc>, flags: AllocFlags)
+where
+    A: Allocator + ?Sized,
+    T: ?Sized + Trace<A::Alloc>,
+    A::Alloc: TracingAllocator + Alloc<T>,
+{
+    if flags.contains(AllocFlags::IMMORTAL) {
+        allocator.as_raw_allocator().register_immortal(object);
+    }
+}
+
+/// Allocate the value produced by `f`, handing each failure to [`Allocator::handle_alloc_failure`].
+#[inline(always)]
+fn alloc_value<A, F, T>(allocator: &mut A, f: F, flags: AllocFlags) -> Gc<T, A::Alloc>
+where
+    A: Allocator + ?Sized,
+    F: FnOnce() -> T,
+    A::Alloc: Alloc<T>,
+{
+    alloc_or_handle(allocator, Layout::new::<T>(), f, |this, f| {
+        try_alloc_value(this, Escalate::new(), f, flags)
+    })
+}
+
+#[inline(always)]
+fn try_alloc_value<A, F, T, P>(
+    allocator: &mut A,
+    policy: P,
+    f: F,
+    flags: AllocFlags,
+) -> Result<Gc<T, A::Alloc>, AllocError<F>>
+where
+    A: Allocator + ?Sized,
+    F: FnOnce() -> T,
+    A::Alloc: Alloc<T>,
+    P: RetryPolicy,
+{
+    let layout = Layout::new::<T>();
+
+    unsafe {
+        match alloc_uninit::<A, T, P>(allocator, policy, layout, RawMeta::THIN, safe_flags(flags)) {
+            Ok(handle) => Ok(init_alloc(allocator, handle, layout, |ptr| {
+                ptr::write(ptr.as_ptr() as *mut T, f())
+            })),
+            Err(err) => Err(AllocError::new(err, f)),
+        }
+    }
+}
+
+/// The async counterpart to [`alloc_value`].
+async fn alloc_value_async<A, T>(allocator: &mut A, val: T, flags: AllocFlags) -> Gc<T, A::Alloc>
+where
+    A: Allocator + ?Sized,
+    A::Alloc: Alloc<T>,
+{
+    let mut f = || val;
+
+    loop {
+        let err = match try_alloc_value_async(allocator, Escalate::new(), f, flags).await {
+            Ok(object) => return object,
+            Err(err) => err,
+        };
+
+        let (error, value) = err.into_parts();
+        match allocator.handle_alloc_failure(Layout::new::<T>(), &error) {
+            OomAction::Retry => f = value,
+            OomAction::Fail => failed_allocation(error),
+        }
+    }
+}
+
+/// The async counterpart to [`try_alloc_value`].
+async fn try_alloc_value_async<A, F, T, P>(
+    allocator: &mut A,
+    policy: P,
+    f: F,
+    flags: AllocFlags,
+) -> Result<Gc<T, A::Alloc>, AllocError<F>>
+where
+    A: Allocator + ?Sized,
+    F: FnOnce() -> T,
+    A::Alloc: Alloc<T>,
+    P: RetryPolicy,
+{
+    let layout = Layout::new::<T>();
+
+    unsafe {
+        match alloc_uninit_async::<A, T, P>(
+            allocator,
+            policy,
+            layout,
+            RawMeta::THIN,
+            safe_flags(flags),
+        )
+        .await
+        {
+            Ok(handle) => Ok(init_alloc(allocator, handle, layout, |ptr| {
+                ptr::write(ptr.as_ptr() as *mut T, f())
+            })),
+            Err(err) => Err(AllocError::new(err, f)),
+        }
+    }
+}
+
+/// # Safety
+/// See [`Allocator::try_gc_alloc_init_unsized_with_flags`].
+#[inline(always)]
+unsafe fn try_alloc_init<A, F, T, P>(
+    allocator: &mut A,
+    policy: P,
+    layout: Layout,
+    meta: RawMeta,
+    init: F,
+    flags: AllocFlags,
+) -> Result<Gc<T, A::Alloc>, AllocError<F>>
+where
+    A: Allocator + ?Sized,
+    T: ?Sized + Finalize,
+    F: FnOnce(NonNull<u8>),
+    A::Alloc: Alloc<T>,
+    P: RetryPolicy,
+{
+    match alloc_uninit::<A, T, P>(allocator, policy, layout, meta, flags) {
+        Ok(handle) => Ok(init_alloc(allocator, handle, layout, init)),
+        Err(err) => Err(AllocError::new(err, init)),
+    }
+}
+
+/// The async counterpart to [`try_alloc_init`].
+///
+/// # Safety
+/// See [`Allocator::try_gc_alloc_init_with_flags`].
+async unsafe fn try_alloc_init_async<A, F, T, P>(
+    allocator: &mut A,
+    policy: P,
+    layout: Layout,
+    init: F,
+    flags: AllocFlags,
+) -> Result<Gc<T, A::Alloc>, AllocError<F>>
+where
+    A: Allocator + ?Sized,
+    T: ?Sized + Finalize,
+    F: FnOnce(NonNull<u8>),
+    A::Alloc: Alloc<T>,
+    P: RetryPolicy,
+{
+    match alloc_uninit_async::<A, T, P>(allocator, policy, layout, RawMeta::THIN, flags).await {
+        Ok(handle) => Ok(init_alloc(allocator, handle, layout, init)),
+        Err(err) => Err(AllocError::new(err, init)),
+    }
+}
+
+#[inline(always)]
+fn try_alloc_setup<A, F, T, P>(
+    allocator: &mut A,
+    policy: P,
+    init: F,
+    flags: AllocFlags,
+) -> Result<Gc<T, A::Alloc>, AllocError<F>>
+where
+    A: Allocator + ?Sized,
+    T: Default,
+    F: FnOnce(&mut T),
+    A::Alloc: Alloc<T>,
+    P: RetryPolicy,
+{
+    let layout = Layout::new::<T>();
+
+    unsafe {
+        match alloc_uninit::<A, T, P>(allocator, policy, layout, RawMeta::THIN, safe_flags(flags)) {
+            Ok(handle) => Ok(init_alloc(allocator, handle, layout, |ptr| {
+                // Hopefully the compiler will understand that this call can be optimized away
+                ptr::write(ptr.cast::<T>().as_ptr(), T::default());
+
+                let guard = SliceInitGuard {
+                    ptr: ptr.cast::<T>(),
+                    len: 1,
+                };
+                init(&mut *ptr.cast::<T>().as_ptr());
+                mem::forget(guard);
+            })),
+            Err(err) => Err(AllocError::new(err, init)),
+        }
+    }
+}
+
+/// Allocate a copy of `src`, handing each failure to [`Allocator::handle_alloc_failure`].
+///
+/// # Safety
+/// If `flags` contains [`AllocFlags::NO_SCAN`], `T` must not hold any GC handles.
+#[inline(always)]
+unsafe fn alloc_copied_slice<A, T>(
+    allocator: &mut A,
+    src: &[T],
+    flags: AllocFlags,
+) -> Gc<[T], A::Alloc>
+where
+    A: Allocator + ?Sized,
+    T: Copy,
+    A::Alloc: Alloc<[T]>,
+{
+    let layout = Layout::for_value(src);
+    let meta = RawMeta::slice::<T>(src.len());
+
+    let init = |ptr: NonNull<u8>| {
+        ptr::copy_nonoverlapping(src.as_ptr(), ptr.as_ptr() as *mut T, src.len());
+    };
+
+    alloc_or_handle(allocator, layout, init, |this, init| {
+        try_alloc_init(this, Escalate::new(), layout, meta, init, flags)
+    })
+}
+
+/// Allocate a slice where each element is produced by `f`, handing each failure to
+/// [`Allocator::handle_alloc_failure`].
+#[inline(always)]
+fn alloc_slice<A, T, F>(allocator: &mut A, len: usize, f: F, flags: AllocFlags) -> Gc<[T], A::Alloc>
+where
+    A: Allocator + ?Sized,
+    F: FnMut(usize) -> T,
+    A::Alloc: Alloc<[T]>,
+{
+    let layout = Layout::array::<T>(len)
+        .unwrap_or_else(|_| failed_allocation(Error::from(AllocationTooLarge)));
+
+    alloc_or_handle(allocator, layout, f, |this, f| {
+        try_alloc_slice(this, Escalate::new(), len, f, flags)
+    })
+}
+
+#[inline(always)]
+fn try_alloc_slice<A, T, F, P>(
+    allocator: &mut A,
+    policy: P,
+    len: usize,
+    mut f: F,
+    flags: AllocFlags,
+) -> Result<Gc<[T], A::Alloc>, AllocError<F>>
+where
+    A: Allocator + ?Sized,
+    F: FnMut(usize) -> T,
+    A::Alloc: Alloc<[T]>,
+    P: RetryPolicy,
+{
+    let layout = match Layout::array::<T>(len) {
+        Ok(layout) => layout,
+        Err(_) => return Err(AllocError::new(Error::from(AllocationTooLarge), f)),
+    };
+    let meta = RawMeta::slice::<T>(len);
+
+    unsafe {
+        let handle =
+            match alloc_uninit::<A, [T], P>(allocator, policy, layout, meta, safe_flags(flags)) {
+                Ok(handle) => handle,
+                Err(err) => return Err(AllocError::new(err, f)),
+            };
+
+        Ok(init_alloc(allocator, handle, layout, |ptr| {
+            // Drop any elements which were already written if f panics
+            let mut guard = SliceInitGuard {
+                ptr: ptr.cast::<T>(),
+                len: 0,
+            };
+
+            for index in 0..len {
+                ptr::write(guard.ptr.as_ptr().add(index), f(index));
+                guard.len += 1;
+            }
+
+            mem::forget(guard);
+        }))
+    }
+}
+
+/// Allocate space for an object, recovering from running out of memory as directed by the retry
+/// policy.
+///
+/// # Safety
+/// The layout and metadata must describe a valid `T`. The returned handle refers to uninitialized
+/// memory and must be initialized via [`init_alloc`].
+unsafe fn alloc_uninit<A, T, P>(
+    allocator: &mut A,
+    mut policy: P,
+    layout: Layout,
+    meta: RawMeta,
+    flags: AllocFlags,
+) -> Result<<A::Alloc as Alloc<T>>::RawHandle, Error>
+where
+    A: Allocator + ?Sized,
+    T: ?Sized,
+    A::Alloc: Alloc<T>,
+    P: RetryPolicy,
+{
+    allocator.charge_alloc(layout)?;
+
+    let mut attempt = 0;
+    let result = loop {
+        match Alloc::<T>::try_alloc_with_flags(allocator.as_raw_allocator(), layout, meta, flags) {
+            Ok(handle) => break Ok(handle),
+            Err(err) if err.kind() == OutOfMemory => {
+                match policy.on_failure(attempt, layout) {
+                    RetryAction::Collect(collection) => {
+                        allocator.request_gc(collection);
+                        allocator.yield_point();
+                    }
+                    RetryAction::Yield => allocator.yield_point(),
+                    RetryAction::BackOff(delay) => {
+                        // Sleeping outside of a blocking region would hold up any collection
+                        // requested in the meantime
+                        allocator.yield_point();
+                        allocator.blocking(|| thread::sleep(delay));
+                    }
+                    RetryAction::Grow(additional) => {
+                        allocator.try_grow(additional);
+                    }
+                    RetryAction::GiveUp => break Err(err),
+                }
+
+                attempt = attempt.saturating_add(1);
+            }
+            Err(err) => break Err(err),
+        };
+    };
+
+    if result.is_err() {
+        allocator.refund_alloc(layout);
+    }
+
+    result
+}
+
+/// The async counterpart to [`alloc_uninit`] which awaits each collection.
+///
+/// # Safety
+/// See [`alloc_uninit`].
+async unsafe fn alloc_uninit_async<A, T, P>(
+    allocator: &mut A,
+    mut policy: P,
+    layout: Layout,
+    meta: RawMeta,
+    flags: AllocFlags,
+) -> Result<<A::Alloc as Alloc<T>>::RawHandle, Error>
+where
+    A: Allocator + ?Sized,
+    T: ?Sized,
+    A::Alloc: Alloc<T>,
+    P: RetryPolicy,
+{
+    allocator.charge_alloc(layout)?;
+
+    let mut attempt = 0;
+    let result = loop {
+        // The attempt is resolved before awaiting so a raw handle is never held across an await
+        // point. Otherwise, the future would only be `Send` if the raw handle was as well.
+        let err = match Alloc::<T>::try_alloc_with_flags(
+            allocator.as_raw_allocator(),
+            layout,
+            meta,
+            flags,
+        ) {
+            Ok(handle) => break Ok(handle),
+            Err(err) if err.kind() == OutOfMemory => err,
+            Err(err) => break Err(err),
+        };
+
+        match policy.on_failure(attempt, layout) {
+            RetryAction::Collect(collection) => {
+                allocator.request_gc(collection);
+                allocator.yield_point_async().await;
+            }
+            RetryAction::Yield => allocator.yield_point_async().await,
+            RetryAction::BackOff(delay) => {
+                allocator.yield_point_async().await;
+                match allocator.sleep_async(delay) {
+                    Some(timer) => timer.await,
+                    None => break Err(err),
+                }
+            }
+            RetryAction::Grow(additional) => {
+                allocator.try_grow(additional);
+            }
+            RetryAction::GiveUp => break Err(err),
+        }
+
+        attempt = attempt.saturating_add(1);
+    };
+
+    if result.is_err() {
+        allocator.refund_alloc(layout);
+    }
+
+    result
+}
+
+/// Initialize a new allocation, register its drop glue and pass it to
+/// [`Allocator::record_alloc`]. If init panics, the partially initialized allocation is handed back
+/// to the allocator.
+///
+/// # Safety
+/// The handle must have been produced by [`alloc_uninit`] with the given layout and the init
+/// function must fully initialize the object.
+unsafe fn init_alloc<A, T, F>(
+    allocator: &mut A,
+    handle: <A::Alloc as Alloc<T>>::RawHandle,
+    layout: Layout,
+    init: F,
+) -> Gc<T, A::Alloc>
+where
+    A: Allocator + ?Sized,
+    T: ?Sized + Finalize,
+    F: FnOnce(NonNull<u8>),
+    A::Alloc: Alloc<T>,
+{
+    // The allocator remains borrowed by the guard until the object is fully initialized
+    let object = {
+        let alloc = allocator.as_raw_allocator();
+        let data_ptr = alloc.handle_ptr(&handle);
+        debug_assert!(
+            data_ptr.as_ptr() as usize & (layout.align() - 1) == 0,
+            "GC allocation did not meet required alignment"
+        );
+
+        let mut guard = AbandonGuard::<T, A::Alloc> {
+            alloc,
+            handle: Some(handle),
+            _phantom: PhantomData,
+        };
+
+        init(data_ptr);
+
+        let handle = guard.handle.take().unwrap();
+        if let Some(glue) = T::DROP_GLUE {
+            let meta = RawMeta::of(guard.alloc.handle_ref(&handle) as *const T);
+            guard
+                .alloc
+                .register_drop_glue(&handle, glue.with_meta(meta));
+        }
+
+        Gc::from_raw(handle)
+    };
+    allocator.record_alloc(layout, &object);
+    object
+}
+
+/// Exits the current scope once dropped, so the scope ends even if it is unwound by a panic.
+struct ScopeGuard<'a, A: Allocator + ?Sized> {
+    allocator: &'a mut A,
+}
+
+impl<A: Allocator + ?Sized> Drop for ScopeGuard<'_, A> {
+    fn drop(&mut self) {
+        unsafe { self.allocator.exit_scope() }
+    }
+}
+
+/// Returns an allocation to the allocator if initialization does not complete.
+struct AbandonGuard<'a, T: ?Sized, A: Alloc<T>> {
+    alloc: &'a mut A,
+    handle: Option<A::RawHandle>,
+    _phantom: PhantomData<*const T>,
+}
+
+impl<T: ?Sized, A: Alloc<T>> Drop for AbandonGuard<'_, T, A> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            unsafe { self.alloc.abandon_alloc(handle) }
+        }
+    }
+}
+
+/// Drops the initialized prefix of a slice if initialization does not complete.
+struct SliceInitGuard<T> {
+    ptr: NonNull<T>,
+    len: usize,
+}
+
+impl<T> Drop for SliceInitGuard<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.ptr.as_ptr(), self.len));
+        }
+    }
+}
+
+/// Objects allocated with [`AllocFlags::NO_SCAN`] may never be traced, so the flag can only be
+/// trusted for types known to implement [`NoTrace`]. Safe allocation methods strip it from any
+/// flags they are given.
+#[inline(always)]
+fn safe_flags(flags: AllocFlags) -> AllocFlags {
+    flags - AllocFlags::NO_SCAN
+}
+
 #[cold]
 #[inline(never)]
-fn failed_allocation<T: Debug>(err: T) -> ! {
+fn failed_allocation(err: Error) -> ! {
     panic!("Failed to perform GC allocation: {:?}", err)
 }
+
+/// Repeat an allocation until it succeeds, handing each failure to
+/// [`Allocator::handle_alloc_failure`]. `value` is the value (or init function) being allocated
+/// which is handed back by each failed attempt.
+#[inline(always)]
+fn alloc_or_handle<A, V, R, F>(allocator: &mut A, layout: Layout, mut value: V, mut attempt: F) -> R
+where
+    A: Allocator + ?Sized,
+    F: FnMut(&mut A, V) -> Result<R, AllocError<V>>,
+{
+    loop {
+        let (error, returned) = match attempt(allocator, value) {
+            Ok(object) => return object,
+            Err(err) => err.into_parts(),
+        };
+
+        match allocator.handle_alloc_failure(layout, &error) {
+            OomAction::Retry => value = returned,
+            OomAction::Fail => failed_allocation(error),
+        }
+    }
+}
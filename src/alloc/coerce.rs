@@ -0,0 +1,101 @@
+//! Support for unsized handles such as `Gc<dyn Trait>` and `Gc<[T]>`.
+//!
+//! A reference to an unsized type is made up of a data pointer and some pointer metadata (the
+//! length of a slice or the vtable of a trait object). Since a raw handle only identifies the
+//! object, handles to unsized types must also carry this metadata so a full reference can be
+//! rebuilt when the object is accessed. [`RawMeta`] provides a type erased container for this
+//! metadata so raw handles can keep the same type no matter what they point to.
+
+use crate::alloc::Alloc;
+use std::mem::{size_of, transmute_copy};
+use std::ptr::{self, null, NonNull};
+
+/// Type erased pointer metadata (Ex: the length of a slice or the vtable of a trait object). Thin
+/// pointers do not have any metadata and use [`RawMeta::THIN`].
+///
+/// The layout of wide pointers is unspecified, so the metadata is kept as a copy of the original
+/// pointer with its address cleared instead of being split out of the pointer.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct RawMeta([*const (); 2]);
+
+// The metadata is never dereferenced on its own, so it is safe to share between threads.
+unsafe impl Send for RawMeta {}
+unsafe impl Sync for RawMeta {}
+
+impl RawMeta {
+    pub const THIN: RawMeta = RawMeta([null(); 2]);
+
+    /// Get the metadata for a pointer. The address of the pointer is not retained.
+    pub fn of<T: ?Sized>(ptr: *const T) -> Self {
+        const {
+            assert!(
+                size_of::<*const T>() <= size_of::<RawMeta>(),
+                "Unsupported pointer metadata"
+            )
+        };
+
+        let mut meta = RawMeta::THIN;
+        unsafe { ptr::write_unaligned(meta.0.as_mut_ptr().cast::<*const T>(), ptr.with_addr(0)) };
+        meta
+    }
+
+    /// Get the metadata for a slice with the given length. This can also be used for `str` since
+    /// it shares the same metadata as `[u8]`.
+    pub fn slice<T>(len: usize) -> Self {
+        RawMeta::of(ptr::slice_from_raw_parts(null::<T>(), len))
+    }
+
+    /// Rebuild a pointer to `T` using this metadata and the given address. The returned pointer
+    /// keeps the provenance of `addr`.
+    ///
+    /// # Safety
+    /// This metadata must have been produced from a pointer to `T`.
+    pub unsafe fn with_addr<T: ?Sized>(self, addr: NonNull<u8>) -> NonNull<T> {
+        if size_of::<*const T>() == size_of::<*const ()>() {
+            return transmute_copy(&addr);
+        }
+
+        // `<*const T>::with_addr` would give the pointer the provenance of the stored pointer, so
+        // the address is written over the word which holds it instead. That word is found by
+        // comparing the stored pointer against a copy with a different address.
+        let ptr = ptr::read_unaligned(self.0.as_ptr().cast::<*const T>());
+        let mut probe = RawMeta::THIN;
+        ptr::write_unaligned(
+            probe.0.as_mut_ptr().cast::<*const T>(),
+            ptr.with_addr(usize::MAX),
+        );
+
+        let mut words = self.0;
+        let index = (0..words.len())
+            .find(|&index| words[index].addr() != probe.0[index].addr())
+            .expect("Pointer address is not stored in a single word");
+        words[index] = addr.as_ptr().cast_const().cast();
+
+        let rebuilt = ptr::read_unaligned(words.as_ptr().cast::<*const T>());
+        debug_assert_eq!(rebuilt.cast::<u8>(), addr.as_ptr().cast_const());
+        NonNull::new_unchecked(rebuilt.cast_mut())
+    }
+}
+
+impl Default for RawMeta {
+    fn default() -> Self {
+        RawMeta::THIN
+    }
+}
+
+/// A capability trait for allocators which are able to convert a handle of `T` into a handle of
+/// `U` where `U` is an unsized view of the same object (Ex: `T` to `dyn Trait` or `[T; N]` to
+/// `[T]`). See [`Gc::coerce`](crate::Gc::coerce).
+///
+/// # Safety
+/// The produced handle must refer to the same object as the original handle.
+pub unsafe trait CoerceHandle<T: ?Sized, U: ?Sized>: Alloc<T> + Alloc<U> {
+    /// Convert a handle using the pointer metadata for `U`.
+    ///
+    /// # Safety
+    /// `meta` must be valid metadata for a pointer to `U` referring to the object behind `handle`.
+    unsafe fn coerce_handle(
+        handle: <Self as Alloc<T>>::RawHandle,
+        meta: RawMeta,
+    ) -> <Self as Alloc<U>>::RawHandle;
+}
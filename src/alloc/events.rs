@@ -0,0 +1,111 @@
+//! Callbacks for observing the lifecycle of garbage collection.
+//!
+//! Observers are notified synchronously by the thread performing the collection while every other
+//! mutator is stopped, so they should avoid doing any heavy work. Observers must not touch the heap
+//! they are observing: allocating, accessing objects, querying the heap, requesting a collection,
+//! reaching a yield point or registering observers and allocators may deadlock or interfere with
+//! the collection. Everything an observer is told about a collection is carried by its events
+//! (Ex: [`CollectionReport`]), so work which needs the heap should be deferred until the mutators
+//! have resumed.
+
+use crate::alloc::CollectionType;
+use std::fmt::{self, Debug, Formatter};
+use std::time::Duration;
+
+/// An event emitted by a GC during a collection.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GcEvent {
+    /// A collection is about to start. No objects have been marked or moved yet.
+    CollectionStart(CollectionType),
+    /// All reachable objects have been marked, but no objects have been freed or moved yet.
+    MarkComplete,
+    /// A collection has finished. If the GC moves objects, they will be in their new locations.
+    CollectionEnd(CollectionReport),
+    /// The capacity of the heap has changed.
+    HeapResized {
+        old_capacity: usize,
+        new_capacity: usize,
+    },
+}
+
+/// A summary of a single collection.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CollectionReport {
+    pub collection: CollectionType,
+    /// The number of objects which survived the collection.
+    pub live_objects: usize,
+    /// The number of bytes in use after the collection.
+    pub bytes_used: usize,
+    /// The number of bytes reclaimed by the collection.
+    pub bytes_reclaimed: usize,
+    /// The time spent performing the collection.
+    pub pause_time: Duration,
+}
+
+/// A handle to a registered observer which can be used to remove it later.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ObserverId(u64);
+
+/// Heaps and allocators which can notify observers of [`GcEvent`]s.
+pub trait ObserveGc {
+    /// Register a callback to be run whenever an event is emitted. The callback must not touch the
+    /// heap (See [`crate::alloc::events`]).
+    fn add_observer<F>(&mut self, observer: F) -> ObserverId
+    where
+        F: FnMut(&GcEvent) + Send + 'static;
+
+    /// Remove a previously registered observer. Returns `false` if the observer had already been
+    /// removed.
+    fn remove_observer(&mut self, id: ObserverId) -> bool;
+}
+
+type BoxedObserver = Box<dyn FnMut(&GcEvent) + Send>;
+
+/// A simple registry of observers which can be used to implement [`ObserveGc`].
+#[derive(Default)]
+pub struct GcObservers {
+    observers: Vec<(ObserverId, BoxedObserver)>,
+    next_id: u64,
+}
+
+impl GcObservers {
+    pub fn new() -> Self {
+        GcObservers::default()
+    }
+
+    pub fn add<F>(&mut self, observer: F) -> ObserverId
+    where
+        F: FnMut(&GcEvent) + Send + 'static,
+    {
+        let id = ObserverId(self.next_id);
+        self.next_id += 1;
+        self.observers.push((id, Box::new(observer)));
+        id
+    }
+
+    pub fn remove(&mut self, id: ObserverId) -> bool {
+        let len = self.observers.len();
+        self.observers.retain(|(x, _)| *x != id);
+        self.observers.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+
+    /// Notify all observers of an event in the order they were registered. This should not be
+    /// called while holding a lock which an observer may need (Ex: the lock guarding the heap).
+    pub fn emit(&mut self, event: &GcEvent) {
+        for (_, observer) in &mut self.observers {
+            observer(event);
+        }
+    }
+}
+
+impl Debug for GcObservers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GcObservers")
+            .field("len", &self.observers.len())
+            .finish()
+    }
+}
@@ -0,0 +1,93 @@
+//! Finalization of collected objects.
+//!
+//! When an object is collected, any resources it owns (Ex: the buffer of a `Vec<T>` or `String`)
+//! must still be released by running its drop glue. Since a garbage collector only sees objects as
+//! untyped bytes by the time they are collected, the drop glue for each allocation is recorded up
+//! front through [`Alloc::register_drop_glue`](crate::alloc::Alloc::register_drop_glue). This is
+//! only done for types where [`std::mem::needs_drop`] returns `true`, so plain data does not pay
+//! for finalization.
+
+use crate::alloc::RawMeta;
+use std::mem::needs_drop;
+use std::ptr::{self, NonNull};
+
+/// Type erased drop glue for an object in the heap.
+#[derive(Copy, Clone)]
+pub struct DropGlue {
+    drop_fn: unsafe fn(NonNull<u8>, RawMeta),
+    meta: RawMeta,
+}
+
+impl DropGlue {
+    /// Get the drop glue for `T` or `None` if `T` does not need to be dropped.
+    pub const fn of<T>() -> Option<Self> {
+        if needs_drop::<T>() {
+            Some(DropGlue {
+                drop_fn: drop_sized::<T>,
+                meta: RawMeta::THIN,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Get the drop glue for `[T]` or `None` if `T` does not need to be dropped.
+    ///
+    /// The number of elements is read from the pointer metadata of the object, so the glue must be
+    /// given the metadata of the allocation through [`DropGlue::with_meta`] before it is run.
+    pub const fn of_slice<T>() -> Option<Self> {
+        if needs_drop::<T>() {
+            Some(DropGlue {
+                drop_fn: drop_slice::<T>,
+                meta: RawMeta::THIN,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Attach the pointer metadata of the object this glue will be run on.
+    pub fn with_meta(self, meta: RawMeta) -> Self {
+        DropGlue { meta, ..self }
+    }
+
+    /// Run the drop glue for an object.
+    ///
+    /// # Safety
+    /// The pointer must refer to a fully initialized object of the type this glue was created for
+    /// and the glue must hold the pointer metadata of that object. The object must not be used
+    /// again after it has been dropped.
+    #[inline(always)]
+    pub unsafe fn run(&self, ptr: NonNull<u8>) {
+        (self.drop_fn)(ptr, self.meta)
+    }
+}
+
+unsafe fn drop_sized<T>(ptr: NonNull<u8>, _meta: RawMeta) {
+    ptr::drop_in_place(ptr.cast::<T>().as_ptr())
+}
+
+unsafe fn drop_slice<T>(ptr: NonNull<u8>, meta: RawMeta) {
+    ptr::drop_in_place(meta.with_addr::<[T]>(ptr).as_ptr())
+}
+
+/// Types for which drop glue can be recorded at the time of allocation. This is implemented for
+/// all `Sized` types, slices, and `str`.
+///
+/// # Safety
+/// `DROP_GLUE` must be safe to run on any fully initialized value of `Self`.
+pub unsafe trait Finalize {
+    const DROP_GLUE: Option<DropGlue>;
+}
+
+unsafe impl<T> Finalize for T {
+    const DROP_GLUE: Option<DropGlue> = DropGlue::of::<T>();
+}
+
+unsafe impl<T> Finalize for [T] {
+    const DROP_GLUE: Option<DropGlue> = DropGlue::of_slice::<T>();
+}
+
+unsafe impl Finalize for str {
+    const DROP_GLUE: Option<DropGlue> = None;
+}
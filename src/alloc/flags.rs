@@ -0,0 +1,167 @@
+//! Flags which change how an object is allocated.
+//!
+//! Flags fall into two groups. Requirements (such as [`AllocFlags::PINNED`]) change the semantics
+//! of an allocation and must either be honored or cause the allocation to fail with
+//! [`ErrorKind::UnsupportedFlags`](crate::error::ErrorKind::UnsupportedFlags). Hints (such as
+//! [`AllocFlags::LONG_LIVED`]) only provide extra information to the GC and may be silently
+//! ignored. Use [`Alloc::supported_flags`](crate::alloc::Alloc::supported_flags) to check which
+//! flags an allocator is able to honor.
+//!
+//! [`AllocFlags::NO_SCAN`] is neither. An allocator may always ignore it, but honoring it for an
+//! object which holds GC handles leaves those handles untraced. It is a promise made by the caller,
+//! so it is only accepted by the unsafe allocation methods.
+
+use std::fmt::{self, Debug, Formatter};
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Sub, SubAssign};
+
+#[derive(Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct AllocFlags(u32);
+
+impl AllocFlags {
+    /// The object will never be moved by the GC.
+    pub const PINNED: AllocFlags = AllocFlags(1 << 0);
+    /// Hint that the object is large and should be kept separate from regular objects.
+    pub const LARGE: AllocFlags = AllocFlags(1 << 1);
+    /// The object will never be collected. The handles it holds are kept alive for as long as the
+    /// heap exists (See
+    /// [`TracingAllocator::register_immortal`](crate::trace::TracingAllocator::register_immortal)).
+    pub const IMMORTAL: AllocFlags = AllocFlags(1 << 2);
+    /// The object does not contain any GC handles, so it does not need to be traced. Allocators may
+    /// ignore this flag, but one which honors it will never trace the object. Allocating an object
+    /// which holds GC handles with this flag is undefined behavior, so safe allocation methods only
+    /// pass it along for types implementing [`NoTrace`](crate::trace::NoTrace).
+    pub const NO_SCAN: AllocFlags = AllocFlags(1 << 3);
+    /// The memory of the object will be zeroed before it is initialized.
+    pub const ZEROED: AllocFlags = AllocFlags(1 << 4);
+    /// Hint that the object is expected to survive for a long time.
+    pub const LONG_LIVED: AllocFlags = AllocFlags(1 << 5);
+    /// The object must outlive the current [`Allocator::scope`](crate::alloc::Allocator::scope)
+    /// instead of being freed along with the scoped region.
+    pub const ESCAPE: AllocFlags = AllocFlags(1 << 6);
+
+    /// Flags which may be ignored by an allocator without changing the behavior of a program.
+    pub const HINTS: AllocFlags = AllocFlags(Self::LARGE.0 | Self::LONG_LIVED.0);
+
+    const NAMES: [(AllocFlags, &'static str); 7] = [
+        (Self::PINNED, "PINNED"),
+        (Self::LARGE, "LARGE"),
+        (Self::IMMORTAL, "IMMORTAL"),
+        (Self::NO_SCAN, "NO_SCAN"),
+        (Self::ZEROED, "ZEROED"),
+        (Self::LONG_LIVED, "LONG_LIVED"),
+        (Self::ESCAPE, "ESCAPE"),
+    ];
+
+    #[inline(always)]
+    pub const fn empty() -> Self {
+        AllocFlags(0)
+    }
+
+    #[inline(always)]
+    pub const fn all() -> Self {
+        AllocFlags((1 << 7) - 1)
+    }
+
+    #[inline(always)]
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Create a set of flags from raw bits, discarding any bits which do not correspond to a flag.
+    #[inline(always)]
+    pub const fn from_bits_truncate(bits: u32) -> Self {
+        AllocFlags(bits & Self::all().0)
+    }
+
+    #[inline(always)]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    #[inline(always)]
+    pub const fn contains(self, other: AllocFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[inline(always)]
+    pub const fn intersects(self, other: AllocFlags) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    #[inline(always)]
+    pub const fn union(self, other: AllocFlags) -> Self {
+        AllocFlags(self.0 | other.0)
+    }
+
+    #[inline(always)]
+    pub const fn difference(self, other: AllocFlags) -> Self {
+        AllocFlags(self.0 & !other.0)
+    }
+
+    /// The flags which must be honored by an allocator. This excludes hints and
+    /// [`AllocFlags::NO_SCAN`], since tracing an object is always allowed.
+    #[inline(always)]
+    pub const fn requirements(self) -> Self {
+        self.difference(Self::HINTS).difference(Self::NO_SCAN)
+    }
+}
+
+impl Debug for AllocFlags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "AllocFlags(empty)");
+        }
+
+        write!(f, "AllocFlags(")?;
+        let mut names = Self::NAMES.iter().filter(|(flag, _)| self.contains(*flag));
+        if let Some((_, name)) = names.next() {
+            write!(f, "{}", name)?;
+        }
+        for (_, name) in names {
+            write!(f, " | {}", name)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl BitOr for AllocFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.union(rhs)
+    }
+}
+
+impl BitOrAssign for AllocFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = self.union(rhs);
+    }
+}
+
+impl BitAnd for AllocFlags {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        AllocFlags(self.0 & rhs.0)
+    }
+}
+
+impl BitAndAssign for AllocFlags {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl Sub for AllocFlags {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.difference(rhs)
+    }
+}
+
+impl SubAssign for AllocFlags {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = self.difference(rhs);
+    }
+}
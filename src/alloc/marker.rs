@@ -1,7 +1,18 @@
 //! Marker traits which effect the functionality allowed on a GcHandle.
 
+use std::hash::Hash;
+
 /// Can a type be safely transmuted without the help of the allocator?
 pub trait BlindTransmute {}
 
 /// Can a handle be upgraded from a [Gc] to a [GcMut]?
 pub trait UpgradeHandle {}
+
+/// Raw handles whose `Eq` and `Hash` implementations reflect the identity of the object they refer
+/// to. Two handles must compare equal if and only if they refer to the same object, and the hash of
+/// a handle must remain stable for as long as the object is alive. For a moving GC, this means the
+/// hash can not be derived from the current address of the object.
+///
+/// Implementing this trait enables [`Gc::ptr_eq`](crate::Gc::ptr_eq) as well as `Eq` and `Hash` for
+/// [`Gc`](crate::Gc) and [`GcWeak`](crate::GcWeak).
+pub trait IdentityHandle: Eq + Hash {}
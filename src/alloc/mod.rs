@@ -1,14 +1,39 @@
-use crate::error::Error;
+use crate::error::{Error, ErrorKind};
 use std::alloc::Layout;
-use std::ptr::NonNull;
+use std::mem::size_of;
+use std::ptr::{self, NonNull};
 
 pub mod access;
 pub mod api;
+pub mod coerce;
+pub mod events;
+pub mod finalize;
+pub mod flags;
 pub mod marker;
+pub mod oom;
+pub mod profile;
+pub mod quota;
+pub mod retry;
+pub mod stats;
+pub mod tagged;
 
 pub use access::*;
 pub use api::*;
+pub use coerce::*;
+pub use events::*;
+pub use finalize::*;
+pub use flags::*;
 pub use marker::*;
+pub use oom::*;
+pub use retry::*;
+pub use stats::*;
+pub use tagged::*;
+
+/// Checks if an object is still alive without knowing its type. See [`Alloc::liveness_check`].
+///
+/// Checks are only ever called with the allocator which created them, but may be moved or shared
+/// between threads along with it.
+pub type LivenessCheck<H> = Box<dyn Fn(&H) -> Option<bool> + Send + Sync>;
 
 /// A marker trait which can be used to indicate a type can be allocated by an allocator.
 pub trait Alloc<T: ?Sized>: Sized {
@@ -20,12 +45,10 @@ pub trait Alloc<T: ?Sized>: Sized {
     ///
     /// For where `T: Sized`, it can be assumed `MutTy: From<T>`. This bound is not included since
     /// this trait covers DSTs too and it would complicate the process for this to be required.
-    type MutTy;
+    type MutTy: ?Sized;
 
     type RawHandle: Sized;
 
-    type Flags;
-
     /// Performs allocation for the specified type
     ///
     /// # Safety
@@ -33,6 +56,64 @@ pub trait Alloc<T: ?Sized>: Sized {
     /// `Sized` and DST `T`s.
     unsafe fn try_alloc_layout(&mut self, layout: Layout) -> Result<Self::RawHandle, Error>;
 
+    /// Performs allocation for a DST (Ex: `[T]` or `str`). The pointer metadata (such as the length
+    /// of a slice) must be retained by either the handle or the object so a reference to the full
+    /// object can be rebuilt by [`Alloc::handle_ref`].
+    ///
+    /// By default, this falls back to [`Alloc::try_alloc_layout`] for `Sized` types and returns an
+    /// error for everything else.
+    ///
+    /// # Safety
+    /// The given layout must be the layout of a `T` with the given pointer metadata.
+    unsafe fn try_alloc_unsized(
+        &mut self,
+        layout: Layout,
+        _meta: RawMeta,
+    ) -> Result<Self::RawHandle, Error> {
+        if size_of::<*const T>() != size_of::<*const ()>() {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "Allocator does not support unsized types",
+            ));
+        }
+
+        self.try_alloc_layout(layout)
+    }
+
+    /// The set of [`AllocFlags`] this allocator is able to honor. By default, this includes all hints
+    /// along with [`AllocFlags::ZEROED`] since it can be handled by
+    /// [`Alloc::try_alloc_with_flags`], and [`AllocFlags::ESCAPE`] since allocators which do not
+    /// support scoped regions never free objects early.
+    fn supported_flags(&self) -> AllocFlags {
+        AllocFlags::HINTS | AllocFlags::ZEROED | AllocFlags::ESCAPE
+    }
+
+    /// Performs allocation for the specified type with the given flags. If any of the
+    /// [requirements](AllocFlags::requirements) are not supported, an
+    /// [`ErrorKind::UnsupportedFlags`] error is returned.
+    ///
+    /// By default, this falls back to [`Alloc::try_alloc_unsized`] and zeroes the object if
+    /// requested.
+    ///
+    /// # Safety
+    /// The given layout must be the layout of a `T` with the given pointer metadata. If `flags`
+    /// contains [`AllocFlags::NO_SCAN`], the object must not hold any GC handles.
+    unsafe fn try_alloc_with_flags(
+        &mut self,
+        layout: Layout,
+        meta: RawMeta,
+        flags: AllocFlags,
+    ) -> Result<Self::RawHandle, Error> {
+        check_flags(self.supported_flags(), flags)?;
+
+        let handle = self.try_alloc_unsized(layout, meta)?;
+        if flags.contains(AllocFlags::ZEROED) {
+            ptr::write_bytes(self.handle_ptr(&handle).as_ptr(), 0, layout.size());
+        }
+
+        Ok(handle)
+    }
+
     /// Retrieves a pointer to the memory on the heap for a given handle
     ///
     /// # Safety
@@ -49,6 +130,64 @@ pub trait Alloc<T: ?Sized>: Sized {
     /// # Safety
     /// This function can only be used when exclusive access is held.
     unsafe fn handle_ref(&self, handle: &Self::RawHandle) -> &T;
+
+    /// Check if the object behind a handle is still alive. This is the raw equivalent of
+    /// [`Accessor::is_alive`] and may be used by tracers which need to inspect handles that may have
+    /// outlived their object (Ex: the keys of a weak map). Not all garbage collectors will support
+    /// performing this check.
+    fn handle_is_alive(&self, _handle: &Self::RawHandle) -> Option<bool> {
+        None
+    }
+
+    /// Create a check which can later tell if the object behind a handle is still alive, as
+    /// [`Alloc::handle_is_alive`] would. The check does not borrow the handle or depend on `T`, so
+    /// it can be kept after the handle is gone (Ex: by a
+    /// [`Profiler`](crate::alloc::profile::Profiler)). Returns `None` if the garbage collector does
+    /// not support this.
+    fn liveness_check(&self, _handle: &Self::RawHandle) -> Option<LivenessCheck<Self>> {
+        None
+    }
+
+    /// Record the drop glue which should be run once the object behind this handle has been
+    /// collected. This is only called after the object has been fully initialized and only for
+    /// types which need to be dropped. The glue already holds the pointer metadata of the object,
+    /// so it only needs the address of the object to be run.
+    ///
+    /// Garbage collectors which do not support finalization may leave this as a no-op at the cost
+    /// of leaking any resources owned by collected objects.
+    ///
+    /// # Safety
+    /// The handle must refer to a live object which was allocated as the type the glue was created
+    /// for.
+    unsafe fn register_drop_glue(&mut self, _handle: &Self::RawHandle, _glue: DropGlue) {}
+
+    /// Give up on an allocation whose initialization was interrupted by a panic. The object may be
+    /// partially initialized, so it must never be traced, finalized, or otherwise read. No drop glue
+    /// will have been registered for the handle and the handle will not be used again.
+    ///
+    /// By default this is a no-op and the allocation is left to be reclaimed like any other
+    /// unreachable object. Garbage collectors which may read objects without going through a handle
+    /// (Ex: while walking the heap) should override this to mark the allocation as dead.
+    ///
+    /// # Safety
+    /// The handle must have been returned by this allocator and must not have been handed out to the
+    /// user.
+    unsafe fn abandon_alloc(&mut self, _handle: Self::RawHandle) {}
+}
+
+/// Check that all of the requirements within `flags` are supported. Hints and
+/// [`AllocFlags::NO_SCAN`] are always accepted.
+pub fn check_flags(supported: AllocFlags, flags: AllocFlags) -> Result<(), Error> {
+    let unsupported = flags.requirements() - supported;
+
+    if unsupported.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::UnsupportedFlags,
+            format!("Allocator does not support {:?}", unsupported),
+        ))
+    }
 }
 
 /// This trait is a helper that can be added to trait bounds. Writing `A: AllocMut<T>` is equivalent
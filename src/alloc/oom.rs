@@ -0,0 +1,107 @@
+//! Recovering from allocations which have run out of memory.
+//!
+//! Infallible allocation methods (Ex: [`Allocator::alloc`](crate::alloc::Allocator::alloc)) can not
+//! return an error, so once their [`RetryPolicy`](crate::alloc::RetryPolicy) gives up the failure is
+//! passed to [`Allocator::handle_alloc_failure`](crate::alloc::Allocator::handle_alloc_failure).
+//! Heaps which implement [`HandleOom`] forward it to an installed handler, which can either retry
+//! the allocation or let it fail. A failed allocation panics, but a handler may also diverge on its
+//! own (Ex: to raise a language level exception or dump the heap before aborting).
+//!
+//! To give a handler something to work with, a heap can hold back an [`EmergencyReserve`] which
+//! regular allocations can not use. Releasing the reserve from within the handler and retrying lets
+//! the allocation (or any cleanup work) succeed.
+
+use crate::error::Error;
+use std::alloc::Layout;
+
+/// The decision of an OOM handler.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OomAction {
+    /// Attempt the allocation again, starting over with its retry policy.
+    Retry,
+    /// Give up on the allocation and panic.
+    Fail,
+}
+
+/// Memory held back by a heap which regular allocations are not able to use.
+pub trait EmergencyReserve {
+    /// The number of bytes currently held in reserve.
+    fn reserved(&self) -> usize;
+
+    /// Make up to `bytes` of the reserve available to regular allocations. Returns the number of
+    /// bytes which were released.
+    fn release(&mut self, bytes: usize) -> usize;
+
+    /// Make the entire reserve available to regular allocations.
+    fn release_all(&mut self) -> usize {
+        self.release(usize::MAX)
+    }
+}
+
+/// An empty reserve for heaps which do not support one.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NoReserve;
+
+impl EmergencyReserve for NoReserve {
+    fn reserved(&self) -> usize {
+        0
+    }
+
+    fn release(&mut self, _: usize) -> usize {
+        0
+    }
+}
+
+/// Details about a failed allocation given to an OOM handler.
+pub struct OomContext<'a> {
+    layout: Layout,
+    error: &'a Error,
+    reserve: &'a mut dyn EmergencyReserve,
+}
+
+impl<'a> OomContext<'a> {
+    pub fn new(layout: Layout, error: &'a Error, reserve: &'a mut dyn EmergencyReserve) -> Self {
+        OomContext {
+            layout,
+            error,
+            reserve,
+        }
+    }
+
+    /// The layout of the allocation which failed.
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// The error returned by the final allocation attempt.
+    pub fn error(&self) -> &Error {
+        self.error
+    }
+
+    /// The emergency reserve of the heap.
+    pub fn reserve(&mut self) -> &mut dyn EmergencyReserve {
+        self.reserve
+    }
+}
+
+pub type OomHandler = Box<dyn FnMut(&mut OomContext<'_>) -> OomAction + Send>;
+
+/// Heaps and allocators which allow an OOM handler to be installed.
+pub trait HandleOom {
+    /// Install a handler which is called whenever an infallible allocation fails. This replaces any
+    /// handler which was previously installed. A handler which keeps returning
+    /// [`OomAction::Retry`] without freeing anything will retry forever. If the handler runs out of
+    /// memory itself, the nested failure is treated as [`OomAction::Fail`] instead of calling the
+    /// handler again.
+    fn set_oom_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&mut OomContext<'_>) -> OomAction + Send + 'static;
+
+    /// Remove the installed handler. Returns `false` if there was no handler installed.
+    fn remove_oom_handler(&mut self) -> bool;
+
+    /// Hold back `bytes` of the heap as an emergency reserve, replacing the current reserve. If there
+    /// is not enough free space, an [`ErrorKind::OutOfMemory`](crate::error::ErrorKind::OutOfMemory)
+    /// error is returned and the reserve is left unchanged.
+    fn set_emergency_reserve(&mut self, bytes: usize) -> Result<(), Error>;
+}
@@ -0,0 +1,489 @@
+//! Sampled allocation profiling.
+//!
+//! A [`Profiler`] wraps any [`Allocator`] and samples the allocations made through it (See
+//! [`Allocator::record_alloc`]). Every `sample_interval` bytes, the allocation which crosses the
+//! interval is recorded along with its [`Layout`], type name and a [`Backtrace`] of where it was
+//! allocated. Each sample holds on to a [`LivenessCheck`] for its object, so later collections can
+//! be observed. Liveness checks are [`Send`] and [`Sync`], so a profiler can be moved between
+//! threads whenever the allocator it wraps can.
+//!
+//! Samples are grouped by type and backtrace into a [`Profile`], which can be written out as text
+//! or as collapsed stacks for use with flamegraph tools. A sample stands in for every byte
+//! allocated since the previous sample, so byte counts in a profile are estimates unless the sample
+//! interval is `1`.
+//!
+//! Backtraces are captured regardless of `RUST_BACKTRACE`, but are only symbolized once a profile
+//! is built. Frames will only be named if debug info is available.
+
+use crate::alloc::{
+    Accessor, AccessorMut, Alloc, AllocMut, Allocator, CollectionType, GcEvent, GcStats, HandleOom,
+    HeapStats, LivenessCheck, ObserveGc, ObserverId, OomAction, OomContext,
+};
+use crate::error::Error;
+use crate::trace::roots::{GcRootStorage, RootStorage};
+use crate::Gc;
+use std::alloc::Layout;
+use std::backtrace::Backtrace;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter, Write};
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::time::Duration;
+
+/// A single sampled allocation.
+pub struct Sample<H> {
+    layout: Layout,
+    type_name: &'static str,
+    backtrace: Backtrace,
+    /// The number of allocated bytes this sample stands in for.
+    weight: usize,
+    is_alive: Option<LivenessCheck<H>>,
+}
+
+impl<H> Sample<H> {
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+
+    /// The estimated number of bytes allocated since the previous sample, including this one.
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    /// Check if the sampled object has not yet been collected. Returns `None` if the allocator is
+    /// unable to tell.
+    pub fn is_alive(&self, alloc: &H) -> Option<bool> {
+        self.is_alive.as_ref().and_then(|check| check(alloc))
+    }
+}
+
+/// An [`Allocator`] which samples allocations to find where the heap is being filled.
+///
+/// Every allocation method of [`Allocator`] is sampled. Allocations made directly through the raw
+/// allocator (See [`Allocator::as_raw_allocator`]) bypass the profiler.
+pub struct Profiler<A: Allocator> {
+    inner: A,
+    sample_interval: usize,
+    /// The number of bytes left to allocate before the next sample is taken.
+    until_sample: usize,
+    samples: Vec<Sample<A::Alloc>>,
+}
+
+impl<A: Allocator> Profiler<A> {
+    /// Wrap an allocator so an allocation is sampled every `sample_interval` bytes.
+    pub fn new(inner: A, sample_interval: usize) -> Self {
+        assert!(sample_interval > 0, "The sample interval must not be zero");
+
+        Profiler {
+            inner,
+            sample_interval,
+            until_sample: sample_interval,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn sample_interval(&self) -> usize {
+        self.sample_interval
+    }
+
+    pub fn samples(&self) -> &[Sample<A::Alloc>] {
+        &self.samples
+    }
+
+    /// Discard every sample taken so far.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    /// Group the samples taken so far by allocation site. Sites are ordered by the number of live
+    /// bytes they hold, then by the total number of bytes they allocated. Samples whose liveness can
+    /// not be determined are counted as live.
+    pub fn report(&mut self) -> Profile {
+        let alloc = self.inner.as_raw_allocator();
+        let mut sites = HashMap::<(&'static str, String), usize>::new();
+        let mut profile = Profile {
+            sample_interval: self.sample_interval,
+            sites: Vec::new(),
+        };
+
+        for sample in &self.samples {
+            let backtrace = sample.backtrace.to_string();
+            let index = *sites
+                .entry((sample.type_name, backtrace))
+                .or_insert_with_key(|(type_name, backtrace)| {
+                    profile.sites.push(Site {
+                        type_name,
+                        frames: parse_frames(backtrace),
+                        samples: 0,
+                        total_bytes: 0,
+                        live_bytes: 0,
+                    });
+                    profile.sites.len() - 1
+                });
+
+            let site = &mut profile.sites[index];
+            site.samples += 1;
+            site.total_bytes += sample.weight;
+
+            if sample.is_alive(alloc) != Some(false) {
+                site.live_bytes += sample.weight;
+            }
+        }
+
+        profile
+            .sites
+            .sort_by_key(|site| Reverse((site.live_bytes, site.total_bytes)));
+        profile
+    }
+
+    /// Count an allocation towards the sample interval and take a sample if it crosses it.
+    #[inline(always)]
+    fn record<T>(&mut self, layout: Layout, object: &Gc<T, A::Alloc>)
+    where
+        T: ?Sized,
+        A::Alloc: Alloc<T>,
+    {
+        if layout.size() < self.until_sample {
+            self.until_sample -= layout.size();
+            return;
+        }
+
+        // An allocation may cross the interval several times, so it stands in for each of them
+        let overshoot = layout.size() - self.until_sample;
+        let crossed = 1 + overshoot / self.sample_interval;
+        self.until_sample = self.sample_interval - overshoot % self.sample_interval;
+
+        let is_alive = Alloc::<T>::liveness_check(self.inner.as_raw_allocator(), object.as_raw());
+        self.samples.push(Sample {
+            layout,
+            type_name: std::any::type_name::<T>(),
+            backtrace: Backtrace::force_capture(),
+            weight: crossed.saturating_mul(self.sample_interval),
+            is_alive,
+        });
+    }
+}
+
+/// Read the function names out of a rendered backtrace, skipping the frames used to capture it.
+fn parse_frames(backtrace: &str) -> Vec<String> {
+    backtrace
+        .lines()
+        .filter_map(|line| {
+            let (index, name) = line.trim_start().split_once(": ")?;
+            index.parse::<usize>().ok()?;
+            Some(name.trim())
+        })
+        .skip_while(|name| {
+            // Samples are taken from within the allocation methods of gc_api
+            name.starts_with("std::backtrace")
+                || name.trim_start_matches('<').starts_with("gc_api::alloc::")
+        })
+        .map(String::from)
+        .collect()
+}
+
+/// The samples taken by a [`Profiler`] grouped by allocation site.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    sample_interval: usize,
+    sites: Vec<Site>,
+}
+
+/// The allocations of a single type from a single backtrace.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Site {
+    pub type_name: &'static str,
+    /// The function names of the backtrace, starting from the innermost frame.
+    pub frames: Vec<String>,
+    pub samples: usize,
+    /// The estimated number of bytes allocated by this site.
+    pub total_bytes: usize,
+    /// The estimated number of bytes allocated by this site which have not been collected.
+    pub live_bytes: usize,
+}
+
+/// The byte counts used to weigh each stack of a [`Profile::collapsed`] profile.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Measure {
+    Live,
+    Total,
+}
+
+impl Profile {
+    pub fn sites(&self) -> &[Site] {
+        &self.sites
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.sites.iter().map(|site| site.total_bytes).sum()
+    }
+
+    pub fn live_bytes(&self) -> usize {
+        self.sites.iter().map(|site| site.live_bytes).sum()
+    }
+
+    /// Write this profile in the collapsed stack format used by flamegraph tools. Each line holds
+    /// the frames of a site from the outermost frame inwards, followed by the type which was
+    /// allocated and the number of bytes. Sites without any bytes are skipped.
+    pub fn collapsed(&self, measure: Measure) -> String {
+        let mut out = String::new();
+
+        for site in &self.sites {
+            let bytes = match measure {
+                Measure::Live => site.live_bytes,
+                Measure::Total => site.total_bytes,
+            };
+
+            if bytes == 0 {
+                continue;
+            }
+
+            for frame in site.frames.iter().rev() {
+                // Semicolons separate frames and spaces separate the count, so neither may appear
+                // within a frame
+                let frame = frame.replace(';', ":").replace(' ', "");
+                write!(out, "{};", frame).unwrap();
+            }
+
+            let type_name = site.type_name.replace(';', ":").replace(' ', "");
+            writeln!(out, "{} {}", type_name, bytes).unwrap();
+        }
+
+        out
+    }
+}
+
+/// Writes a human readable summary of every site.
+impl Display for Profile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Sampled every {} bytes: {} bytes live, {} bytes allocated across {} sites",
+            self.sample_interval,
+            self.live_bytes(),
+            self.total_bytes(),
+            self.sites.len()
+        )?;
+
+        for site in &self.sites {
+            writeln!(
+                f,
+                "\n{} bytes live, {} bytes allocated, {} samples: {}",
+                site.live_bytes, site.total_bytes, site.samples, site.type_name
+            )?;
+
+            for frame in &site.frames {
+                writeln!(f, "    at {}", frame)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<A: Allocator> Allocator for Profiler<A> {
+    type Alloc = A::Alloc;
+
+    #[inline(always)]
+    fn as_raw_allocator(&mut self) -> &mut Self::Alloc {
+        self.inner.as_raw_allocator()
+    }
+
+    #[inline(always)]
+    fn yield_point(&mut self) {
+        self.inner.yield_point()
+    }
+
+    fn yield_point_async(&mut self) -> impl Future<Output = ()> {
+        self.inner.yield_point_async()
+    }
+
+    #[inline(always)]
+    fn request_gc(&mut self, request: CollectionType) {
+        self.inner.request_gc(request)
+    }
+
+    #[inline(always)]
+    fn enter_scope(&mut self) {
+        self.inner.enter_scope()
+    }
+
+    #[inline(always)]
+    unsafe fn exit_scope(&mut self) {
+        self.inner.exit_scope()
+    }
+
+    #[inline(always)]
+    fn charge_alloc(&mut self, layout: Layout) -> Result<(), Error> {
+        self.inner.charge_alloc(layout)
+    }
+
+    #[inline(always)]
+    fn refund_alloc(&mut self, layout: Layout) {
+        self.inner.refund_alloc(layout)
+    }
+
+    fn record_alloc<T>(&mut self, layout: Layout, object: &Gc<T, Self::Alloc>)
+    where
+        T: ?Sized,
+        Self::Alloc: Alloc<T>,
+    {
+        self.record(layout, object);
+        self.inner.record_alloc(layout, object);
+    }
+
+    #[inline(always)]
+    fn try_grow(&mut self, additional: usize) -> bool {
+        self.inner.try_grow(additional)
+    }
+
+    #[inline(always)]
+    fn blocking<R, F: FnOnce() -> R>(&mut self, f: F) -> R {
+        self.inner.blocking(f)
+    }
+
+    fn sleep_async(&mut self, delay: Duration) -> Option<impl Future<Output = ()>> {
+        self.inner.sleep_async(delay)
+    }
+
+    fn handle_alloc_failure(&mut self, layout: Layout, error: &Error) -> OomAction {
+        self.inner.handle_alloc_failure(layout, error)
+    }
+}
+
+impl<T, H, A> Accessor<T, H> for Profiler<A>
+where
+    T: ?Sized,
+    H: Alloc<T>,
+    A: Allocator + Accessor<T, H>,
+{
+    type Guard<'g>
+        = A::Guard<'g>
+    where
+        Self: 'g;
+
+    #[inline(always)]
+    unsafe fn access<'g>(
+        &'g self,
+        handle: &'g <H as Alloc<T>>::RawHandle,
+    ) -> Result<Self::Guard<'g>, Error> {
+        self.inner.access(handle)
+    }
+
+    fn is_alive(&self, object: &Gc<T, H>) -> Option<bool> {
+        self.inner.is_alive(object)
+    }
+
+    unsafe fn pin_handle(&self, handle: &<H as Alloc<T>>::RawHandle) -> Result<NonNull<T>, Error> {
+        self.inner.pin_handle(handle)
+    }
+
+    unsafe fn unpin_handle(&self, handle: &<H as Alloc<T>>::RawHandle) {
+        self.inner.unpin_handle(handle)
+    }
+}
+
+impl<T, H, A> AccessorMut<T, H> for Profiler<A>
+where
+    T: ?Sized,
+    H: AllocMut<T>,
+    A: Allocator + AccessorMut<T, H>,
+{
+    type GuardMut<'g>
+        = A::GuardMut<'g>
+    where
+        Self: 'g;
+
+    #[inline(always)]
+    unsafe fn access_mut<'g>(
+        &'g self,
+        handle: &'g <H as Alloc<<H as Alloc<T>>::MutTy>>::RawHandle,
+    ) -> Result<Self::GuardMut<'g>, Error> {
+        self.inner.access_mut(handle)
+    }
+}
+
+impl<H, A: Allocator + RootStorage<H>> RootStorage<H> for Profiler<A> {
+    type Index = A::Index;
+
+    #[inline(always)]
+    fn remove_root(&mut self, index: Self::Index) -> bool {
+        self.inner.remove_root(index)
+    }
+}
+
+impl<T, H, A> GcRootStorage<T, H> for Profiler<A>
+where
+    T: ?Sized,
+    H: Alloc<T>,
+    A: Allocator + GcRootStorage<T, H>,
+{
+    #[inline(always)]
+    fn add_root(&mut self, root: &Gc<T, H>) -> Self::Index {
+        self.inner.add_root(root)
+    }
+}
+
+impl<A: Allocator + ObserveGc> ObserveGc for Profiler<A> {
+    fn add_observer<F>(&mut self, observer: F) -> ObserverId
+    where
+        F: FnMut(&GcEvent) + Send + 'static,
+    {
+        self.inner.add_observer(observer)
+    }
+
+    fn remove_observer(&mut self, id: ObserverId) -> bool {
+        self.inner.remove_observer(id)
+    }
+}
+
+impl<A: Allocator + HandleOom> HandleOom for Profiler<A> {
+    fn set_oom_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&mut OomContext<'_>) -> OomAction + Send + 'static,
+    {
+        self.inner.set_oom_handler(handler)
+    }
+
+    fn remove_oom_handler(&mut self) -> bool {
+        self.inner.remove_oom_handler()
+    }
+
+    fn set_emergency_reserve(&mut self, bytes: usize) -> Result<(), Error> {
+        self.inner.set_emergency_reserve(bytes)
+    }
+}
+
+impl<A: Allocator + HeapStats> HeapStats for Profiler<A> {
+    fn stats(&self) -> GcStats {
+        self.inner.stats()
+    }
+}
+
+impl<A: Allocator> Deref for Profiler<A> {
+    type Target = A;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<A: Allocator> DerefMut for Profiler<A> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
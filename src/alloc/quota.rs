@@ -0,0 +1,473 @@
+//! Allocation budgets for individual allocators.
+//!
+//! A [`Quota`] wraps any [`Allocator`] and charges every allocation made through it against a
+//! [`Budget`]. Once the budget would be exceeded, allocation fails with
+//! [`ErrorKind::QuotaExceeded`] instead of [`ErrorKind::OutOfMemory`] so the caller can tell the
+//! difference between a full heap and a tenant which has used up its share.
+//!
+//! Budgets count the bytes requested through the allocator. On their own, collections do not
+//! credit reclaimed objects back to the budget, so the usage only grows. Calling
+//! [`Quota::credit_collections`] registers a [`GcEvent`] observer which credits back an estimate
+//! of what each collection reclaimed, so a tenant is mostly charged for what it retains. Runtimes
+//! which track retention themselves can instead call [`Budget::release`] or [`Budget::reset`].
+//! A budget can also be given a GC threshold so a collection is requested before the hard limit
+//! is reached.
+
+use crate::alloc::{
+    Accessor, AccessorMut, AllocMut, Allocator, CollectionReport, CollectionType, GcEvent, GcStats,
+    HandleOom, HeapStats, ObserveGc, ObserverId, OomAction, OomContext,
+};
+use crate::error::{Error, ErrorKind};
+use crate::trace::roots::{GcRootStorage, RootStorage};
+use crate::{Alloc, Gc};
+use std::alloc::Layout;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+/// The allocation budget of a [`Quota`]. Budgets are shared through an [`Arc`], so they can be
+/// inspected and adjusted from other threads while the allocator is in use.
+#[derive(Debug)]
+pub struct Budget {
+    limit: AtomicUsize,
+    used: AtomicUsize,
+    gc_threshold: AtomicUsize,
+}
+
+impl Budget {
+    /// Create a budget which allows up to `limit` bytes to be allocated.
+    pub fn new(limit: usize) -> Self {
+        Budget {
+            limit: AtomicUsize::new(limit),
+            used: AtomicUsize::new(0),
+            gc_threshold: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    /// The maximum number of bytes which can be allocated against this budget.
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    /// Change the limit of this budget. Lowering the limit below the current usage does not free
+    /// anything, but all further allocations will fail until enough of the budget is released.
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// The number of bytes currently charged against this budget.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// The number of bytes which can still be allocated before the limit is reached.
+    pub fn remaining(&self) -> usize {
+        self.limit().saturating_sub(self.used())
+    }
+
+    /// The usage at which a collection is requested, if any.
+    pub fn gc_threshold(&self) -> Option<usize> {
+        match self.gc_threshold.load(Ordering::Relaxed) {
+            usize::MAX => None,
+            threshold => Some(threshold),
+        }
+    }
+
+    /// Request a collection whenever an allocation pushes the usage of this budget past the given
+    /// threshold.
+    pub fn set_gc_threshold(&self, threshold: Option<usize>) {
+        let threshold = threshold.unwrap_or(usize::MAX);
+        self.gc_threshold.store(threshold, Ordering::Relaxed);
+    }
+
+    /// Credit `bytes` back to this budget.
+    pub fn release(&self, bytes: usize) {
+        let _ = self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used.saturating_sub(bytes))
+            });
+    }
+
+    /// Credit the entire usage back to this budget.
+    pub fn reset(&self) {
+        self.used.store(0, Ordering::Relaxed);
+    }
+
+    /// Credit the bytes reclaimed by a collection back to this budget.
+    ///
+    /// A [`CollectionReport`] only describes the heap as a whole, so the usage is scaled by the
+    /// fraction of the heap which survived. This assumes the objects charged against this budget
+    /// survive at the same rate as the rest of the heap.
+    pub fn credit_collection(&self, report: &CollectionReport) {
+        let used_after = report.bytes_used as u128;
+        let total = used_after + report.bytes_reclaimed as u128;
+        if total == 0 {
+            return;
+        }
+
+        let _ = self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some((used as u128 * used_after / total) as usize)
+            });
+    }
+
+    /// Attempt to charge `bytes` against this budget. On success, returns the usage before the
+    /// charge was applied.
+    fn charge(&self, bytes: usize) -> Result<usize, Error> {
+        let limit = self.limit();
+
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes).filter(|total| *total <= limit)
+            })
+            .map_err(|used| {
+                Error::new(
+                    ErrorKind::QuotaExceeded,
+                    format!(
+                        "Allocation of {} bytes exceeds the remaining budget of {} bytes",
+                        bytes,
+                        limit.saturating_sub(used)
+                    ),
+                )
+            })
+    }
+}
+
+/// An [`Allocator`] which charges every allocation against a [`Budget`].
+pub struct Quota<A> {
+    inner: A,
+    budget: Arc<Budget>,
+}
+
+impl<A> Quota<A> {
+    /// Wrap an allocator so it can allocate at most `limit` bytes.
+    pub fn new(inner: A, limit: usize) -> Self {
+        Quota::with_budget(inner, Arc::new(Budget::new(limit)))
+    }
+
+    /// Wrap an allocator with an existing budget. The budget may be shared between allocators to
+    /// give them a combined limit.
+    pub fn with_budget(inner: A, budget: Arc<Budget>) -> Self {
+        Quota { inner, budget }
+    }
+
+    pub fn budget(&self) -> &Arc<Budget> {
+        &self.budget
+    }
+
+    /// The number of bytes charged against the budget of this allocator which have not been
+    /// released. If the budget is shared through [`Quota::with_budget`], this includes the
+    /// allocations of every allocator sharing it.
+    pub fn usage(&self) -> usize {
+        self.budget.used()
+    }
+
+    /// Credit the budget with [`Budget::credit_collection`] at the end of every collection.
+    ///
+    /// The returned observer only holds a weak reference to the budget, but it stays registered
+    /// with the heap until it is passed to [`ObserveGc::remove_observer`].
+    pub fn credit_collections(&mut self) -> ObserverId
+    where
+        A: ObserveGc,
+    {
+        let budget = Arc::downgrade(&self.budget);
+        self.inner.add_observer(move |event: &GcEvent| {
+            if let GcEvent::CollectionEnd(report) = event {
+                if let Some(budget) = Weak::upgrade(&budget) {
+                    budget.credit_collection(report);
+                }
+            }
+        })
+    }
+
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+}
+
+impl<A: Allocator> Allocator for Quota<A> {
+    type Alloc = A::Alloc;
+
+    #[inline(always)]
+    fn as_raw_allocator(&mut self) -> &mut Self::Alloc {
+        self.inner.as_raw_allocator()
+    }
+
+    #[inline(always)]
+    fn yield_point(&mut self) {
+        self.inner.yield_point()
+    }
+
+    fn yield_point_async(&mut self) -> impl Future<Output = ()> {
+        self.inner.yield_point_async()
+    }
+
+    #[inline(always)]
+    fn request_gc(&mut self, request: CollectionType) {
+        self.inner.request_gc(request)
+    }
+
+    #[inline(always)]
+    fn enter_scope(&mut self) {
+        self.inner.enter_scope()
+    }
+
+    #[inline(always)]
+    unsafe fn exit_scope(&mut self) {
+        self.inner.exit_scope()
+    }
+
+    #[inline(always)]
+    fn try_grow(&mut self, additional: usize) -> bool {
+        self.inner.try_grow(additional)
+    }
+
+    #[inline(always)]
+    fn blocking<R, F: FnOnce() -> R>(&mut self, f: F) -> R {
+        self.inner.blocking(f)
+    }
+
+    fn sleep_async(&mut self, delay: Duration) -> Option<impl Future<Output = ()>> {
+        self.inner.sleep_async(delay)
+    }
+
+    fn handle_alloc_failure(&mut self, layout: Layout, error: &Error) -> OomAction {
+        self.inner.handle_alloc_failure(layout, error)
+    }
+
+    fn charge_alloc(&mut self, layout: Layout) -> Result<(), Error> {
+        self.inner.charge_alloc(layout)?;
+
+        let used = match self.budget.charge(layout.size()) {
+            Ok(used) => used,
+            Err(err) => {
+                self.inner.refund_alloc(layout);
+                return Err(err);
+            }
+        };
+
+        if let Some(threshold) = self.budget.gc_threshold() {
+            if used <= threshold && used + layout.size() > threshold {
+                self.inner.request_gc(CollectionType::Suggest);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn refund_alloc(&mut self, layout: Layout) {
+        self.budget.release(layout.size());
+        self.inner.refund_alloc(layout);
+    }
+
+    #[inline(always)]
+    fn record_alloc<T>(&mut self, layout: Layout, object: &Gc<T, Self::Alloc>)
+    where
+        T: ?Sized,
+        Self::Alloc: Alloc<T>,
+    {
+        self.inner.record_alloc(layout, object)
+    }
+}
+
+impl<T, H, A> Accessor<T, H> for Quota<A>
+where
+    T: ?Sized,
+    H: Alloc<T>,
+    A: Accessor<T, H>,
+{
+    type Guard<'g>
+        = A::Guard<'g>
+    where
+        Self: 'g;
+
+    #[inline(always)]
+    unsafe fn access<'g>(
+        &'g self,
+        handle: &'g <H as Alloc<T>>::RawHandle,
+    ) -> Result<Self::Guard<'g>, Error> {
+        self.inner.access(handle)
+    }
+
+    fn is_alive(&self, object: &Gc<T, H>) -> Option<bool> {
+        self.inner.is_alive(object)
+    }
+
+    unsafe fn pin_handle(&self, handle: &<H as Alloc<T>>::RawHandle) -> Result<NonNull<T>, Error> {
+        self.inner.pin_handle(handle)
+    }
+
+    unsafe fn unpin_handle(&self, handle: &<H as Alloc<T>>::RawHandle) {
+        self.inner.unpin_handle(handle)
+    }
+}
+
+impl<T, H, A> AccessorMut<T, H> for Quota<A>
+where
+    T: ?Sized,
+    H: AllocMut<T>,
+    A: AccessorMut<T, H>,
+{
+    type GuardMut<'g>
+        = A::GuardMut<'g>
+    where
+        Self: 'g;
+
+    #[inline(always)]
+    unsafe fn access_mut<'g>(
+        &'g self,
+        handle: &'g <H as Alloc<<H as Alloc<T>>::MutTy>>::RawHandle,
+    ) -> Result<Self::GuardMut<'g>, Error> {
+        self.inner.access_mut(handle)
+    }
+}
+
+impl<H, A: RootStorage<H>> RootStorage<H> for Quota<A> {
+    type Index = A::Index;
+
+    #[inline(always)]
+    fn remove_root(&mut self, index: Self::Index) -> bool {
+        self.inner.remove_root(index)
+    }
+}
+
+impl<T, H, A> GcRootStorage<T, H> for Quota<A>
+where
+    T: ?Sized,
+    H: Alloc<T>,
+    A: GcRootStorage<T, H>,
+{
+    #[inline(always)]
+    fn add_root(&mut self, root: &Gc<T, H>) -> Self::Index {
+        self.inner.add_root(root)
+    }
+}
+
+impl<A: ObserveGc> ObserveGc for Quota<A> {
+    fn add_observer<F>(&mut self, observer: F) -> ObserverId
+    where
+        F: FnMut(&GcEvent) + Send + 'static,
+    {
+        self.inner.add_observer(observer)
+    }
+
+    fn remove_observer(&mut self, id: ObserverId) -> bool {
+        self.inner.remove_observer(id)
+    }
+}
+
+impl<A: HandleOom> HandleOom for Quota<A> {
+    fn set_oom_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&mut OomContext<'_>) -> OomAction + Send + 'static,
+    {
+        self.inner.set_oom_handler(handler)
+    }
+
+    fn remove_oom_handler(&mut self) -> bool {
+        self.inner.remove_oom_handler()
+    }
+
+    fn set_emergency_reserve(&mut self, bytes: usize) -> Result<(), Error> {
+        self.inner.set_emergency_reserve(bytes)
+    }
+}
+
+impl<A: HeapStats> HeapStats for Quota<A> {
+    fn stats(&self) -> GcStats {
+        self.inner.stats()
+    }
+}
+
+impl<A> Deref for Quota<A> {
+    type Target = A;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<A> DerefMut for Quota<A> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn charge() {
+        let budget = Budget::new(100);
+        assert_eq!(budget.charge(60).unwrap(), 0);
+        assert_eq!(budget.charge(40).unwrap(), 60);
+        assert_eq!(budget.used(), 100);
+        assert_eq!(budget.remaining(), 0);
+
+        // Failed charges leave the usage untouched
+        let err = budget.charge(1).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::QuotaExceeded);
+        assert_eq!(budget.used(), 100);
+
+        budget.release(30);
+        assert_eq!(budget.charge(30).unwrap(), 70);
+
+        // Releasing more than was charged does not underflow
+        budget.release(1000);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn charge_overflow() {
+        let budget = Budget::new(usize::MAX);
+        budget.charge(usize::MAX - 1).unwrap();
+        assert_eq!(
+            budget.charge(2).unwrap_err().kind(),
+            ErrorKind::QuotaExceeded
+        );
+        assert_eq!(budget.used(), usize::MAX - 1);
+    }
+
+    #[test]
+    fn lowered_limit() {
+        let budget = Budget::new(100);
+        budget.charge(80).unwrap();
+        budget.set_limit(50);
+        assert_eq!(budget.remaining(), 0);
+        assert!(budget.charge(0).is_err());
+
+        budget.reset();
+        assert_eq!(budget.charge(50).unwrap(), 0);
+    }
+
+    #[test]
+    fn credit_collection() {
+        let budget = Budget::new(1000);
+        budget.charge(800).unwrap();
+
+        let report = |bytes_used, bytes_reclaimed| CollectionReport {
+            collection: CollectionType::Full,
+            live_objects: 0,
+            bytes_used,
+            bytes_reclaimed,
+            pause_time: Duration::ZERO,
+        };
+
+        // A quarter of the heap survived, so a quarter of the usage is kept
+        budget.credit_collection(&report(100, 300));
+        assert_eq!(budget.used(), 200);
+
+        budget.credit_collection(&report(0, 0));
+        assert_eq!(budget.used(), 200);
+
+        budget.credit_collection(&report(0, 100));
+        assert_eq!(budget.used(), 0);
+    }
+}
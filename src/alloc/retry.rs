@@ -0,0 +1,210 @@
+//! Policies deciding how an allocation recovers from running out of memory.
+//!
+//! Whenever an allocation made through an [`Allocator`](crate::alloc::Allocator) fails with
+//! [`ErrorKind::OutOfMemory`](crate::error::ErrorKind::OutOfMemory), the [`RetryPolicy`] passed to
+//! the allocation is asked what to do next. The allocation is then retried until it either succeeds
+//! or the policy gives up.
+//!
+//! For compatibility with retry limits, `Option<u32>` is also a policy. `Some(n)` requests a
+//! [`CollectionType::AllocAtLeast`] collection up to `n` times before giving up, while `None`
+//! retries indefinitely.
+
+use crate::alloc::CollectionType;
+use std::alloc::Layout;
+use std::time::Duration;
+
+/// The action to take after a failed allocation attempt.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RetryAction {
+    /// Request a collection of the given type, then retry once the allocator has reached a yield
+    /// point.
+    Collect(CollectionType),
+    /// Reach a yield point without requesting a collection. This gives a collection requested by
+    /// another thread the chance to complete before retrying.
+    Yield,
+    /// Reach a yield point, then wait for the given duration before retrying. The wait happens in
+    /// [`Allocator::blocking`](crate::alloc::Allocator::blocking) so it does not hold up
+    /// collections on other threads. Async allocations wait with
+    /// [`Allocator::sleep_async`](crate::alloc::Allocator::sleep_async) instead. If the allocator
+    /// does not provide a timer, a back off is treated as [`RetryAction::GiveUp`] by async
+    /// allocations rather than retrying without waiting.
+    BackOff(Duration),
+    /// Attempt to grow the heap by at least the given number of bytes before retrying (See
+    /// [`Allocator::try_grow`](crate::alloc::Allocator::try_grow)).
+    Grow(usize),
+    /// Stop retrying and return the error.
+    GiveUp,
+}
+
+/// Decides how an allocation recovers after running out of memory.
+pub trait RetryPolicy {
+    /// Choose what to do after a failed attempt to allocate `layout`. `attempt` is the number of
+    /// previous failures for this allocation, so it starts at zero.
+    fn on_failure(&mut self, attempt: u32, layout: Layout) -> RetryAction;
+}
+
+impl<P: RetryPolicy + ?Sized> RetryPolicy for &mut P {
+    #[inline(always)]
+    fn on_failure(&mut self, attempt: u32, layout: Layout) -> RetryAction {
+        (**self).on_failure(attempt, layout)
+    }
+}
+
+impl RetryPolicy for Option<u32> {
+    #[inline(always)]
+    fn on_failure(&mut self, attempt: u32, layout: Layout) -> RetryAction {
+        match *self {
+            Some(limit) if attempt >= limit => RetryAction::GiveUp,
+            _ => RetryAction::Collect(CollectionType::AllocAtLeast(layout)),
+        }
+    }
+}
+
+/// Give up immediately without attempting a collection.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NoRetry;
+
+impl RetryPolicy for NoRetry {
+    #[inline(always)]
+    fn on_failure(&mut self, _: u32, _: Layout) -> RetryAction {
+        RetryAction::GiveUp
+    }
+}
+
+/// Request progressively more expensive collections before giving up: first a
+/// [`CollectionType::Suggest`], then a [`CollectionType::Partial`], then a
+/// [`CollectionType::Full`]. If growth is enabled, the heap is grown once all of them have failed
+/// to free enough space. This is the policy used by allocation methods which do not take one.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Escalate {
+    grow: bool,
+}
+
+impl Escalate {
+    pub const fn new() -> Self {
+        Escalate { grow: false }
+    }
+
+    /// Attempt to grow the heap after a full collection fails to free enough space.
+    pub const fn with_growth(self) -> Self {
+        Escalate { grow: true }
+    }
+}
+
+impl RetryPolicy for Escalate {
+    fn on_failure(&mut self, attempt: u32, layout: Layout) -> RetryAction {
+        match attempt {
+            0 => RetryAction::Collect(CollectionType::Suggest),
+            1 => RetryAction::Collect(CollectionType::Partial),
+            2 => RetryAction::Collect(CollectionType::Full),
+            3 if self.grow => RetryAction::Grow(layout.size()),
+            _ => RetryAction::GiveUp,
+        }
+    }
+}
+
+/// Wait for a collection started by another thread, backing off exponentially between attempts.
+/// This never requests a collection itself, so it is suited to threads which should not trigger
+/// pauses on their own.
+#[derive(Debug, Copy, Clone)]
+pub struct BackOff {
+    /// The delay before the first retry. Each following retry doubles the delay.
+    pub initial: Duration,
+    /// The number of retries before giving up.
+    pub retries: u32,
+}
+
+impl RetryPolicy for BackOff {
+    fn on_failure(&mut self, attempt: u32, _: Layout) -> RetryAction {
+        if attempt >= self.retries {
+            return RetryAction::GiveUp;
+        }
+
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        RetryAction::BackOff(self.initial.saturating_mul(factor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escalate() {
+        let layout = Layout::new::<u64>();
+
+        let mut escalate = Escalate::new();
+        let actions: Vec<_> = (0..5).map(|i| escalate.on_failure(i, layout)).collect();
+        assert_eq!(
+            actions,
+            [
+                RetryAction::Collect(CollectionType::Suggest),
+                RetryAction::Collect(CollectionType::Partial),
+                RetryAction::Collect(CollectionType::Full),
+                RetryAction::GiveUp,
+                RetryAction::GiveUp,
+            ]
+        );
+
+        let mut growing = Escalate::new().with_growth();
+        assert_eq!(growing.on_failure(3, layout), RetryAction::Grow(8));
+        assert_eq!(growing.on_failure(4, layout), RetryAction::GiveUp);
+    }
+
+    #[test]
+    fn retry_limit() {
+        let layout = Layout::new::<u64>();
+        let collect = RetryAction::Collect(CollectionType::AllocAtLeast(layout));
+
+        let mut limit = Some(2);
+        assert_eq!(limit.on_failure(0, layout), collect);
+        assert_eq!(limit.on_failure(1, layout), collect);
+        assert_eq!(limit.on_failure(2, layout), RetryAction::GiveUp);
+
+        assert_eq!(Some(0).on_failure(0, layout), RetryAction::GiveUp);
+        assert_eq!(None.on_failure(u32::MAX, layout), collect);
+    }
+
+    #[test]
+    fn back_off() {
+        let layout = Layout::new::<u64>();
+
+        let mut back_off = BackOff {
+            initial: Duration::from_millis(1),
+            retries: 3,
+        };
+        let actions: Vec<_> = (0..4).map(|i| back_off.on_failure(i, layout)).collect();
+        assert_eq!(
+            actions,
+            [
+                RetryAction::BackOff(Duration::from_millis(1)),
+                RetryAction::BackOff(Duration::from_millis(2)),
+                RetryAction::BackOff(Duration::from_millis(4)),
+                RetryAction::GiveUp,
+            ]
+        );
+
+        // Large attempt counts saturate instead of overflowing
+        let mut patient = BackOff {
+            initial: Duration::from_secs(1),
+            retries: u32::MAX,
+        };
+        assert_eq!(
+            patient.on_failure(40, layout),
+            RetryAction::BackOff(Duration::from_secs(u32::MAX as u64))
+        );
+    }
+
+    #[test]
+    fn policies_by_reference() {
+        fn first_failure<P: RetryPolicy>(mut policy: P) -> RetryAction {
+            policy.on_failure(0, Layout::new::<u64>())
+        }
+
+        assert_eq!(
+            first_failure(&mut Escalate::new()),
+            RetryAction::Collect(CollectionType::Suggest)
+        );
+        assert_eq!(first_failure(&mut NoRetry), RetryAction::GiveUp);
+    }
+}
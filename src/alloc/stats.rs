@@ -0,0 +1,77 @@
+//! A common interface for reporting the state of a garbage collected heap.
+//!
+//! Every backend tracks slightly different information, but monitoring generally only needs a
+//! handful of numbers. [`HeapStats`] exposes these through a single snapshot so heaps and
+//! allocators from different backends can be inspected the same way.
+
+use crate::alloc::CollectionType;
+use std::time::Duration;
+
+/// A snapshot of the statistics for a heap.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct GcStats {
+    /// The number of bytes currently in use by objects, including any per-object overhead.
+    pub bytes_used: usize,
+    /// The total number of bytes available to the heap.
+    pub capacity: usize,
+    /// The number of objects which have not yet been collected. Objects which have become
+    /// unreachable since the last collection are still included.
+    pub live_objects: usize,
+    /// The number of collections which have been performed.
+    pub collections: CollectionCounts,
+    /// The total number of bytes reclaimed across all collections.
+    pub bytes_reclaimed: u64,
+    /// The total time spent performing collections.
+    pub pause_time: Duration,
+}
+
+/// The number of collections performed for each [`CollectionType`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct CollectionCounts {
+    pub full: u64,
+    pub partial: u64,
+    pub alloc_at_least: u64,
+    pub suggest: u64,
+    pub custom: u64,
+}
+
+impl CollectionCounts {
+    /// Record that a collection of the given type was performed.
+    pub fn record(&mut self, collection: CollectionType) {
+        *self.get_mut(collection) += 1;
+    }
+
+    /// Get the number of collections performed of the given type. Custom collections are counted
+    /// together regardless of their id.
+    pub fn get(&self, collection: CollectionType) -> u64 {
+        match collection {
+            CollectionType::Full => self.full,
+            CollectionType::Partial => self.partial,
+            CollectionType::AllocAtLeast(_) => self.alloc_at_least,
+            CollectionType::Suggest => self.suggest,
+            CollectionType::Custom(_) => self.custom,
+        }
+    }
+
+    fn get_mut(&mut self, collection: CollectionType) -> &mut u64 {
+        match collection {
+            CollectionType::Full => &mut self.full,
+            CollectionType::Partial => &mut self.partial,
+            CollectionType::AllocAtLeast(_) => &mut self.alloc_at_least,
+            CollectionType::Suggest => &mut self.suggest,
+            CollectionType::Custom(_) => &mut self.custom,
+        }
+    }
+
+    /// The total number of collections performed.
+    pub fn total(&self) -> u64 {
+        self.full + self.partial + self.alloc_at_least + self.suggest + self.custom
+    }
+}
+
+/// Heaps and allocators which are able to report statistics about their heap. When implemented
+/// for an allocator, the statistics should cover the entire heap and not just the portion used by
+/// that allocator.
+pub trait HeapStats {
+    fn stats(&self) -> GcStats;
+}
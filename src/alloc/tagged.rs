@@ -0,0 +1,131 @@
+//! Generation tagged handles for garbage collectors which use a reference table.
+//!
+//! A reference table adds a layer of indirection between handles and the objects they point to.
+//! Each slot in the table holds the current location of an object so the GC is free to move it.
+//! However, once an object has been collected its slot will eventually be reused by a new object
+//! and any stale handles would silently point to the wrong data (the ABA problem). To detect this,
+//! each slot also holds a generation counter which is incremented whenever the slot is freed. A
+//! handle records the generation of the slot at the time it was created and is only considered
+//! alive while the two agree.
+
+use crate::alloc::IdentityHandle;
+use crate::error::{Error, ErrorKind};
+use std::cell::Cell;
+use std::ptr::{null_mut, NonNull};
+
+/// A single entry within a reference table.
+///
+/// The pointer held by a slot is left entirely up to the GC. When occupied, it will generally point
+/// to the object data. When free, it may be used to link to the next free slot in the table.
+#[repr(C)]
+pub struct TaggedSlot {
+    ptr: Cell<*mut u8>,
+    generation: Cell<usize>,
+}
+
+impl TaggedSlot {
+    pub const fn new(ptr: *mut u8) -> Self {
+        TaggedSlot {
+            ptr: Cell::new(ptr),
+            generation: Cell::new(0),
+        }
+    }
+
+    #[inline(always)]
+    pub fn get(&self) -> *mut u8 {
+        self.ptr.get()
+    }
+
+    #[inline(always)]
+    pub fn set(&self, ptr: *mut u8) {
+        self.ptr.set(ptr)
+    }
+
+    #[inline(always)]
+    pub fn generation(&self) -> usize {
+        self.generation.get()
+    }
+
+    /// Increment the generation of this slot. Any handles created before this point will no longer
+    /// be considered alive. This should be called whenever the object held by this slot is freed.
+    #[inline(always)]
+    pub fn invalidate(&self) {
+        self.generation.set(self.generation.get().wrapping_add(1));
+    }
+}
+
+impl Default for TaggedSlot {
+    fn default() -> Self {
+        TaggedSlot::new(null_mut())
+    }
+}
+
+/// A handle into a reference table made up of [`TaggedSlot`]s. It consists of a pointer to the slot
+/// along with the generation of the slot at the time the handle was created.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct TaggedHandle {
+    slot: NonNull<TaggedSlot>,
+    generation: usize,
+}
+
+/// Slots do not move, so the slot and generation uniquely identify an object.
+impl IdentityHandle for TaggedHandle {}
+
+impl TaggedHandle {
+    /// Create a new handle for the object currently held by a slot.
+    ///
+    /// # Safety
+    /// The slot must be valid for reads for as long as this handle (or any copies of it) may be
+    /// used.
+    #[inline(always)]
+    pub unsafe fn new(slot: NonNull<TaggedSlot>) -> Self {
+        TaggedHandle {
+            slot,
+            generation: slot.as_ref().generation(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn slot(&self) -> NonNull<TaggedSlot> {
+        self.slot
+    }
+
+    #[inline(always)]
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Check if the object referenced by this handle has not been freed.
+    ///
+    /// # Safety
+    /// The reference table this handle points into must still be alive.
+    #[inline(always)]
+    pub unsafe fn is_alive(&self) -> bool {
+        self.slot.as_ref().generation() == self.generation
+    }
+
+    /// Get the pointer held by the slot for this handle or return a [`ErrorKind::UseAfterFree`]
+    /// error if the object has since been freed.
+    ///
+    /// # Safety
+    /// The reference table this handle points into must still be alive.
+    #[inline(always)]
+    pub unsafe fn get(&self) -> Result<NonNull<u8>, Error> {
+        if !self.is_alive() {
+            return Err(Error::from(ErrorKind::UseAfterFree));
+        }
+
+        Ok(self.get_unchecked())
+    }
+
+    /// Get the pointer held by the slot for this handle without checking the generation.
+    ///
+    /// # Safety
+    /// The reference table this handle points into must still be alive and the handle must not
+    /// refer to an object which has been freed.
+    #[inline(always)]
+    pub unsafe fn get_unchecked(&self) -> NonNull<u8> {
+        debug_assert!(self.is_alive(), "Attempted to use a stale handle");
+        NonNull::new_unchecked(self.slot.as_ref().get())
+    }
+}
@@ -18,6 +18,13 @@ pub enum ErrorKind {
     /// to a fixed alignment for all allocations and are unable to support custom allocations larger
     /// than the programmed amount.
     UnsupportedAlignment,
+    /// Used to indicate an allocation requested [`AllocFlags`](crate::alloc::AllocFlags) which are
+    /// not supported by the allocator. Supported flags can be checked ahead of time through
+    /// [`Alloc::supported_flags`](crate::alloc::Alloc::supported_flags).
+    UnsupportedFlags,
+    /// Used to indicate an operation which is not supported by the GC, such as pinning objects in
+    /// place through an accessor which is unable to do so.
+    Unsupported,
     /// This error indicates that an invalid state has been reached by the GC. This may be the
     /// result of unsafe code or be produced by a GC which requires strict usage requirements.
     IllegalState,
@@ -25,6 +32,10 @@ pub enum ErrorKind {
     /// collected. It should not be assumed that this error will be returned as not many garbage
     /// collectors attempt to detect when this occurs.
     UseAfterFree,
+    /// This error indicates that an allocation would exceed the budget assigned to the allocator
+    /// (See [`Quota`](crate::alloc::quota::Quota)). Unlike [`ErrorKind::OutOfMemory`], there may
+    /// still be space remaining in the heap.
+    QuotaExceeded,
     /// Any error which is not covered by another error kind.
     Other,
 }
@@ -39,6 +50,10 @@ impl Display for ErrorKind {
             ErrorKind::UnsupportedAlignment => {
                 write!(f, "The requested allocation alignment is not supported")
             }
+            ErrorKind::UnsupportedFlags => {
+                write!(f, "The requested allocation flags are not supported")
+            }
+            ErrorKind::Unsupported => write!(f, "The requested operation is not supported"),
             ErrorKind::IllegalState => write!(
                 f,
                 "Attempted to enter an invalid state to complete this request"
@@ -46,6 +61,12 @@ impl Display for ErrorKind {
             ErrorKind::UseAfterFree => {
                 write!(f, "Attempted to access an object which has been freed")
             }
+            ErrorKind::QuotaExceeded => {
+                write!(
+                    f,
+                    "The allocation budget of this allocator has been exceeded"
+                )
+            }
             ErrorKind::Other => write!(
                 f,
                 "An unknown error occurred while attempting to complete the request"
@@ -95,3 +116,62 @@ impl Display for Error {
         }
     }
 }
+
+/// An error returned by a fallible allocation which hands back the value (or the function used to
+/// create it) that could not be allocated. This allows callers to retry with a different heap
+/// instead of losing the value.
+pub struct AllocError<T> {
+    error: Error,
+    value: T,
+}
+
+impl<T> AllocError<T> {
+    pub fn new(error: Error, value: T) -> Self {
+        AllocError { error, value }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Get back the value which could not be allocated.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    pub fn into_error(self) -> Error {
+        self.error
+    }
+
+    pub fn into_parts(self) -> (Error, T) {
+        (self.error, self.value)
+    }
+}
+
+impl<T> From<AllocError<T>> for Error {
+    fn from(err: AllocError<T>) -> Self {
+        err.error
+    }
+}
+
+impl<T> Debug for AllocError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AllocError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Display for AllocError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.error, f)
+    }
+}
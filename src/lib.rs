@@ -1,12 +1,16 @@
 //! A collection of traits and structures to help define the semantics of a multithreading garbage
 //! collector.
 use crate::alloc::access::Accessor;
-use crate::alloc::{Alloc, AllocMut};
+use crate::alloc::{Alloc, AllocMut, CoerceHandle, IdentityHandle, RawMeta};
 use crate::error::Error;
+use std::hash::{Hash, Hasher};
+use std::mem::size_of;
+use std::ptr::NonNull;
 
 pub mod alloc;
 pub mod error;
 pub mod mark;
+pub mod safepoint;
 pub mod trace;
 
 /// An owned handle into a garbage collected heap. The heap should outlive
@@ -32,17 +36,21 @@ pub trait Heap {
 ///
 /// ```rust
 /// # use gc_api::Gc;
-/// let mut item: Gc<i32, SomeAllocator> = allocator.alloc(3);
-///
-/// // `Gc<T>` may implement `Deref` to mimic `Arc<T>` in function. However, this is the least safe
-/// // approach to access data and may trigger a panic if unsupported by the current GC.
-/// let deref: &i32 = &*item;
+/// # use gc_api::alloc::{Accessor, Alloc, Allocator};
+/// # fn example<A>(allocator: &mut A)
+/// # where
+/// #     A: Allocator + Accessor<i32, <A as Allocator>::Alloc>,
+/// #     A::Alloc: Alloc<i32>,
+/// # {
+/// let item: Gc<i32, A::Alloc> = allocator.alloc(3);
 ///
 /// // This is the recommended way to access data since it provides extra safety guarantees. The
 /// // reference to an allocator prevents garbage collection from being performed while the item is
 /// // in use and ensures that the heap is still alive. It also has the option to use the allocator
 /// // to get information necessary to dereference the item.
-/// let with_alloc: &i32 = item.with(&allocator);
+/// let with_alloc = item.get(allocator);
+/// assert_eq!(*with_alloc, 3);
+/// # }
 /// ```
 #[repr(transparent)]
 pub struct Gc<T: ?Sized, H: Alloc<T>> {
@@ -90,6 +98,86 @@ impl<T: ?Sized, H: Alloc<T>> Gc<T, H> {
     pub unsafe fn from_raw(raw: <H as Alloc<T>>::RawHandle) -> Self {
         Gc { handle: raw }
     }
+
+    /// Check if two handles refer to the same object.
+    #[inline(always)]
+    pub fn ptr_eq(&self, other: &Self) -> bool
+    where
+        <H as Alloc<T>>::RawHandle: IdentityHandle,
+    {
+        self.handle == other.handle
+    }
+
+    /// Create a weak handle to the same object.
+    pub fn downgrade(&self) -> GcWeak<T, H>
+    where
+        <H as Alloc<T>>::RawHandle: Clone,
+    {
+        GcWeak {
+            handle: self.handle.clone(),
+        }
+    }
+}
+
+impl<T, H: Alloc<T>> Gc<T, H> {
+    /// Convert this handle into a handle to an unsized view of the same object. The given function
+    /// performs the coercion on a (dangling) pointer so the resulting pointer metadata can be
+    /// recorded in the new handle. In most cases, [`gc_coerce`] should be used instead.
+    ///
+    /// ```rust
+    /// # use gc_api::Gc;
+    /// # use gc_api::alloc::{Allocator, CoerceHandle};
+    /// # use std::fmt::Debug;
+    /// # fn example<A>(allocator: &mut A)
+    /// # where
+    /// #     A: Allocator,
+    /// #     A::Alloc: CoerceHandle<u32, dyn Debug>,
+    /// # {
+    /// let value: Gc<u32, A::Alloc> = allocator.alloc(3);
+    /// let value: Gc<dyn Debug, A::Alloc> = unsafe { value.coerce(|ptr| ptr as *const dyn Debug) };
+    /// # }
+    /// ```
+    ///
+    /// # Safety
+    /// The function must only perform an unsizing coercion. The returned pointer must have the same
+    /// address as the given pointer and its metadata must be valid for the object.
+    pub unsafe fn coerce<U: ?Sized>(self, f: impl FnOnce(*const T) -> *const U) -> Gc<U, H>
+    where
+        H: CoerceHandle<T, U>,
+    {
+        const {
+            assert!(
+                size_of::<*const U>() > size_of::<*const T>(),
+                "Handles can only be coerced to unsized types"
+            )
+        };
+
+        let meta = RawMeta::of(f(NonNull::<T>::dangling().as_ptr()));
+        Gc::from_raw(H::coerce_handle(self.handle, meta))
+    }
+}
+
+/// Safely coerce a `Gc<T>` into a handle to an unsized type (Ex: `Gc<dyn Trait>` or `Gc<[T]>`).
+///
+/// ```rust
+/// # use gc_api::{gc_coerce, Gc};
+/// # use gc_api::alloc::{Allocator, CoerceHandle};
+/// # fn example<A>(allocator: &mut A)
+/// # where
+/// #     A: Allocator,
+/// #     A::Alloc: CoerceHandle<[u32; 4], [u32]>,
+/// # {
+/// let values: Gc<[u32; 4], A::Alloc> = allocator.alloc([1, 2, 3, 4]);
+/// let values: Gc<[u32], A::Alloc> = gc_coerce!(values => [u32]);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! gc_coerce {
+    ($gc:expr => $target:ty) => {
+        // SAFETY: Casting a thin pointer to a wide pointer with `as` is only possible through an
+        // unsizing coercion. `Gc::coerce` rejects targets which are not wide pointers.
+        unsafe { $crate::Gc::coerce($gc, |ptr| ptr as *const $target) }
+    };
 }
 
 impl<T: ?Sized, H: Alloc<T>> Copy for Gc<T, H> where <H as Alloc<T>>::RawHandle: Copy {}
@@ -105,4 +193,107 @@ where
     }
 }
 
+/// Handles are compared by identity (See [`Gc::ptr_eq`]) and not by the value they refer to.
+impl<T: ?Sized, H: Alloc<T>> PartialEq for Gc<T, H>
+where
+    <H as Alloc<T>>::RawHandle: IdentityHandle,
+{
+    #[inline(always)]
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other)
+    }
+}
+
+impl<T: ?Sized, H: Alloc<T>> Eq for Gc<T, H> where <H as Alloc<T>>::RawHandle: IdentityHandle {}
+
+impl<T: ?Sized, H: Alloc<T>> Hash for Gc<T, H>
+where
+    <H as Alloc<T>>::RawHandle: IdentityHandle,
+{
+    #[inline(always)]
+    fn hash<S: Hasher>(&self, state: &mut S) {
+        self.handle.hash(state)
+    }
+}
+
 pub type GcMut<T, H> = Gc<<H as Alloc<T>>::MutTy, H>;
+
+/// A weak pointer into the heap. Unlike [`Gc`], a weak handle does not keep the object it refers to
+/// alive during garbage collection. To access the object, it must first be upgraded back into a
+/// [`Gc`] via an [`Accessor`]. Upgrading will fail once the object has been collected.
+///
+/// Whether a weak handle can be upgraded depends on the garbage collector being able to tell if an
+/// object is still alive (See [`Accessor::is_alive`]). Garbage collectors which are unable to do so
+/// will never upgrade a weak handle.
+#[repr(transparent)]
+pub struct GcWeak<T: ?Sized, H: Alloc<T>> {
+    handle: <H as Alloc<T>>::RawHandle,
+}
+
+impl<T: ?Sized, H: Alloc<T>> GcWeak<T, H> {
+    /// Attempt to upgrade this handle to a strong handle.
+    ///
+    /// This function is syntactic sugar for `accessor.upgrade(self)`.
+    #[inline(always)]
+    pub fn upgrade<A>(&self, accessor: &A) -> Option<Gc<T, H>>
+    where
+        A: Accessor<T, H>,
+        <H as Alloc<T>>::RawHandle: Clone,
+    {
+        accessor.upgrade(self)
+    }
+
+    /// Converts a `GcWeak<T>` into the underlying raw handle type.
+    pub fn into_raw(self) -> <H as Alloc<T>>::RawHandle {
+        self.handle
+    }
+
+    /// Get a reference into the underlying raw handle type.
+    pub fn as_raw(&self) -> &<H as Alloc<T>>::RawHandle {
+        &self.handle
+    }
+
+    /// Reconstructs a `GcWeak<T>` from a raw handle type.
+    ///
+    /// # Safety
+    /// This function should only be used with an unmodified raw handle produced by
+    /// [`GcWeak::into_raw`], [`Gc::into_raw`], or by an underlying garbage collector implementation.
+    pub unsafe fn from_raw(raw: <H as Alloc<T>>::RawHandle) -> Self {
+        GcWeak { handle: raw }
+    }
+}
+
+impl<T: ?Sized, H: Alloc<T>> Copy for GcWeak<T, H> where <H as Alloc<T>>::RawHandle: Copy {}
+
+impl<T: ?Sized, H: Alloc<T>> Clone for GcWeak<T, H>
+where
+    <H as Alloc<T>>::RawHandle: Clone,
+{
+    fn clone(&self) -> Self {
+        GcWeak {
+            handle: self.handle.clone(),
+        }
+    }
+}
+
+impl<T: ?Sized, H: Alloc<T>> PartialEq for GcWeak<T, H>
+where
+    <H as Alloc<T>>::RawHandle: IdentityHandle,
+{
+    #[inline(always)]
+    fn eq(&self, other: &Self) -> bool {
+        self.handle == other.handle
+    }
+}
+
+impl<T: ?Sized, H: Alloc<T>> Eq for GcWeak<T, H> where <H as Alloc<T>>::RawHandle: IdentityHandle {}
+
+impl<T: ?Sized, H: Alloc<T>> Hash for GcWeak<T, H>
+where
+    <H as Alloc<T>>::RawHandle: IdentityHandle,
+{
+    #[inline(always)]
+    fn hash<S: Hasher>(&self, state: &mut S) {
+        self.handle.hash(state)
+    }
+}
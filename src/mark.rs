@@ -3,7 +3,7 @@
 /// > **Notes:** There seem to be two primary types of object mark depending on implementation.
 /// >  - Marks which are unset on an initial pass before being set during the tracing pass.
 /// >  - Marks which flip the state of the mark between traces. This requires new objects be
-///      initialized to the current mark state, but does not require an un-marking pass on tracing.
+/// >    initialized to the current mark state, but does not require an un-marking pass on tracing.
 pub trait Mark {
     /// Read the current state of the mark.
     fn load_mark_state(&self) -> bool;
@@ -0,0 +1,285 @@
+//! Stop-the-world coordination between mutator threads.
+//!
+//! Each thread which allocates into a shared heap registers itself as a [`Mutator`] with a
+//! [`Safepoint`]. When a collection is needed, [`Safepoint::request_stop`] is called and every
+//! mutator will park the next time it reaches [`Mutator::yield_point`]. Once all mutators have
+//! parked, the last thread to arrive performs the collection and then resumes everyone else. This
+//! is the handshake expected behind [`Allocator::yield_point`](crate::alloc::Allocator::yield_point)
+//! for multithreaded garbage collectors.
+//!
+//! Threads which may block for long periods of time (Ex: waiting on IO or a lock) should do so
+//! within [`Mutator::blocking`] so they do not hold up collections. A mutator must not touch the
+//! heap while inside a blocking region.
+//!
+//! Mutators running on an async executor can use [`Mutator::yield_point_async`] instead. Rather
+//! than blocking the thread until the collection is complete, the returned future stays parked and
+//! is woken once it is able to make progress.
+
+use std::future::Future;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+struct SafepointState {
+    /// The number of registered mutators
+    mutators: usize,
+    /// The number of mutators which are either parked or inside a blocking region
+    parked: usize,
+    /// Incremented after every collection
+    epoch: u64,
+    /// Wakers for parked async yield points
+    wakers: Vec<Waker>,
+}
+
+/// A registry of mutators along with a stop-the-world request flag.
+pub struct Safepoint {
+    stop_requested: AtomicBool,
+    state: Mutex<SafepointState>,
+    changed: Condvar,
+}
+
+impl Safepoint {
+    pub fn new() -> Self {
+        Safepoint {
+            stop_requested: AtomicBool::new(false),
+            state: Mutex::new(SafepointState {
+                mutators: 0,
+                parked: 0,
+                epoch: 0,
+                wakers: Vec::new(),
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SafepointState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Wake every mutator waiting on a change to the state, including parked async yield points.
+    fn notify(&self, state: &mut SafepointState) {
+        self.changed.notify_all();
+
+        for waker in mem::take(&mut state.wakers) {
+            waker.wake();
+        }
+    }
+
+    /// Register the current thread as a mutator. A collection can not occur until the returned
+    /// mutator reaches a yield point or is dropped.
+    pub fn register(self: &Arc<Self>) -> Mutator {
+        self.lock().mutators += 1;
+
+        Mutator {
+            safepoint: self.clone(),
+        }
+    }
+
+    /// Request that all mutators stop at their next yield point so a collection can be performed.
+    pub fn request_stop(&self) {
+        self.stop_requested.store(true, Ordering::Release);
+    }
+
+    /// Check if a stop has been requested, but not yet completed.
+    #[inline(always)]
+    pub fn is_stop_requested(&self) -> bool {
+        self.stop_requested.load(Ordering::Acquire)
+    }
+
+    /// The number of stops which have been completed.
+    pub fn epoch(&self) -> u64 {
+        self.lock().epoch
+    }
+
+    /// The number of mutators currently registered.
+    pub fn mutators(&self) -> usize {
+        self.lock().mutators
+    }
+}
+
+impl Default for Safepoint {
+    fn default() -> Self {
+        Safepoint::new()
+    }
+}
+
+/// A registration of a thread with a [`Safepoint`]. Dropping the mutator removes it from the
+/// registry.
+pub struct Mutator {
+    safepoint: Arc<Safepoint>,
+}
+
+impl Mutator {
+    pub fn safepoint(&self) -> &Arc<Safepoint> {
+        &self.safepoint
+    }
+
+    /// Park this mutator if a stop has been requested. Once every mutator has parked, the last
+    /// thread to arrive runs `collect` before all mutators are resumed. Returns `true` if `collect`
+    /// was run by this thread.
+    ///
+    /// If `collect` panics, the other mutators are still resumed before the panic is propagated.
+    pub fn yield_point<F: FnOnce()>(&self, collect: F) -> bool {
+        let safepoint = &*self.safepoint;
+        if !safepoint.is_stop_requested() {
+            return false;
+        }
+
+        let mut state = safepoint.lock();
+        let epoch = state.epoch;
+        state.parked += 1;
+
+        let mut collect = Some(collect);
+        let mut result = Ok(false);
+
+        // The stop may have completed between checking the flag and acquiring the lock
+        while safepoint.is_stop_requested() && state.epoch == epoch {
+            if state.parked < state.mutators {
+                state = safepoint
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+                continue;
+            }
+
+            // Every mutator is parked so it is safe to collect. The lock is held for the duration
+            // of the collection so no new mutators can register in the meantime.
+            let collect = collect.take().unwrap();
+            result = panic::catch_unwind(AssertUnwindSafe(collect)).map(|_| true);
+
+            state.epoch = state.epoch.wrapping_add(1);
+            safepoint.stop_requested.store(false, Ordering::Release);
+            safepoint.notify(&mut state);
+        }
+
+        state.parked -= 1;
+        drop(state);
+
+        match result {
+            Ok(collected) => collected,
+            Err(err) => panic::resume_unwind(err),
+        }
+    }
+
+    /// An async counterpart to [`Mutator::yield_point`]. The returned future parks this mutator
+    /// when it is first polled if a stop has been requested, then resolves once the collection is
+    /// complete. If it is the last mutator to arrive, `collect` is run while it is being polled.
+    /// Resolves to `true` if `collect` was run by this future.
+    ///
+    /// Dropping the future before it resolves removes it from the set of parked mutators.
+    pub fn yield_point_async<F: FnOnce()>(&self, collect: F) -> YieldPoint<'_, F> {
+        YieldPoint {
+            mutator: self,
+            collect: Some(collect),
+            parked_epoch: None,
+        }
+    }
+
+    /// Run a function which may block for an extended period of time. Collections may occur while
+    /// inside the blocking region, so the heap must not be accessed by `f`.
+    pub fn blocking<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        struct LeaveGuard<'a>(&'a Safepoint);
+
+        impl Drop for LeaveGuard<'_> {
+            fn drop(&mut self) {
+                // Acquiring the lock waits for any ongoing collection to finish
+                self.0.lock().parked -= 1;
+            }
+        }
+
+        {
+            let mut state = self.safepoint.lock();
+            state.parked += 1;
+            self.safepoint.notify(&mut state);
+        }
+
+        let _guard = LeaveGuard(&self.safepoint);
+        f()
+    }
+}
+
+impl Drop for Mutator {
+    fn drop(&mut self) {
+        let mut state = self.safepoint.lock();
+        state.mutators -= 1;
+
+        // This may have been the last mutator holding up a collection
+        self.safepoint.notify(&mut state);
+    }
+}
+
+/// A future returned by [`Mutator::yield_point_async`].
+pub struct YieldPoint<'a, F> {
+    mutator: &'a Mutator,
+    collect: Option<F>,
+    /// The epoch at the time this future was parked, or `None` if it is not currently parked.
+    parked_epoch: Option<u64>,
+}
+
+// The collect function is never pinned
+impl<F> Unpin for YieldPoint<'_, F> {}
+
+impl<F: FnOnce()> Future for YieldPoint<'_, F> {
+    type Output = bool;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let safepoint = &*self.mutator.safepoint;
+        let mut state = safepoint.lock();
+
+        let epoch = match self.parked_epoch {
+            Some(epoch) => epoch,
+            None if !safepoint.is_stop_requested() => return Poll::Ready(false),
+            None => {
+                state.parked += 1;
+                self.parked_epoch = Some(state.epoch);
+                state.epoch
+            }
+        };
+
+        // Another mutator completed the stop while this one was parked
+        if !safepoint.is_stop_requested() || state.epoch != epoch {
+            state.parked -= 1;
+            self.parked_epoch = None;
+            return Poll::Ready(false);
+        }
+
+        if state.parked < state.mutators {
+            // Futures are usually polled repeatedly with the same waker, which only needs to be
+            // woken once
+            if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                state.wakers.push(cx.waker().clone());
+            }
+            return Poll::Pending;
+        }
+
+        // Every mutator is parked so it is safe to collect
+        let collect = self
+            .collect
+            .take()
+            .expect("YieldPoint polled after completion");
+        let result = panic::catch_unwind(AssertUnwindSafe(collect));
+
+        state.epoch = state.epoch.wrapping_add(1);
+        state.parked -= 1;
+        self.parked_epoch = None;
+        safepoint.stop_requested.store(false, Ordering::Release);
+        safepoint.notify(&mut state);
+        drop(state);
+
+        match result {
+            Ok(()) => Poll::Ready(true),
+            Err(err) => panic::resume_unwind(err),
+        }
+    }
+}
+
+impl<F> Drop for YieldPoint<'_, F> {
+    fn drop(&mut self) {
+        if self.parked_epoch.is_some() {
+            self.mutator.safepoint.lock().parked -= 1;
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Ephemerons are key/value pairs where the key is held weakly and the value is only kept alive for
+//! as long as the key is reachable by other means. Unlike a map with weak keys and strong values,
+//! a value referring back to its own key will not keep the entry alive. This makes them useful for
+//! attaching side metadata to objects without changing their lifetime.
+
+use crate::alloc::{Accessor, Alloc, IdentityHandle};
+use crate::trace::{Trace, Tracer, TracingAllocator};
+use crate::{Gc, GcWeak};
+use std::collections::HashMap;
+
+/// A map with weakly held keys where each entry is traced as an ephemeron.
+///
+/// Keys are compared by the identity of their handles (See [`IdentityHandle`]) so lookups are
+/// performed with the same `Gc` (or a copy of it) used to insert the entry. Once a key has been
+/// collected its entry can no longer be found, but it will continue to take up space until
+/// [`GcWeakMap::purge`] is called.
+pub struct GcWeakMap<K: ?Sized, V, H: Alloc<K>> {
+    entries: HashMap<GcWeak<K, H>, V>,
+}
+
+impl<K: ?Sized, V, H: Alloc<K>> GcWeakMap<K, V, H> {
+    /// The number of entries in this map, including those whose keys may have been collected.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&GcWeak<K, H>, &V)> {
+        self.entries.iter()
+    }
+}
+
+impl<K: ?Sized, V, H: Alloc<K>> GcWeakMap<K, V, H>
+where
+    <H as Alloc<K>>::RawHandle: Clone + IdentityHandle,
+{
+    pub fn new() -> Self {
+        GcWeakMap {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Remove any entries whose keys are known to have been collected.
+    pub fn purge<A: Accessor<K, H>>(&mut self, accessor: &A) {
+        self.entries
+            .retain(|key, _| accessor.upgrade(key).is_some());
+    }
+
+    /// Insert a new entry into the map. If an entry already exists for this key, the previous value
+    /// is returned.
+    pub fn insert(&mut self, key: &Gc<K, H>, value: V) -> Option<V> {
+        self.entries.insert(key.downgrade(), value)
+    }
+
+    pub fn get(&self, key: &Gc<K, H>) -> Option<&V> {
+        self.entries.get(&key.downgrade())
+    }
+
+    pub fn get_mut(&mut self, key: &Gc<K, H>) -> Option<&mut V> {
+        self.entries.get_mut(&key.downgrade())
+    }
+
+    pub fn contains_key(&self, key: &Gc<K, H>) -> bool {
+        self.entries.contains_key(&key.downgrade())
+    }
+
+    pub fn remove(&mut self, key: &Gc<K, H>) -> Option<V> {
+        self.entries.remove(&key.downgrade())
+    }
+}
+
+impl<K: ?Sized, V, H: Alloc<K>> Default for GcWeakMap<K, V, H>
+where
+    <H as Alloc<K>>::RawHandle: Clone + IdentityHandle,
+{
+    fn default() -> Self {
+        GcWeakMap::new()
+    }
+}
+
+impl<K, V, H> Trace<H> for GcWeakMap<K, V, H>
+where
+    K: ?Sized,
+    V: Trace<H>,
+    H: Alloc<K> + TracingAllocator,
+{
+    fn trace(&self, tracer: &mut H::Tracer<'_>) {
+        for (key, value) in &self.entries {
+            tracer.trace_ephemeron(key, value);
+        }
+    }
+}
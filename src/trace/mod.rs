@@ -1,10 +1,27 @@
-use crate::{Alloc, Gc};
+use crate::{Alloc, Gc, GcWeak};
 
+pub mod ephemeron;
 pub mod roots;
 mod trace_impls;
 
 pub trait TracingAllocator {
     type Tracer<'a>: 'a + Tracer<'a, Self>;
+
+    /// Called once an object allocated with [`AllocFlags::IMMORTAL`](crate::alloc::AllocFlags::IMMORTAL) has
+    /// been initialized. Immortal objects are never collected, so they may outlive every object
+    /// which refers to them. Tracing allocators must trace them as roots for the rest of the heap's
+    /// lifetime so the handles they hold are kept alive.
+    ///
+    /// This is called by [`Allocator`](crate::alloc::Allocator). Objects allocated with the flag
+    /// through [`Alloc`] directly must be registered by the caller. By default, this is a no-op for
+    /// allocators which already find immortal objects on their own.
+    #[inline(always)]
+    fn register_immortal<T>(&mut self, _object: &Gc<T, Self>)
+    where
+        Self: Alloc<T> + Sized,
+        T: ?Sized + Trace<Self>,
+    {
+    }
 }
 
 /// A simple and versatile Trace trait modeled after `std::hash::Hash`.
@@ -23,6 +40,19 @@ pub trait Trace<A: TracingAllocator> {
     }
 }
 
+/// A marker for types which never contain any GC handles, so tracing them would be a no-op. This is
+/// implemented for the types given an empty [`Trace`] implementation by this crate, except for raw
+/// pointers (Ex: `*const T` or `NonNull<T>`) since the data behind them may hold handles.
+///
+/// Allocators may record this when an object is allocated (See
+/// [`Allocator::alloc_leaf`](crate::alloc::Allocator::alloc_leaf)) so tracers can skip the object
+/// entirely and leaf objects can be grouped together.
+///
+/// # Safety
+/// The type must not contain any GC handles, either directly or through indirection. Otherwise, the
+/// objects they refer to may be collected while still reachable.
+pub unsafe trait NoTrace {}
+
 /// Not sure what I want this to be, but I thought I might as well leave it as a stub to make
 /// its usage more explicit.
 pub trait Tracer<'a, A: ?Sized>: Sized
@@ -32,4 +62,41 @@ where
     fn trace_obj<T: ?Sized + Trace<A>>(&mut self, obj: &Gc<T, A>)
     where
         A: Alloc<T>;
+
+    /// Called for weak edges in the object graph. Unlike [`Tracer::trace_obj`], this must not mark
+    /// the object or trace its contents.
+    ///
+    /// Garbage collectors which can already tell when a handle is stale (Ex: through a
+    /// [`crate::alloc::TaggedHandle`]) can leave this as a no-op. Otherwise, this may be used to
+    /// record weak handles so they can be cleared once marking has completed.
+    #[inline(always)]
+    fn trace_weak<T: ?Sized>(&mut self, _obj: &GcWeak<T, A>)
+    where
+        A: Alloc<T>,
+    {
+    }
+
+    /// Called for ephemeron entries (See [`ephemeron::GcWeakMap`]). The key is held weakly and the
+    /// value should only be traced once the key has been found to be reachable through some other
+    /// path. Since that may not be known yet, tracers are expected to defer entries with unmarked
+    /// keys until [`Tracer::process_ephemerons`] is called.
+    ///
+    /// The value reference is only guaranteed to remain valid until marking has completed.
+    ///
+    /// By default, ephemerons are treated conservatively by tracing the value right away. This
+    /// keeps everything reachable from the value alive for as long as the entry exists.
+    #[inline(always)]
+    fn trace_ephemeron<K: ?Sized, V: Trace<A>>(&mut self, key: &GcWeak<K, A>, value: &V)
+    where
+        A: Alloc<K>,
+    {
+        self.trace_weak(key);
+        value.trace(self);
+    }
+
+    /// Trace the values of any deferred ephemerons whose keys have since been marked. Since tracing
+    /// a value may mark the keys of other ephemerons, this must be repeated until no further
+    /// progress can be made. Garbage collectors should call this once all roots have been traced.
+    #[inline(always)]
+    fn process_ephemerons(&mut self) {}
 }
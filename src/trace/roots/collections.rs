@@ -45,13 +45,16 @@ where
 
 impl<'r, T, R, A, S> GcRootStorage<T, A> for StackRoots<'r, R, A, S>
 where
+    T: ?Sized,
     A: Alloc<T>,
     R: RootStorage<A> + GcRootStorage<T, A>,
     S: Array<Item = R::Index>,
 {
     #[inline(always)]
     fn add_root(&mut self, root: &Gc<T, A>) -> Self::Index {
-        self.root_source.add_root(root)
+        let index = self.root_source.add_root(root);
+        self.storage.push(index.clone());
+        index
     }
 }
 
@@ -100,7 +103,7 @@ impl<A: TracingAllocator, R> RootStorage<A> for UniformHandleRoots<A, R> {
 #[cfg(feature = "slab")]
 impl<T, A, R> GcRootStorage<T, A> for UniformHandleRoots<A, R>
 where
-    T: Trace<A>,
+    T: ?Sized + Trace<A>,
     A: Alloc<T, RawHandle = R> + TracingAllocator,
     R: Clone,
 {
@@ -9,12 +9,14 @@ pub use collections::*;
 /// A source for gc roots which can be iterated over. Root sources are assumed to be unordered and
 /// may contain duplicate values.
 pub trait RootStorage<A> {
-    type Index;
+    /// An index used to remove a root once it is no longer required. Indices must be cheap to clone
+    /// so wrappers such as [`StackRoots`] can keep track of the roots they add.
+    type Index: Clone;
 
     fn remove_root(&mut self, index: Self::Index) -> bool;
 }
 
-pub trait GcRootStorage<T, A: Alloc<T>>: RootStorage<A> {
+pub trait GcRootStorage<T: ?Sized, A: Alloc<T>>: RootStorage<A> {
     fn add_root(&mut self, root: &Gc<T, A>) -> Self::Index;
 }
 
@@ -44,6 +46,7 @@ impl<H, A, R: RootStorage<H>> RootStorage<H> for RootingAllocator<A, R> {
 
 impl<T, H, A, R> GcRootStorage<T, H> for RootingAllocator<A, R>
 where
+    T: ?Sized,
     H: Alloc<T>,
     R: RootStorage<H> + GcRootStorage<T, H>,
 {
@@ -82,12 +85,12 @@ impl<A: TracingAllocator, R> UntypedTracable<A, R> {
     pub fn from_handle<T>(handle: Gc<T, A>) -> Self
     where
         A: Alloc<T, RawHandle = R>,
-        T: Trace<A>,
+        T: ?Sized + Trace<A>,
     {
         fn trace_fn<K, B>(ptr: <B as Alloc<K>>::RawHandle, tracer: &mut B::Tracer<'_>)
         where
             B: TracingAllocator + Alloc<K>,
-            K: Trace<B>,
+            K: ?Sized + Trace<B>,
         {
             let handle: Gc<K, B> = unsafe { Gc::from_raw(ptr) };
             handle.trace(tracer);
@@ -98,4 +101,9 @@ impl<A: TracingAllocator, R> UntypedTracable<A, R> {
             trace: trace_fn::<T, A>,
         }
     }
+
+    /// The raw handle of the rooted object.
+    pub fn raw_handle(&self) -> &R {
+        &self.raw_handle
+    }
 }
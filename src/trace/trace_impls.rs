@@ -1,6 +1,6 @@
 use crate::alloc::Alloc;
-use crate::trace::{Trace, Tracer, TracingAllocator};
-use crate::Gc;
+use crate::trace::{NoTrace, Trace, Tracer, TracingAllocator};
+use crate::{Gc, GcWeak};
 use std::marker::PhantomData;
 use std::mem::ManuallyDrop;
 use std::num::*;
@@ -10,13 +10,21 @@ use std::sync::atomic::*;
 use std::sync::Arc;
 
 /// This implementation simply switches the underying method from the tracer to consume the item.
-impl<T: Trace<A>, A: Alloc<T> + TracingAllocator> Trace<A> for Gc<T, A> {
+impl<T: ?Sized + Trace<A>, A: Alloc<T> + TracingAllocator> Trace<A> for Gc<T, A> {
     #[inline(always)]
     fn trace(&self, tracer: &mut A::Tracer<'_>) {
         tracer.trace_obj(self)
     }
 }
 
+/// Weak handles are handed to the tracer so they can be recorded without keeping the object alive.
+impl<T: ?Sized, A: Alloc<T> + TracingAllocator> Trace<A> for GcWeak<T, A> {
+    #[inline(always)]
+    fn trace(&self, tracer: &mut A::Tracer<'_>) {
+        tracer.trace_weak(self)
+    }
+}
+
 macro_rules! impl_trace_nop {
         ($($(#[$($macros:tt)+])* $name:ty)+) => {
             $(
@@ -25,6 +33,9 @@ macro_rules! impl_trace_nop {
                     #[inline(always)]
                     fn trace(&self, _: &mut A::Tracer<'_>) {}
                 }
+
+                $(#[$($macros)+])*
+                unsafe impl NoTrace for $name {}
             )+
         };
     }
@@ -64,6 +75,8 @@ impl<A: TracingAllocator, P: ?Sized> Trace<A> for PhantomData<P> {
     fn trace(&self, _: &mut A::Tracer<'_>) {}
 }
 
+unsafe impl<P: ?Sized + NoTrace> NoTrace for PhantomData<P> {}
+
 impl<A: TracingAllocator, P: ?Sized> Trace<A> for *const P {
     #[inline(always)]
     fn trace(&self, _: &mut A::Tracer<'_>) {}
@@ -99,6 +112,11 @@ macro_rules! impl_trace_tuple {
                     $($name.trace(tracer);)+
                 }
             }
+
+            unsafe impl<$($name: NoTrace),+> NoTrace for ($($name,)+)
+                where last_type!($($name,)+): ?Sized,
+            {
+            }
         };
     }
 
@@ -141,6 +159,8 @@ impl<A: TracingAllocator, T: Trace<A>> Trace<A> for [T] {
     }
 }
 
+unsafe impl<T: NoTrace> NoTrace for [T] {}
+
 impl<A: TracingAllocator, T: Trace<A>, const N: usize> Trace<A> for [T; N] {
     #[inline]
     fn trace(&self, tracer: &mut A::Tracer<'_>) {
@@ -148,6 +168,8 @@ impl<A: TracingAllocator, T: Trace<A>, const N: usize> Trace<A> for [T; N] {
     }
 }
 
+unsafe impl<T: NoTrace, const N: usize> NoTrace for [T; N] {}
+
 impl<A: TracingAllocator, T: ?Sized + ToOwned + Trace<A>> Trace<A> for std::borrow::Cow<'_, T> {
     #[inline]
     fn trace(&self, tracer: &mut A::Tracer<'_>) {
@@ -165,12 +187,15 @@ impl<A: TracingAllocator, T: Trace<A>> Trace<A> for Option<T> {
     }
 }
 
+unsafe impl<T: NoTrace> NoTrace for Option<T> {}
+
 impl<A: TracingAllocator, T: Trace<A>> Trace<A> for Box<T> {
     #[inline]
     fn trace(&self, tracer: &mut A::Tracer<'_>) {
         Trace::trace(&**self, tracer)
     }
 }
+unsafe impl<T: NoTrace> NoTrace for Box<T> {}
 
 impl<A: TracingAllocator, T: Trace<A>> Trace<A> for Rc<T> {
     #[inline]
@@ -178,6 +203,7 @@ impl<A: TracingAllocator, T: Trace<A>> Trace<A> for Rc<T> {
         Trace::trace(&**self, tracer)
     }
 }
+unsafe impl<T: NoTrace> NoTrace for Rc<T> {}
 
 impl<A: TracingAllocator, T: Trace<A>> Trace<A> for Arc<T> {
     #[inline]
@@ -185,6 +211,7 @@ impl<A: TracingAllocator, T: Trace<A>> Trace<A> for Arc<T> {
         Trace::trace(&**self, tracer)
     }
 }
+unsafe impl<T: NoTrace> NoTrace for Arc<T> {}
 
 impl<A: TracingAllocator, T: Trace<A>> Trace<A> for Vec<T> {
     #[inline]
@@ -192,6 +219,7 @@ impl<A: TracingAllocator, T: Trace<A>> Trace<A> for Vec<T> {
         Trace::trace_slice(&self[..], tracer)
     }
 }
+unsafe impl<T: NoTrace> NoTrace for Vec<T> {}
 
 #[cfg(feature = "slab")]
 impl<A: TracingAllocator, T: Trace<A>> Trace<A> for slab::Slab<T> {